@@ -0,0 +1,288 @@
+// ============== Audio Endpoints ==============
+//
+// Output devices are listed and switched for the output menu, and changes are
+// reported for the menu, the output icon and the unplug pause. The volume
+// mixer and the sleep timer's fade work on the audio sessions of the default
+// output.
+
+use windows::core::{implement, Interface, BSTR, HSTRING, PCWSTR, PWSTR};
+use windows::Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName;
+use windows::Win32::Foundation::{CloseHandle, BOOL, S_OK};
+use windows::Win32::Media::Audio::{
+    eConsole, eMultimedia, eRender, AudioSessionStateExpired, DigitalAudioDisplayDevice, EDataFlow, ERole, Handset,
+    Headphones, Headset, IAudioSessionControl2, IAudioSessionManager2, IMMDevice, IMMDeviceEnumerator,
+    IMMNotificationClient, IMMNotificationClient_Impl, ISimpleAudioVolume, MMDeviceEnumerator, Speakers,
+    DEVICE_STATE, DEVICE_STATE_ACTIVE, PKEY_AudioEndpoint_FormFactor,
+};
+use windows::Win32::System::Com::{CoCreateInstance, CoTaskMemFree, CLSCTX_ALL, STGM_READ};
+use windows::Win32::System::Threading::{
+    OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use windows::Win32::UI::Shell::PropertiesSystem::PROPERTYKEY;
+
+use crate::mixer::{AudioSession, MixerBackend};
+use crate::output::{DeviceClass, OutputBackend, OutputDevice};
+
+unsafe fn enumerator() -> Option<IMMDeviceEnumerator> {
+    CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL).ok()
+}
+
+unsafe fn default_device() -> Option<IMMDevice> {
+    enumerator()?.GetDefaultAudioEndpoint(eRender, eMultimedia).ok()
+}
+
+unsafe fn device_class(device: &IMMDevice) -> DeviceClass {
+    let form_factor = device
+        .OpenPropertyStore(STGM_READ)
+        .and_then(|store| store.GetValue(&PKEY_AudioEndpoint_FormFactor))
+        .ok()
+        .and_then(|value| u32::try_from(&value).ok());
+    match form_factor.map(|f| f as i32) {
+        Some(f) if f == Headphones.0 || f == Headset.0 || f == Handset.0 => DeviceClass::Headphones,
+        Some(f) if f == Speakers.0 => DeviceClass::Speakers,
+        Some(f) if f == DigitalAudioDisplayDevice.0 => DeviceClass::Display,
+        _ => DeviceClass::Other,
+    }
+}
+
+/// Reads a string the callee allocated, and frees it.
+unsafe fn take_string(text: PWSTR) -> Option<String> {
+    let string = text.to_string().ok();
+    CoTaskMemFree(Some(text.0 as *const _));
+    string
+}
+
+unsafe fn device_id(device: &IMMDevice) -> Option<String> {
+    take_string(device.GetId().ok()?)
+}
+
+unsafe fn device_name(device: &IMMDevice) -> Option<String> {
+    let value = device
+        .OpenPropertyStore(STGM_READ)
+        .and_then(|store| store.GetValue(&PKEY_Device_FriendlyName))
+        .ok()?;
+    Some(BSTR::try_from(&value).ok()?.to_string()).filter(|name| !name.is_empty())
+}
+
+// Windows has no public API for changing the default device. The Sound
+// settings use this undocumented interface, which has kept its layout since
+// Windows 7. Only `SetDefaultEndpoint` is called; the other methods are
+// declared to keep the vtable layout.
+mod policy {
+    #![allow(dead_code, non_snake_case)]
+
+    use std::ffi::c_void;
+
+    use windows::core::{interface, IUnknown, IUnknown_Vtbl, GUID, HRESULT, PCWSTR};
+    use windows::Win32::Media::Audio::ERole;
+
+    pub const POLICY_CONFIG_CLIENT: GUID = GUID::from_u128(0x870af99c_171d_4f9e_af0d_e63df40c2bc9);
+
+    #[interface("f8679f50-850a-41cf-9c72-430f290290c8")]
+    pub unsafe trait IPolicyConfig: IUnknown {
+        fn GetMixFormat(&self, id: PCWSTR, format: *mut *mut c_void) -> HRESULT;
+        fn GetDeviceFormat(&self, id: PCWSTR, default: i32, format: *mut *mut c_void) -> HRESULT;
+        fn ResetDeviceFormat(&self, id: PCWSTR) -> HRESULT;
+        fn SetDeviceFormat(&self, id: PCWSTR, endpoint: *mut c_void, mix: *mut c_void) -> HRESULT;
+        fn GetProcessingPeriod(&self, id: PCWSTR, default: i32, period: *mut i64, minimum: *mut i64) -> HRESULT;
+        fn SetProcessingPeriod(&self, id: PCWSTR, period: *mut i64) -> HRESULT;
+        fn GetShareMode(&self, id: PCWSTR, mode: *mut c_void) -> HRESULT;
+        fn SetShareMode(&self, id: PCWSTR, mode: *mut c_void) -> HRESULT;
+        fn GetPropertyValue(&self, id: PCWSTR, key: *const c_void, value: *mut c_void) -> HRESULT;
+        fn SetPropertyValue(&self, id: PCWSTR, key: *const c_void, value: *mut c_void) -> HRESULT;
+        pub fn SetDefaultEndpoint(&self, id: PCWSTR, role: ERole) -> HRESULT;
+        fn SetEndpointVisibility(&self, id: PCWSTR, visible: i32) -> HRESULT;
+    }
+}
+
+/// Output devices from the active render endpoints.
+pub struct SystemOutputs;
+
+impl OutputBackend for SystemOutputs {
+    fn outputs(&self) -> Vec<OutputDevice> {
+        unsafe {
+            let Some(enumerator) = enumerator() else {
+                return Vec::new();
+            };
+            let Ok(collection) = enumerator.EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE) else {
+                return Vec::new();
+            };
+            let default = default_device().and_then(|device| device_id(&device));
+            (0..collection.GetCount().unwrap_or(0))
+                .filter_map(|i| collection.Item(i).ok())
+                .filter_map(|device| {
+                    let id = device_id(&device)?;
+                    Some(OutputDevice {
+                        name: device_name(&device).unwrap_or_else(|| id.clone()),
+                        class: device_class(&device),
+                        default: default.as_ref() == Some(&id),
+                        id,
+                    })
+                })
+                .collect()
+        }
+    }
+
+    /// Switches the console and multimedia roles, like "Set as Default
+    /// Device" does; the communications device stays where it is.
+    fn set_default(&self, id: &str) {
+        unsafe {
+            let policy: windows::core::Result<policy::IPolicyConfig> =
+                CoCreateInstance(&policy::POLICY_CONFIG_CLIENT, None, CLSCTX_ALL);
+            let Ok(policy) = policy else {
+                return;
+            };
+            let id = HSTRING::from(id);
+            for role in [eConsole, eMultimedia] {
+                let _ = policy.SetDefaultEndpoint(PCWSTR(id.as_ptr()), role);
+            }
+        }
+    }
+}
+
+/// Audio sessions on the default output, as the Windows volume mixer lists
+/// them.
+pub struct SystemMixer;
+
+impl SystemMixer {
+    /// Live app sessions with their executable names; system sounds are left
+    /// out.
+    unsafe fn controls() -> Vec<(String, IAudioSessionControl2)> {
+        let Some(device) = default_device() else {
+            return Vec::new();
+        };
+        let manager = device.Activate::<IAudioSessionManager2>(CLSCTX_ALL, None);
+        let Ok(sessions) = manager.and_then(|manager| manager.GetSessionEnumerator()) else {
+            return Vec::new();
+        };
+        (0..sessions.GetCount().unwrap_or(0))
+            .filter_map(|i| sessions.GetSession(i).ok()?.cast::<IAudioSessionControl2>().ok())
+            .filter(|control| control.IsSystemSoundsSession() != S_OK)
+            .filter(|control| control.GetState().is_ok_and(|state| state != AudioSessionStateExpired))
+            .filter_map(|control| Some((process_name(control.GetProcessId().ok()?)?, control)))
+            .collect()
+    }
+
+    unsafe fn volumes(process: &str) -> Vec<ISimpleAudioVolume> {
+        Self::controls()
+            .into_iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(process))
+            .filter_map(|(_, control)| control.cast::<ISimpleAudioVolume>().ok())
+            .collect()
+    }
+}
+
+impl MixerBackend for SystemMixer {
+    fn sessions(&self) -> Vec<AudioSession> {
+        unsafe {
+            Self::controls()
+                .into_iter()
+                .filter_map(|(process, control)| {
+                    let volume = control.cast::<ISimpleAudioVolume>().ok()?;
+                    // Names like `@%SystemRoot%\...` point into resources; the executable reads better
+                    let name = control.GetDisplayName().ok().and_then(|name| take_string(name));
+                    Some(AudioSession {
+                        id: take_string(control.GetSessionInstanceIdentifier().ok()?)?,
+                        name: name.filter(|name| !name.starts_with('@')).unwrap_or_default(),
+                        volume: volume.GetMasterVolume().ok()?,
+                        muted: volume.GetMute().ok()?.as_bool(),
+                        process,
+                    })
+                })
+                .collect()
+        }
+    }
+
+    fn set_volume(&self, process: &str, volume: f32) {
+        unsafe {
+            for session in Self::volumes(process) {
+                let _ = session.SetMasterVolume(volume.clamp(0.0, 1.0), std::ptr::null());
+            }
+        }
+    }
+
+    fn set_muted(&self, process: &str, muted: bool) {
+        unsafe {
+            for session in Self::volumes(process) {
+                let _ = session.SetMute(BOOL::from(muted), std::ptr::null());
+            }
+        }
+    }
+
+    fn set_session_volume(&self, id: &str, volume: f32) {
+        unsafe {
+            let session = Self::controls().into_iter().map(|(_, control)| control).find(|control| {
+                control.GetSessionInstanceIdentifier().ok().and_then(|text| take_string(text)).as_deref() == Some(id)
+            });
+            if let Some(session) = session.and_then(|control| control.cast::<ISimpleAudioVolume>().ok()) {
+                let _ = session.SetMasterVolume(volume.clamp(0.0, 1.0), std::ptr::null());
+            }
+        }
+    }
+}
+
+/// File name of a process's executable.
+unsafe fn process_name(pid: u32) -> Option<String> {
+    let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+    let mut buffer = [0u16; 1024];
+    let mut size = buffer.len() as u32;
+    let path = QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(buffer.as_mut_ptr()), &mut size)
+        .ok()
+        .map(|_| String::from_utf16_lossy(&buffer[..size as usize]));
+    let _ = CloseHandle(process);
+    path?.rsplit('\\').next().map(str::to_string)
+}
+
+/// Registration for output device changes; `stop` ends it.
+pub struct OutputWatcher {
+    enumerator: IMMDeviceEnumerator,
+    client: IMMNotificationClient,
+}
+
+impl OutputWatcher {
+    /// Calls `notify` whenever the default output device changes or a device
+    /// comes or goes.
+    pub unsafe fn start(notify: impl Fn() + Send + Sync + 'static) -> Option<OutputWatcher> {
+        let enumerator = enumerator()?;
+        let client: IMMNotificationClient = OutputNotifier { notify: Box::new(notify) }.into();
+        enumerator.RegisterEndpointNotificationCallback(&client).ok()?;
+        Some(OutputWatcher { enumerator, client })
+    }
+
+    pub unsafe fn stop(self) {
+        let _ = self.enumerator.UnregisterEndpointNotificationCallback(&self.client);
+    }
+}
+
+/// Runs on a system thread, so it only pokes the UI thread.
+#[implement(IMMNotificationClient)]
+struct OutputNotifier {
+    notify: Box<dyn Fn() + Send + Sync>,
+}
+
+impl IMMNotificationClient_Impl for OutputNotifier_Impl {
+    // Added and removed only report installs; plugging in and out is a state change
+    fn OnDeviceStateChanged(&self, _: &PCWSTR, _: DEVICE_STATE) -> windows::core::Result<()> {
+        (self.notify)();
+        Ok(())
+    }
+
+    fn OnDeviceAdded(&self, _: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnDeviceRemoved(&self, _: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnDefaultDeviceChanged(&self, flow: EDataFlow, role: ERole, _: &PCWSTR) -> windows::core::Result<()> {
+        if flow == eRender && role == eMultimedia {
+            (self.notify)();
+        }
+        Ok(())
+    }
+
+    fn OnPropertyValueChanged(&self, _: &PCWSTR, _: &PROPERTYKEY) -> windows::core::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+// ============== Automatic Pause ==============
+//
+// Pauses playback while the user is away or in a call and picks it up again
+// afterwards, but only if ClickPlay did the pausing. The engine only sees
+// neutral begin/end signals; the Windows shell derives them from session,
+// display and power notifications, input idle time and microphone/camera use.
+
+use std::time::Duration;
+
+use crate::sessions::PausedSession;
+
+/// Why playback is held. Reasons may overlap, e.g. the display turning off
+/// while locked; playback resumes once the last one has ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Away {
+    Locked,
+    DisplayOff,
+    Suspended,
+    /// No input for the configured time
+    Idle,
+    /// An app is using the microphone or camera
+    Call,
+    /// The default output left the watched kind of device
+    Unplugged,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AutoStep {
+    /// Pause the current session
+    Pause,
+    /// Resume this session
+    Resume(PausedSession),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AutoPause {
+    reasons: Vec<Away>,
+    /// Session paused by us
+    paused: Option<PausedSession>,
+    /// Reason that paused it
+    cause: Option<Away>,
+    /// Set once that session was seen paused, so playing again means the
+    /// user resumed it and it's no longer ours to resume
+    settled: bool,
+}
+
+impl AutoPause {
+    /// Starts a reason. The first one pauses `playing`, the current session
+    /// if it is playing.
+    pub fn begin(&mut self, reason: Away, playing: Option<&PausedSession>) -> Option<AutoStep> {
+        if self.reasons.contains(&reason) {
+            return None;
+        }
+        self.reasons.push(reason);
+        if self.paused.is_some() {
+            return None;
+        }
+        self.paused = Some(playing?.clone());
+        self.cause = Some(reason);
+        self.settled = false;
+        Some(AutoStep::Pause)
+    }
+
+    /// Ends a reason. Once none are left, resumes what `begin` paused.
+    pub fn end(&mut self, reason: Away) -> Option<AutoStep> {
+        let held = !self.reasons.is_empty();
+        self.reasons.retain(|&r| r != reason);
+        if !held || !self.reasons.is_empty() {
+            return None;
+        }
+        self.paused.take().map(AutoStep::Resume)
+    }
+
+    /// Ends a reason without ever resuming what it paused.
+    pub fn abandon(&mut self, reason: Away) {
+        if self.cause == Some(reason) {
+            self.paused = None;
+        }
+        self.reasons.retain(|&r| r != reason);
+    }
+
+    /// Follows the session's play state to notice the user taking over.
+    pub fn observe(&mut self, playing: bool) {
+        if self.paused.is_none() {
+            return;
+        }
+        if !playing {
+            self.settled = true;
+        } else if self.settled {
+            self.paused = None;
+        }
+    }
+
+    pub fn held(&self) -> bool {
+        !self.reasons.is_empty()
+    }
+}
+
+// ============== Idle Detection ==============
+
+/// One reading of an idle source.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IdleSample {
+    /// Time since the last keyboard or mouse input
+    pub idle: Duration,
+    /// A full-screen app is in front, e.g. a movie that needs no input
+    pub fullscreen: bool,
+}
+
+/// Where idle readings come from; the shell implements it per platform.
+pub trait IdleSource {
+    fn sample(&self) -> Option<IdleSample>;
+}
+
+/// Turns idle readings into `Away::Idle` begin/end. Going idle needs
+/// `limit` without input and no full-screen app; only input ends it.
+#[derive(Clone, Debug, Default)]
+pub struct IdleWatch {
+    idle: bool,
+}
+
+impl IdleWatch {
+    /// Returns the new state when it flips.
+    pub fn observe(&mut self, sample: IdleSample, limit: Duration) -> Option<bool> {
+        let idle = if self.idle {
+            sample.idle >= limit
+        } else {
+            sample.idle >= limit && !sample.fullscreen
+        };
+        if idle == self.idle {
+            return None;
+        }
+        self.idle = idle;
+        Some(idle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: Duration = Duration::from_secs(600);
+
+    /// Feeds `(idle seconds, fullscreen)` readings, returning the flips.
+    fn run(watch: &mut IdleWatch, timeline: &[(u64, bool)]) -> Vec<bool> {
+        timeline
+            .iter()
+            .filter_map(|&(secs, fullscreen)| {
+                watch.observe(IdleSample { idle: Duration::from_secs(secs), fullscreen }, LIMIT)
+            })
+            .collect()
+    }
+
+    fn session(app_id: &str) -> PausedSession {
+        PausedSession { app_id: app_id.to_string(), title: "Song".to_string() }
+    }
+
+    fn resume(app_id: &str) -> Option<AutoStep> {
+        Some(AutoStep::Resume(session(app_id)))
+    }
+
+    #[test]
+    fn lock_pauses_and_unlock_resumes() {
+        let mut auto = AutoPause::default();
+        assert_eq!(auto.begin(Away::Locked, Some(&session("Spotify.exe"))), Some(AutoStep::Pause));
+        auto.observe(false);
+        assert!(auto.held());
+        assert_eq!(auto.end(Away::Locked), resume("Spotify.exe"));
+        assert!(!auto.held());
+        assert_eq!(auto.end(Away::Locked), None);
+    }
+
+    #[test]
+    fn nothing_is_resumed_that_was_not_playing() {
+        let mut auto = AutoPause::default();
+        assert_eq!(auto.begin(Away::Locked, None), None);
+        assert_eq!(auto.end(Away::Locked), None);
+    }
+
+    #[test]
+    fn overlapping_reasons_resume_after_the_last() {
+        let mut auto = AutoPause::default();
+        assert_eq!(auto.begin(Away::Locked, Some(&session("Spotify.exe"))), Some(AutoStep::Pause));
+        auto.observe(false);
+        assert_eq!(auto.begin(Away::DisplayOff, None), None);
+        assert_eq!(auto.begin(Away::Suspended, None), None);
+        assert_eq!(auto.end(Away::Suspended), None);
+        assert_eq!(auto.end(Away::DisplayOff), None);
+        assert_eq!(auto.end(Away::Locked), resume("Spotify.exe"));
+    }
+
+    #[test]
+    fn repeated_signals_count_once() {
+        let mut auto = AutoPause::default();
+        auto.begin(Away::Locked, Some(&session("Spotify.exe")));
+        assert_eq!(auto.begin(Away::Locked, Some(&session("Spotify.exe"))), None);
+        assert_eq!(auto.end(Away::Locked), resume("Spotify.exe"));
+    }
+
+    #[test]
+    fn playback_the_user_started_is_left_alone() {
+        let mut auto = AutoPause::default();
+        auto.begin(Away::Locked, Some(&session("Spotify.exe")));
+        // The status from before the pause doesn't count
+        auto.observe(true);
+        auto.observe(false);
+        auto.observe(true);
+        assert_eq!(auto.end(Away::Locked), None);
+    }
+
+    #[test]
+    fn a_later_reason_pauses_what_plays_then() {
+        let mut auto = AutoPause::default();
+        auto.begin(Away::DisplayOff, None);
+        assert_eq!(auto.begin(Away::Locked, Some(&session("Chrome"))), Some(AutoStep::Pause));
+        auto.end(Away::DisplayOff);
+        assert_eq!(auto.end(Away::Locked), resume("Chrome"));
+    }
+
+    #[test]
+    fn idle_begins_at_the_limit_and_ends_with_input() {
+        let mut watch = IdleWatch::default();
+        assert_eq!(run(&mut watch, &[(0, false), (300, false), (599, false)]), vec![]);
+        assert_eq!(run(&mut watch, &[(600, false), (900, false)]), vec![true]);
+        assert_eq!(run(&mut watch, &[(0, false), (1, false)]), vec![false]);
+    }
+
+    #[test]
+    fn full_screen_apps_are_never_idle() {
+        let mut watch = IdleWatch::default();
+        assert_eq!(run(&mut watch, &[(600, true), (3600, true), (0, true)]), vec![]);
+        // Leaving full screen after a long stretch without input counts
+        assert_eq!(run(&mut watch, &[(3700, false)]), vec![true]);
+    }
+
+    #[test]
+    fn going_full_screen_while_idle_takes_input() {
+        let mut watch = IdleWatch::default();
+        assert_eq!(run(&mut watch, &[(600, false), (700, true)]), vec![true]);
+        assert_eq!(run(&mut watch, &[(0, true)]), vec![false]);
+    }
+
+    #[test]
+    fn abandoning_keeps_other_reasons_pauses() {
+        let mut auto = AutoPause::default();
+        auto.begin(Away::Unplugged, Some(&session("Spotify.exe")));
+        auto.abandon(Away::Unplugged);
+        assert!(!auto.held());
+        assert_eq!(auto.end(Away::Unplugged), None);
+
+        auto.begin(Away::Locked, Some(&session("Spotify.exe")));
+        auto.begin(Away::Unplugged, None);
+        auto.abandon(Away::Unplugged);
+        assert_eq!(auto.end(Away::Locked), resume("Spotify.exe"));
+    }
+}
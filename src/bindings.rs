@@ -0,0 +1,355 @@
+// ============== Click Bindings ==============
+//
+// Maps a mouse gesture on a tray icon to an action. User bindings come from
+// `bind.<icon>.<gesture>=<action>` lines in the config; anything not bound
+// there falls back to the built-in table, which matches the classic behavior.
+
+use crate::core::TrayIcon;
+
+// Seek step for the default wheel bindings on prev/next
+const WHEEL_SEEK_SECONDS: i32 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gesture {
+    Left,
+    Right,
+    Middle,
+    Double,
+    ShiftLeft,
+    CtrlLeft,
+    WheelUp,
+    WheelDown,
+}
+
+impl Gesture {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Gesture::Left => "left",
+            Gesture::Right => "right",
+            Gesture::Middle => "middle",
+            Gesture::Double => "double",
+            Gesture::ShiftLeft => "shift_left",
+            Gesture::CtrlLeft => "ctrl_left",
+            Gesture::WheelUp => "wheel_up",
+            Gesture::WheelDown => "wheel_down",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Gesture> {
+        match value {
+            "left" => Some(Gesture::Left),
+            "right" => Some(Gesture::Right),
+            "middle" => Some(Gesture::Middle),
+            "double" => Some(Gesture::Double),
+            "shift_left" => Some(Gesture::ShiftLeft),
+            "ctrl_left" => Some(Gesture::CtrlLeft),
+            "wheel_up" => Some(Gesture::WheelUp),
+            "wheel_down" => Some(Gesture::WheelDown),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    None,
+    PlayPause,
+    Next,
+    Prev,
+    Stop,
+    /// Relative seek in seconds
+    Seek(i32),
+    OpenPlayer,
+    ShowMenu,
+    ShowFlyout,
+    Mute,
+    ToggleShuffle,
+    CycleRepeat,
+    /// Pause every playing session
+    PauseAll,
+    /// Resume the sessions `PauseAll` paused
+    ResumePaused,
+    /// Make the next output device the default
+    NextOutput,
+    /// Step the playback speed through the speed presets
+    SpeedUp,
+    SpeedDown,
+}
+
+impl Action {
+    pub fn to_config(self) -> String {
+        match self {
+            Action::None => "none".to_string(),
+            Action::PlayPause => "play_pause".to_string(),
+            Action::Next => "next".to_string(),
+            Action::Prev => "prev".to_string(),
+            Action::Stop => "stop".to_string(),
+            Action::Seek(seconds) => format!("seek{:+}", seconds),
+            Action::OpenPlayer => "open_player".to_string(),
+            Action::ShowMenu => "menu".to_string(),
+            Action::ShowFlyout => "flyout".to_string(),
+            Action::Mute => "mute".to_string(),
+            Action::ToggleShuffle => "shuffle".to_string(),
+            Action::CycleRepeat => "repeat".to_string(),
+            Action::PauseAll => "pause_all".to_string(),
+            Action::ResumePaused => "resume_paused".to_string(),
+            Action::NextOutput => "next_output".to_string(),
+            Action::SpeedUp => "speed_up".to_string(),
+            Action::SpeedDown => "speed_down".to_string(),
+        }
+    }
+
+    /// Parses an action name. Seeks are written `seek+10` or `seek-10`.
+    pub fn parse(value: &str) -> Option<Action> {
+        match value {
+            "none" => Some(Action::None),
+            "play_pause" => Some(Action::PlayPause),
+            "next" => Some(Action::Next),
+            "prev" => Some(Action::Prev),
+            "stop" => Some(Action::Stop),
+            "open_player" => Some(Action::OpenPlayer),
+            "menu" => Some(Action::ShowMenu),
+            "flyout" => Some(Action::ShowFlyout),
+            "mute" => Some(Action::Mute),
+            "shuffle" => Some(Action::ToggleShuffle),
+            "repeat" => Some(Action::CycleRepeat),
+            "pause_all" => Some(Action::PauseAll),
+            "resume_paused" => Some(Action::ResumePaused),
+            "next_output" => Some(Action::NextOutput),
+            "speed_up" => Some(Action::SpeedUp),
+            "speed_down" => Some(Action::SpeedDown),
+            _ => {
+                let seconds = value.strip_prefix("seek")?;
+                if !seconds.starts_with(['+', '-']) {
+                    return None;
+                }
+                seconds.parse().ok().filter(|&s| s != 0).map(Action::Seek)
+            }
+        }
+    }
+}
+
+/// One `bind.` line. `icon: None` is written as `any` and applies to every icon.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Binding {
+    pub icon: Option<TrayIcon>,
+    pub gesture: Gesture,
+    pub action: Action,
+}
+
+impl Binding {
+    /// Parses `bind.<icon>.<gesture>` and its value.
+    pub fn parse(key: &str, value: &str) -> Option<Binding> {
+        let mut parts = key.strip_prefix("bind.")?.split('.');
+        let icon = match parts.next()? {
+            "any" => None,
+            name => Some(icon_from_name(name)?),
+        };
+        let gesture = Gesture::parse(parts.next()?)?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Binding { icon, gesture, action: Action::parse(value)? })
+    }
+
+    pub fn to_config(self) -> String {
+        let icon = self.icon.map(icon_name).unwrap_or("any");
+        format!("bind.{}.{}={}", icon, self.gesture.as_str(), self.action.to_config())
+    }
+}
+
+fn icon_name(icon: TrayIcon) -> &'static str {
+    match icon {
+        TrayIcon::Default => "default",
+        TrayIcon::Prev => "prev",
+        TrayIcon::Play => "play",
+        TrayIcon::Next => "next",
+        TrayIcon::Shuffle => "shuffle",
+        TrayIcon::Repeat => "repeat",
+        TrayIcon::Speed => "speed",
+        TrayIcon::Output => "output",
+    }
+}
+
+fn icon_from_name(name: &str) -> Option<TrayIcon> {
+    match name {
+        "default" => Some(TrayIcon::Default),
+        "prev" => Some(TrayIcon::Prev),
+        "play" => Some(TrayIcon::Play),
+        "next" => Some(TrayIcon::Next),
+        "shuffle" => Some(TrayIcon::Shuffle),
+        "repeat" => Some(TrayIcon::Repeat),
+        "speed" => Some(TrayIcon::Speed),
+        "output" => Some(TrayIcon::Output),
+        _ => None,
+    }
+}
+
+/// Action for a gesture on an icon. Later bindings override earlier ones, and
+/// a binding for the specific icon beats an `any` binding.
+pub fn resolve(bindings: &[Binding], icon: TrayIcon, gesture: Gesture) -> Action {
+    let find = |target: Option<TrayIcon>| {
+        bindings
+            .iter()
+            .rev()
+            .find(|b| b.icon == target && b.gesture == gesture)
+            .map(|b| b.action)
+    };
+    find(Some(icon))
+        .or_else(|| find(None))
+        .unwrap_or_else(|| default_action(icon, gesture))
+}
+
+fn default_action(icon: TrayIcon, gesture: Gesture) -> Action {
+    match (icon, gesture) {
+        (_, Gesture::Right) => Action::ShowMenu,
+        (TrayIcon::Default, Gesture::Left) => Action::ShowMenu,
+        (TrayIcon::Prev, Gesture::Left) => Action::Prev,
+        (TrayIcon::Play, Gesture::Left) => Action::PlayPause,
+        (TrayIcon::Next, Gesture::Left) => Action::Next,
+        (TrayIcon::Shuffle, Gesture::Left) => Action::ToggleShuffle,
+        (TrayIcon::Repeat, Gesture::Left) => Action::CycleRepeat,
+        (TrayIcon::Speed, Gesture::Left) => Action::SpeedUp,
+        (TrayIcon::Speed, Gesture::ShiftLeft) => Action::SpeedDown,
+        (TrayIcon::Output, Gesture::Left) => Action::NextOutput,
+        (TrayIcon::Play, Gesture::WheelUp) => Action::Next,
+        (TrayIcon::Play, Gesture::WheelDown) => Action::Prev,
+        (TrayIcon::Prev | TrayIcon::Next, Gesture::WheelUp) => Action::Seek(WHEEL_SEEK_SECONDS),
+        (TrayIcon::Prev | TrayIcon::Next, Gesture::WheelDown) => Action::Seek(-WHEEL_SEEK_SECONDS),
+        (TrayIcon::Speed, Gesture::WheelUp) => Action::SpeedUp,
+        (TrayIcon::Speed, Gesture::WheelDown) => Action::SpeedDown,
+        _ => Action::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bind(key: &str, value: &str) -> Binding {
+        Binding::parse(key, value).unwrap()
+    }
+
+    #[test]
+    fn defaults() {
+        use Gesture::*;
+        use TrayIcon::*;
+        let cases = [
+            (Default, Left, Action::ShowMenu),
+            (Default, Right, Action::ShowMenu),
+            (Default, Middle, Action::None),
+            (Default, WheelUp, Action::None),
+            (Prev, Left, Action::Prev),
+            (Prev, Right, Action::ShowMenu),
+            (Prev, Double, Action::None),
+            (Prev, WheelUp, Action::Seek(10)),
+            (Prev, WheelDown, Action::Seek(-10)),
+            (Play, Left, Action::PlayPause),
+            (Play, ShiftLeft, Action::None),
+            (Play, CtrlLeft, Action::None),
+            (Play, WheelUp, Action::Next),
+            (Play, WheelDown, Action::Prev),
+            (Next, Left, Action::Next),
+            (Next, WheelUp, Action::Seek(10)),
+            (Next, WheelDown, Action::Seek(-10)),
+            (Shuffle, Left, Action::ToggleShuffle),
+            (Shuffle, WheelUp, Action::None),
+            (Repeat, Left, Action::CycleRepeat),
+            (Repeat, Right, Action::ShowMenu),
+            (Speed, Left, Action::SpeedUp),
+            (Speed, ShiftLeft, Action::SpeedDown),
+            (Speed, WheelUp, Action::SpeedUp),
+            (Speed, WheelDown, Action::SpeedDown),
+            (Speed, Double, Action::None),
+            (Output, Left, Action::NextOutput),
+            (Output, WheelUp, Action::None),
+        ];
+        for (icon, gesture, action) in cases {
+            assert_eq!(resolve(&[], icon, gesture), action, "{:?} {:?}", icon, gesture);
+        }
+    }
+
+    #[test]
+    fn user_bindings() {
+        use Gesture::*;
+        use TrayIcon::*;
+        let bindings = [
+            bind("bind.any.middle", "mute"),
+            bind("bind.play.middle", "stop"),
+            bind("bind.any.double", "open_player"),
+            bind("bind.play.double", "next"),
+            bind("bind.play.double", "prev"),
+            bind("bind.next.left", "seek+30"),
+            bind("bind.any.right", "open_player"),
+            bind("bind.default.right", "menu"),
+            bind("bind.play.wheel_up", "none"),
+        ];
+        let cases = [
+            // An icon's own binding beats `any`, in either order
+            (Play, Middle, Action::Stop),
+            (Next, Middle, Action::Mute),
+            // The later of two bindings for the same icon wins
+            (Play, Double, Action::Prev),
+            (Prev, Double, Action::OpenPlayer),
+            (Next, Left, Action::Seek(30)),
+            (Prev, Right, Action::OpenPlayer),
+            (Default, Right, Action::ShowMenu),
+            // `none` unbinds a default
+            (Play, WheelUp, Action::None),
+            // Everything else keeps its default
+            (Play, WheelDown, Action::Prev),
+            (Play, Left, Action::PlayPause),
+            (Repeat, Left, Action::CycleRepeat),
+        ];
+        for (icon, gesture, action) in cases {
+            assert_eq!(resolve(&bindings, icon, gesture), action, "{:?} {:?}", icon, gesture);
+        }
+    }
+
+    #[test]
+    fn later_any_binding_doesnt_beat_an_icon_binding() {
+        let bindings = [bind("bind.play.left", "next"), bind("bind.any.left", "stop")];
+        assert_eq!(resolve(&bindings, TrayIcon::Play, Gesture::Left), Action::Next);
+        assert_eq!(resolve(&bindings, TrayIcon::Prev, Gesture::Left), Action::Stop);
+    }
+
+    #[test]
+    fn parses_binding_lines() {
+        assert_eq!(
+            Binding::parse("bind.prev.wheel_up", "seek+10"),
+            Some(Binding { icon: Some(TrayIcon::Prev), gesture: Gesture::WheelUp, action: Action::Seek(10) })
+        );
+        assert_eq!(
+            Binding::parse("bind.any.shift_left", "seek-5"),
+            Some(Binding { icon: None, gesture: Gesture::ShiftLeft, action: Action::Seek(-5) })
+        );
+        for (key, value) in [
+            ("bind.play", "next"),
+            ("bind.play.left.extra", "next"),
+            ("bind.volume.left", "next"),
+            ("bind.play.triple", "next"),
+            ("bind.play.left", "rewind"),
+            ("bind.play.left", "seek10"),
+            ("bind.play.left", "seek+0"),
+            ("play.left", "next"),
+        ] {
+            assert_eq!(Binding::parse(key, value), None, "{}={}", key, value);
+        }
+    }
+
+    #[test]
+    fn bindings_round_trip_through_the_config() {
+        for line in [
+            "bind.any.middle=mute",
+            "bind.next.wheel_down=seek-15",
+            "bind.shuffle.ctrl_left=repeat",
+            "bind.play.shift_left=pause_all",
+            "bind.output.middle=next_output",
+            "bind.speed.middle=speed_down",
+            "bind.any.ctrl_left=speed_up",
+        ] {
+            let (key, value) = line.split_once('=').unwrap();
+            assert_eq!(bind(key, value).to_config(), line);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+// ============== Call Detection ==============
+//
+// An app recording from the microphone or camera is taken to be in a call.
+// Detectors report which apps capture right now; deciding which of them
+// count is done here, so it can be tested with made-up snapshots. On Windows
+// the snapshot comes from the privacy consent store, which keeps a
+// `LastUsedTimeStop` of 0 for every app still using a device.
+
+use crate::sessions::glob_match;
+
+/// One app's record for the microphone or the camera.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaptureUse {
+    /// Consent store key: a package family name, or an executable path with
+    /// `#` in place of `\`
+    pub key: String,
+    pub last_start: u64,
+    pub last_stop: u64,
+}
+
+impl CaptureUse {
+    pub fn active(&self) -> bool {
+        self.last_start != 0 && self.last_stop == 0
+    }
+}
+
+/// Where capture records come from; the shell implements it per platform.
+pub trait CaptureDetector {
+    fn snapshot(&self) -> Vec<CaptureUse>;
+}
+
+/// Short app name for a consent store key: the file name of a path
+/// (`Zoom.exe`), or the package name without its publisher hash
+/// (`MSTeams` for `MSTeams_8wekyb3d8bbwe`).
+pub fn app_name(key: &str) -> &str {
+    if let Some((_, file)) = key.rsplit_once('#') {
+        return file;
+    }
+    match key.rsplit_once('_') {
+        Some((name, hash)) if !name.is_empty() && hash.len() == 13 => name,
+        _ => key,
+    }
+}
+
+/// Apps capturing now that count as a call. With `apps` set, only matching
+/// apps count; `ignore` always wins. Both are globs over the app name.
+pub fn callers(snapshot: &[CaptureUse], apps: &[String], ignore: &[String]) -> Vec<String> {
+    let mut callers: Vec<String> = Vec::new();
+    for record in snapshot.iter().filter(|record| record.active()) {
+        let name = app_name(&record.key);
+        let wanted = apps.is_empty() || apps.iter().any(|pattern| glob_match(pattern, name));
+        let ignored = ignore.iter().any(|pattern| glob_match(pattern, name));
+        if wanted && !ignored && !callers.iter().any(|caller| caller == name) {
+            callers.push(name.to_string());
+        }
+    }
+    callers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(key: &str, last_start: u64, last_stop: u64) -> CaptureUse {
+        CaptureUse { key: key.to_string(), last_start, last_stop }
+    }
+
+    /// Microphone and camera stores as they look during a Zoom call, with
+    /// Teams idle and a packaged app that was never used.
+    fn snapshot() -> Vec<CaptureUse> {
+        vec![
+            record("C:#Program Files#Zoom#bin#Zoom.exe", 133_500_000_000_000_000, 0),
+            record("C:#Program Files#Zoom#bin#Zoom.exe", 133_500_000_000_000_000, 0),
+            record("MSTeams_8wekyb3d8bbwe", 133_400_000_000_000_000, 133_400_000_100_000_000),
+            record("Microsoft.WindowsCamera_8wekyb3d8bbwe", 0, 0),
+            record("C:#Windows#System32#VoiceAccess.exe", 133_500_000_000_000_000, 0),
+        ]
+    }
+
+    #[test]
+    fn names_consent_store_keys() {
+        assert_eq!(app_name("C:#Program Files#Zoom#bin#Zoom.exe"), "Zoom.exe");
+        assert_eq!(app_name("MSTeams_8wekyb3d8bbwe"), "MSTeams");
+        assert_eq!(app_name("Microsoft.WindowsCamera_8wekyb3d8bbwe"), "Microsoft.WindowsCamera");
+        assert_eq!(app_name("Some_App"), "Some_App");
+    }
+
+    #[test]
+    fn active_records_are_started_and_not_stopped() {
+        let active: Vec<bool> = snapshot().iter().map(CaptureUse::active).collect();
+        assert_eq!(active, [true, true, false, false, true]);
+    }
+
+    #[test]
+    fn every_capturing_app_counts_by_default() {
+        assert_eq!(callers(&snapshot(), &[], &[]), ["Zoom.exe", "VoiceAccess.exe"]);
+        assert!(callers(&[], &[], &[]).is_empty());
+    }
+
+    #[test]
+    fn lists_pick_the_apps_that_count() {
+        let list = |items: &[&str]| items.iter().map(|item| item.to_string()).collect::<Vec<_>>();
+        assert_eq!(callers(&snapshot(), &[], &list(&["voice*"])), ["Zoom.exe"]);
+        assert_eq!(callers(&snapshot(), &list(&["MSTeams", "zoom.exe"]), &[]), ["Zoom.exe"]);
+        assert!(callers(&snapshot(), &list(&["MSTeams"]), &[]).is_empty());
+        assert!(callers(&snapshot(), &list(&["Zoom.exe"]), &list(&["Zoom*"])).is_empty());
+    }
+}
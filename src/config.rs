@@ -0,0 +1,339 @@
+// ============== Config File Storage ==============
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::bindings::Binding;
+use crate::i18n::Language;
+use crate::output::DeviceClass;
+use crate::sessions::SessionRule;
+
+/// Icon color selection. `Light` means white glyphs, `Dark` means black glyphs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IconTheme {
+    #[default]
+    Auto,
+    Light,
+    Dark,
+}
+
+impl IconTheme {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IconTheme::Auto => "auto",
+            IconTheme::Light => "light",
+            IconTheme::Dark => "dark",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<IconTheme> {
+        match value {
+            "auto" => Some(IconTheme::Auto),
+            "light" => Some(IconTheme::Light),
+            "dark" => Some(IconTheme::Dark),
+            _ => None,
+        }
+    }
+}
+
+/// When the transport icons give way to the note icon.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AutoHide {
+    #[default]
+    Never,
+    /// While there is no media session
+    NoSession,
+    /// While there is no session or it is stopped
+    Stopped,
+}
+
+impl AutoHide {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AutoHide::Never => "never",
+            AutoHide::NoSession => "no_session",
+            AutoHide::Stopped => "stopped",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<AutoHide> {
+        match value {
+            "never" => Some(AutoHide::Never),
+            "no_session" => Some(AutoHide::NoSession),
+            "stopped" => Some(AutoHide::Stopped),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub show_prev: bool,
+    pub show_play: bool,
+    pub show_next: bool,
+    pub show_shuffle: bool,
+    pub show_repeat: bool,
+    /// Icon showing the session's playback speed
+    pub show_speed: bool,
+    /// Icon for the default output device
+    pub show_output: bool,
+    /// Follows `icon_theme` and the system theme, so it is never saved
+    pub dark_icons: bool,
+    pub icon_theme: IconTheme,
+    pub animations: bool,
+    pub progress_ring: bool,
+    pub notify_track: bool,
+    /// App IDs whose track changes are never announced
+    pub notify_mute: Vec<String>,
+    /// User click bindings, in file order
+    pub bindings: Vec<Binding>,
+    pub wheel: bool,
+    /// Wheel delta per action; 120 is one notch
+    pub wheel_step: i32,
+    /// Minimum time between two wheel actions
+    pub wheel_cooldown_ms: u32,
+    /// Mini-player instead of the menu on the note icon
+    pub flyout: bool,
+    /// UI language; `None` follows Windows
+    pub language: Option<Language>,
+    pub auto_hide: AutoHide,
+    /// Session selection rules, in priority order
+    pub session_rules: Vec<SessionRule>,
+    /// Lower the volume before the sleep timer pauses
+    pub sleep_fade: bool,
+    /// Length of the sleep timer's custom entry, in minutes
+    pub sleep_custom: u32,
+    /// Pause while the session is locked, the display is off or the system sleeps
+    pub pause_on_lock: bool,
+    /// Pause after `idle_minutes` without input
+    pub pause_on_idle: bool,
+    pub idle_minutes: u32,
+    /// Pause while an app uses the microphone or camera
+    pub pause_on_call: bool,
+    /// Apps whose capture counts as a call; empty means any
+    pub call_apps: Vec<String>,
+    /// Apps whose capture never counts
+    pub call_ignore: Vec<String>,
+    /// Pause when the default output leaves the `unplug_classes` devices
+    pub pause_on_unplug: bool,
+    pub unplug_classes: Vec<DeviceClass>,
+    /// Resume once such a device is the default output again
+    pub resume_on_reconnect: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            show_prev: false,
+            show_play: false,
+            show_next: false,
+            show_shuffle: false,
+            show_repeat: false,
+            show_speed: false,
+            show_output: false,
+            dark_icons: false,
+            icon_theme: IconTheme::Auto,
+            animations: false,
+            progress_ring: false,
+            notify_track: false,
+            notify_mute: Vec::new(),
+            bindings: Vec::new(),
+            wheel: false,
+            wheel_step: 120,
+            wheel_cooldown_ms: 150,
+            flyout: false,
+            language: None,
+            auto_hide: AutoHide::Never,
+            session_rules: Vec::new(),
+            sleep_fade: false,
+            sleep_custom: 90,
+            pause_on_lock: false,
+            pause_on_idle: false,
+            idle_minutes: 10,
+            pause_on_call: false,
+            call_apps: Vec::new(),
+            call_ignore: Vec::new(),
+            pause_on_unplug: false,
+            unplug_classes: vec![DeviceClass::Headphones],
+            resume_on_reconnect: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn parse(content: &str) -> Settings {
+        let mut settings = Settings::default();
+
+        for line in content.lines() {
+            // Only the first `=` splits, so values such as session patterns may contain one
+            if let Some((key, raw)) = line.split_once('=') {
+                let value = raw.trim() == "1";
+                match key.trim() {
+                    "prev" => settings.show_prev = value,
+                    "play" => settings.show_play = value,
+                    "next" => settings.show_next = value,
+                    "shuffle" => settings.show_shuffle = value,
+                    "repeat" => settings.show_repeat = value,
+                    "speed" => settings.show_speed = value,
+                    "output" => settings.show_output = value,
+                    "animations" => settings.animations = value,
+                    "progress_ring" => settings.progress_ring = value,
+                    "notify_track" => settings.notify_track = value,
+                    "notify_mute" => settings.notify_mute = parse_list(raw),
+                    "wheel" => settings.wheel = value,
+                    "flyout" => settings.flyout = value,
+                    "language" => settings.language = Language::parse(raw.trim()),
+                    "sleep_fade" => settings.sleep_fade = value,
+                    "pause_on_lock" => settings.pause_on_lock = value,
+                    "pause_on_idle" => settings.pause_on_idle = value,
+                    "pause_on_call" => settings.pause_on_call = value,
+                    "call_apps" => settings.call_apps = parse_list(raw),
+                    "call_ignore" => settings.call_ignore = parse_list(raw),
+                    "pause_on_unplug" => settings.pause_on_unplug = value,
+                    "unplug_classes" => {
+                        settings.unplug_classes = parse_list(raw).iter().filter_map(|c| DeviceClass::parse(c)).collect();
+                    }
+                    "resume_on_reconnect" => settings.resume_on_reconnect = value,
+                    "idle_minutes" => {
+                        if let Some(minutes) = raw.trim().parse().ok().filter(|&m: &u32| m > 0) {
+                            settings.idle_minutes = minutes;
+                        }
+                    }
+                    "sleep_custom" => {
+                        if let Some(minutes) = raw.trim().parse().ok().filter(|&m: &u32| m > 0) {
+                            settings.sleep_custom = minutes;
+                        }
+                    }
+                    "wheel_step" => {
+                        if let Some(step) = raw.trim().parse().ok().filter(|&s: &i32| s > 0) {
+                            settings.wheel_step = step;
+                        }
+                    }
+                    "wheel_cooldown_ms" => {
+                        if let Ok(ms) = raw.trim().parse() {
+                            settings.wheel_cooldown_ms = ms;
+                        }
+                    }
+                    "auto_hide" => {
+                        if let Some(auto_hide) = AutoHide::parse(raw.trim()) {
+                            settings.auto_hide = auto_hide;
+                        }
+                    }
+                    "icon_theme" => {
+                        if let Some(theme) = IconTheme::parse(raw.trim()) {
+                            settings.icon_theme = theme;
+                        }
+                    }
+                    "session" => {
+                        if let Some(rule) = SessionRule::parse(raw) {
+                            settings.session_rules.push(rule);
+                        }
+                    }
+                    key if key.starts_with("bind.") => {
+                        if let Some(binding) = Binding::parse(key, raw.trim()) {
+                            settings.bindings.push(binding);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        settings
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut content = format!(
+            "prev={}\nplay={}\nnext={}\nshuffle={}\nrepeat={}\nspeed={}\noutput={}\nicon_theme={}\nanimations={}\nprogress_ring={}\nnotify_track={}\nnotify_mute={}\nwheel={}\nwheel_step={}\nwheel_cooldown_ms={}\nflyout={}\nlanguage={}\nauto_hide={}\nsleep_fade={}\nsleep_custom={}\npause_on_lock={}\npause_on_idle={}\nidle_minutes={}\npause_on_call={}\ncall_apps={}\ncall_ignore={}\npause_on_unplug={}\nunplug_classes={}\nresume_on_reconnect={}",
+            self.show_prev as u8,
+            self.show_play as u8,
+            self.show_next as u8,
+            self.show_shuffle as u8,
+            self.show_repeat as u8,
+            self.show_speed as u8,
+            self.show_output as u8,
+            self.icon_theme.as_str(),
+            self.animations as u8,
+            self.progress_ring as u8,
+            self.notify_track as u8,
+            self.notify_mute.join(";"),
+            self.wheel as u8,
+            self.wheel_step,
+            self.wheel_cooldown_ms,
+            self.flyout as u8,
+            self.language.map(Language::as_str).unwrap_or("auto"),
+            self.auto_hide.as_str(),
+            self.sleep_fade as u8,
+            self.sleep_custom,
+            self.pause_on_lock as u8,
+            self.pause_on_idle as u8,
+            self.idle_minutes,
+            self.pause_on_call as u8,
+            self.call_apps.join(";"),
+            self.call_ignore.join(";"),
+            self.pause_on_unplug as u8,
+            self.unplug_classes.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(";"),
+            self.resume_on_reconnect as u8
+        );
+        for binding in &self.bindings {
+            content.push('\n');
+            content.push_str(&binding.to_config());
+        }
+        for rule in &self.session_rules {
+            content.push('\n');
+            content.push_str(&rule.to_config());
+        }
+        content
+    }
+}
+
+/// Splits a `;`-separated config value, dropping empty entries.
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn get_config_path() -> PathBuf {
+    let exe_path = env::current_exe().unwrap_or_default();
+    let exe_dir = exe_path.parent().unwrap_or(std::path::Path::new("."));
+    exe_dir.join("clickplay.cfg")
+}
+
+pub fn save_settings(settings: &Settings) {
+    let _ = fs::write(get_config_path(), settings.serialize());
+}
+
+pub fn load_settings() -> Settings {
+    fs::read_to_string(get_config_path())
+        .map(|content| Settings::parse(&content))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::RuleKind;
+
+    #[test]
+    fn values_may_contain_equals_signs() {
+        let settings = Settings::parse("session=prefer App=Player*\nnotify_mute=a=b;c\nplay=1\n");
+        assert_eq!(
+            settings.session_rules,
+            vec![SessionRule { kind: RuleKind::Prefer, pattern: "App=Player*".to_string() }]
+        );
+        assert_eq!(settings.notify_mute, ["a=b", "c"]);
+        assert!(settings.show_play);
+    }
+
+    #[test]
+    fn serialized_settings_parse_back() {
+        let settings = Settings::parse(
+            "play=0\nspeed=1\noutput=1\nicon_theme=dark\nsleep_fade=1\nsleep_custom=45\npause_on_lock=1\nidle_minutes=5\ncall_ignore=Voice*;obs64.exe\nunplug_classes=headphones;display\nsession=ignore *=*\nbind.any.middle=play_pause\n",
+        );
+        assert_eq!(Settings::parse(&settings.serialize()), settings);
+    }
+}
@@ -0,0 +1,78 @@
+// ============== Capability Consent Store ==============
+//
+// Windows records microphone and camera use per app under the privacy
+// consent store: one subkey per packaged app, plus one per executable under
+// `NonPackaged`, each with the FILETIMEs `LastUsedTimeStart` and
+// `LastUsedTimeStop`. Only the current user's store is read.
+
+use std::mem::size_of;
+use std::ptr::null_mut;
+
+use windows::core::{w, HSTRING, PCWSTR, PWSTR};
+use windows::Win32::System::Registry::{
+    RegCloseKey, RegEnumKeyExW, RegGetValueW, RegOpenKeyExW, HKEY, HKEY_CURRENT_USER, KEY_READ,
+    RRF_RT_REG_QWORD,
+};
+
+use crate::capture::{CaptureDetector, CaptureUse};
+
+const CONSENT_STORE: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\CapabilityAccessManager\\ConsentStore";
+const DEVICES: [&str; 2] = ["microphone", "webcam"];
+
+pub struct ConsentStore;
+
+impl CaptureDetector for ConsentStore {
+    fn snapshot(&self) -> Vec<CaptureUse> {
+        let mut records = Vec::new();
+        unsafe {
+            for device in DEVICES {
+                let path = format!("{}\\{}", CONSENT_STORE, device);
+                read_apps(&path, &mut records);
+                read_apps(&format!("{}\\NonPackaged", path), &mut records);
+            }
+        }
+        records
+    }
+}
+
+/// Adds a record for every app subkey of `path` that has usage times.
+unsafe fn read_apps(path: &str, records: &mut Vec<CaptureUse>) {
+    let mut hkey = HKEY(null_mut());
+    if RegOpenKeyExW(HKEY_CURRENT_USER, &HSTRING::from(path), 0, KEY_READ, &mut hkey).is_err() {
+        return;
+    }
+
+    let mut name = [0u16; 512];
+    for index in 0.. {
+        let mut len = name.len() as u32;
+        let result = RegEnumKeyExW(hkey, index, PWSTR(name.as_mut_ptr()), &mut len, None, PWSTR(null_mut()), None, None);
+        if result.is_err() {
+            break;
+        }
+        let key = String::from_utf16_lossy(&name[..len as usize]);
+        let subkey = HSTRING::from(key.as_str());
+        let start = read_qword(hkey, &subkey, w!("LastUsedTimeStart"));
+        let stop = read_qword(hkey, &subkey, w!("LastUsedTimeStop"));
+        if let (Some(last_start), Some(last_stop)) = (start, stop) {
+            records.push(CaptureUse { key, last_start, last_stop });
+        }
+    }
+
+    let _ = RegCloseKey(hkey);
+}
+
+unsafe fn read_qword(hkey: HKEY, subkey: &HSTRING, value: PCWSTR) -> Option<u64> {
+    let mut data: u64 = 0;
+    let mut size = size_of::<u64>() as u32;
+    RegGetValueW(
+        hkey,
+        subkey,
+        value,
+        RRF_RT_REG_QWORD,
+        None,
+        Some(&mut data as *mut u64 as *mut std::ffi::c_void),
+        Some(&mut size),
+    )
+    .is_ok()
+    .then_some(data)
+}
@@ -0,0 +1,2201 @@
+// ============== Application Core ==============
+//
+// All behavior lives in `update`, which turns an event into a new state plus
+// a list of effects for the Win32 shell to perform. Nothing here touches
+// Win32 or WinRT, and time is always passed in with the event.
+
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::bindings::{self, Action, Gesture};
+use crate::config::{AutoHide, IconTheme, Settings};
+use crate::flyout::FlyoutHit;
+use crate::i18n::Language;
+use crate::autopause::{AutoPause, AutoStep, Away, IdleSample, IdleWatch};
+use crate::capture::{self, CaptureUse};
+use crate::mixer::{self, AudioSession, VolumeChange};
+use crate::output::{self, OutputDevice, OutputWatch};
+use crate::sessions::PausedSession;
+use crate::sleep::{SleepChoice, SleepStep, SleepTimer};
+
+pub const POLL_INTERVAL_MS: u32 = 500;
+pub const ANIMATION_FRAME_MS: u32 = 33;
+pub const PROGRESS_INTERVAL_MS: u32 = 1000;
+
+// Theme is checked every 4th poll tick (~2s)
+const THEME_CHECK_TICKS: u32 = 4;
+
+pub const CROSSFADE_DURATION: Duration = Duration::from_millis(200);
+pub const SPINNER_DOTS: u32 = 8;
+
+// How long a clicked play state may disagree with the session before it is
+// rolled back, and how long the rolled-back icon blinks.
+const PENDING_PLAY_TIMEOUT: Duration = Duration::from_millis(1500);
+pub const ROLLBACK_CUE_DURATION: Duration = Duration::from_millis(900);
+
+// Arc resolution; the icon is only redrawn when the quantized value changes
+const PROGRESS_STEPS: f32 = 120.0;
+
+// A track must stay current this long before it is announced, so players that
+// publish title and artist separately produce a single notification.
+const NOTIFY_SETTLE: Duration = Duration::from_millis(1500);
+const NOTIFY_MIN_INTERVAL: Duration = Duration::from_secs(5);
+const NOTIFY_CLICK_SUPPRESS: Duration = Duration::from_secs(3);
+
+// A flyout click arriving this soon after the flyout lost focus is the click
+// that took the focus away, so it closes rather than reopens
+const FLYOUT_TOGGLE_GUARD: Duration = Duration::from_millis(400);
+
+// A hide/show decision must hold this long before the icons change, so a
+// player closing and reopening between tracks doesn't make them flicker
+const AUTO_HIDE_SETTLE: Duration = Duration::from_secs(2);
+
+// A partial wheel delta older than this is dropped instead of completing a step
+const WHEEL_IDLE_RESET: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaybackStatus {
+    Closed,
+    Opened,
+    Changing,
+    Stopped,
+    Playing,
+    Paused,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RepeatMode {
+    Off,
+    Track,
+    List,
+}
+
+impl RepeatMode {
+    /// Click order: off -> all -> one -> off.
+    pub fn next(self) -> RepeatMode {
+        match self {
+            RepeatMode::Off => RepeatMode::List,
+            RepeatMode::List => RepeatMode::Track,
+            RepeatMode::Track => RepeatMode::Off,
+        }
+    }
+}
+
+/// Shuffle, repeat and speed state of the current session. `None` means the
+/// session doesn't support the control, so its icon is hidden.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModeState {
+    pub shuffle: Option<bool>,
+    pub repeat: Option<RepeatMode>,
+    /// Playback rate, 1.0 being normal speed
+    pub speed: Option<f64>,
+}
+
+/// Playback rates offered by the speed menu and stepped through by the
+/// speed actions.
+pub const SPEED_PRESETS: [f64; 4] = [1.0, 1.25, 1.5, 2.0];
+
+/// Closest preset above (`up`) or below `speed`, or `None` past either end.
+/// Rates set elsewhere, e.g. 1.1x in the player, step to the next preset.
+pub fn step_speed(speed: f64, up: bool) -> Option<f64> {
+    if up {
+        SPEED_PRESETS.into_iter().find(|&preset| preset > speed + 0.01)
+    } else {
+        SPEED_PRESETS.into_iter().rev().find(|&preset| preset < speed - 0.01)
+    }
+}
+
+/// "1x", "1.25x": the rate as the menu and the speed icon show it.
+pub fn speed_label(speed: f64) -> String {
+    format!("{}x", (speed * 100.0).round() / 100.0)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackInfo {
+    pub app_id: String,
+    pub title: String,
+    pub artist: String,
+}
+
+/// Album art as top-down 32-bit BGRA rows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlbumArt {
+    pub width: i32,
+    pub height: i32,
+    pub pixels: Vec<u32>,
+}
+
+/// Session timeline in 100ns units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timeline {
+    pub position: i64,
+    pub start: i64,
+    pub end: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrayIcon {
+    Default,
+    Prev,
+    Play,
+    Next,
+    Shuffle,
+    Repeat,
+    /// Playback speed, drawn as text
+    Speed,
+    /// Default output device, drawn by its kind
+    Output,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MediaKey {
+    Prev,
+    PlayPause,
+    Next,
+    Stop,
+    Mute,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timer {
+    Animation,
+    Progress,
+    /// One-shot; fires once the double-click time has passed after a click
+    Click,
+    /// Sleep timer countdown, while armed
+    Sleep,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MenuCommand {
+    /// Transport entries, which work whether or not their icon is shown
+    Run(Action),
+    ToggleShowPrev,
+    ToggleShowPlay,
+    ToggleShowNext,
+    ToggleShowShuffle,
+    ToggleShowRepeat,
+    ToggleShowSpeed,
+    ToggleShowOutput,
+    SetTheme(IconTheme),
+    SetAutoHide(AutoHide),
+    ToggleAnimations,
+    ToggleProgressRing,
+    ToggleNotifyTrack,
+    ToggleMuteCurrentApp,
+    ToggleWheel,
+    ToggleFlyout,
+    SetSleep(SleepChoice),
+    ToggleSleepFade,
+    SetSpeed(f64),
+    /// Index into `State::outputs`
+    SetOutput(usize),
+    /// Index into `State::mixer`
+    AppVolume(usize, VolumeChange),
+    TogglePauseOnLock,
+    TogglePauseOnIdle,
+    TogglePauseOnCall,
+    TogglePauseOnUnplug,
+    ToggleResumeOnReconnect,
+    Exit,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Init { system_dark: bool, system_language: Language },
+    /// Poll timer
+    Tick,
+    AnimationFrame { now: Instant },
+    ProgressTick,
+    /// Sleep timer tick, also sent on resume from suspend; the wall clock
+    /// accounts for time the monotonic clock missed while suspended
+    SleepTick { now: Instant, wall: SystemTime },
+    Status { now: Instant, status: Option<PlaybackStatus> },
+    Track { now: Instant, track: Option<TrackInfo> },
+    Modes(ModeState),
+    Timeline { now: Instant, timeline: Option<Timeline> },
+    SystemTheme { dark: bool },
+    Mouse { now: Instant, icon: TrayIcon, gesture: Gesture },
+    ClickTimeout { now: Instant },
+    /// Raw wheel delta over an icon; positive is away from the user
+    Wheel { now: Instant, icon: TrayIcon, delta: i32 },
+    FlyoutInput { now: Instant, hit: FlyoutHit },
+    /// The flyout window was destroyed, for whatever reason
+    FlyoutClosed { now: Instant },
+    Art(Option<AlbumArt>),
+    /// The shell's tooltip for an icon opened or closed
+    Hover { icon: TrayIcon, open: bool },
+    /// App ID of the current session, answering `Effect::OpenPlayer`
+    PlayerApp(Option<String>),
+    /// Sessions a `PauseAll` paused
+    Paused(Vec<PausedSession>),
+    /// The user went away for `reason`, or came back from it
+    Away { reason: Away, active: bool },
+    /// Idle reading, answering `Effect::QueryIdle`
+    Idle(IdleSample),
+    /// Microphone and camera records, answering `Effect::QueryCapture`
+    Capture(Vec<CaptureUse>),
+    /// Active output devices, sent at startup and whenever they or the
+    /// default change
+    Outputs(Vec<OutputDevice>),
+    /// Audio sessions, answering `Effect::QueryMixer`
+    Mixer(Vec<AudioSession>),
+    Menu { now: Instant, command: MenuCommand },
+    ShuffleChanged(bool),
+    RepeatChanged(RepeatMode),
+    SpeedChanged(f64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Effect {
+    QueryStatus,
+    QueryTrack,
+    QueryModes,
+    QueryTimeline,
+    QueryArt,
+    QueryTheme,
+    /// Read the idle source
+    QueryIdle,
+    /// Read the microphone and camera records
+    QueryCapture,
+    /// Read the audio sessions for the volume mixer
+    QueryMixer,
+    SendKey(MediaKey),
+    /// Transport sent to the session picked by the session rules
+    Control(MediaKey),
+    SetShuffle(bool),
+    SetRepeat(RepeatMode),
+    SetSpeed(f64),
+    /// Relative seek in seconds
+    Seek(i32),
+    /// Seek to a fraction of the track
+    SeekTo(f32),
+    /// Look up the current session's app, then bring it up
+    OpenPlayer,
+    LaunchApp(String),
+    PauseAll,
+    /// Resume these sessions if they are still paused
+    Resume(Vec<PausedSession>),
+    /// Pause the current session
+    Pause,
+    /// Set this media app's audio to a fraction of its level before the fade
+    /// began
+    FadeVolume { app_id: String, level: f32 },
+    /// Put the faded app's volume back
+    RestoreVolume,
+    /// Make this output device the default
+    SetOutput(String),
+    /// Change the volume of this process's audio sessions
+    AppVolume { process: String, change: VolumeChange },
+    /// Remove and re-add every tray icon
+    RebuildIcons,
+    RedrawIcon(TrayIcon),
+    /// Context menu, anchored to the given icon
+    ShowMenu(TrayIcon),
+    /// Track notification, with the album art as its icon if there is any
+    ShowBalloon { title: String, text: String, art: Option<AlbumArt> },
+    /// Mini-player, anchored to the given icon
+    OpenFlyout(TrayIcon),
+    CloseFlyout,
+    RedrawFlyout,
+    StartTimer(Timer),
+    StopTimer(Timer),
+    /// Install or remove the mouse hook that reports wheel input over the icons
+    WheelHook(bool),
+    SaveSettings,
+    Exit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fade {
+    pub from_playing: bool,
+    pub start: Instant,
+}
+
+/// Play state shown optimistically after a click, until the session confirms
+/// it or `deadline` passes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PendingPlay {
+    pub target: bool,
+    pub deadline: Instant,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct State {
+    pub settings: Settings,
+    /// Displayed play state; may be ahead of the session while `pending_play` is set
+    pub playing: bool,
+    pub pending_play: Option<PendingPlay>,
+    /// Start of the blink shown after a pending click was rolled back
+    pub rollback_cue: Option<Instant>,
+    pub buffering: bool,
+    pub modes: ModeState,
+    pub system_dark: bool,
+    pub language: Language,
+    theme_check_counter: u32,
+    pub fade: Option<Fade>,
+    pub spinner_step: u32,
+    /// Fraction of the track played, if the ring is enabled and known
+    pub progress: Option<f32>,
+    last_position: i64,
+    position_seen_at: Option<Instant>,
+    pub notifier: TrackNotifier,
+    /// Track to announce once its album art arrives, or at the next tick
+    pending_balloon: Option<TrackInfo>,
+    pub auto_hide: AutoHider,
+    /// Sessions paused by `PauseAll`, waiting for `ResumePaused`
+    pub paused_sessions: Vec<PausedSession>,
+    pub sleep: SleepTimer,
+    pub auto_pause: AutoPause,
+    idle: IdleWatch,
+    /// Active output devices for the menu and the output icon
+    pub outputs: Vec<OutputDevice>,
+    /// Apps playing audio, one entry each, as of the last menu
+    pub mixer: Vec<AudioSession>,
+    output: OutputWatch,
+    /// Badge and minutes last drawn, so the icon is only redrawn on a change
+    sleep_shown: Option<(f32, Option<u64>)>,
+    /// Icon whose tooltip is showing, i.e. the one under the cursor
+    pub hovered: Option<TrayIcon>,
+    pub flyout_open: bool,
+    flyout_closed_at: Option<Instant>,
+    /// Latest session details for the menu header and the flyout
+    pub track: Option<TrackInfo>,
+    pub timeline: Option<Timeline>,
+    pub art: Option<AlbumArt>,
+    /// Icon whose single click waits to see if it becomes a double-click
+    pending_click: Option<TrayIcon>,
+    /// Set after a double-click so its trailing button-up isn't a click
+    swallow_left_up: bool,
+    wheel: WheelAccumulator,
+    wheel_hook: bool,
+    animation_timer: bool,
+    progress_timer: bool,
+    sleep_timer: bool,
+}
+
+impl State {
+    pub fn new(settings: Settings) -> State {
+        State { settings, ..State::default() }
+    }
+
+    /// Icons currently in the tray, in order. Falls back to the default icon
+    /// so the menu stays reachable.
+    pub fn visible_icons(&self) -> Vec<TrayIcon> {
+        if self.auto_hide.hidden {
+            return vec![TrayIcon::Default];
+        }
+        let s = &self.settings;
+        let mut icons = Vec::new();
+        if s.show_prev { icons.push(TrayIcon::Prev); }
+        if s.show_play { icons.push(TrayIcon::Play); }
+        if s.show_next { icons.push(TrayIcon::Next); }
+        if s.show_shuffle && self.modes.shuffle.is_some() { icons.push(TrayIcon::Shuffle); }
+        if s.show_repeat && self.modes.repeat.is_some() { icons.push(TrayIcon::Repeat); }
+        if s.show_speed && self.modes.speed.is_some() { icons.push(TrayIcon::Speed); }
+        if s.show_output && self.default_output().is_some() { icons.push(TrayIcon::Output); }
+        if icons.is_empty() {
+            icons.push(TrayIcon::Default);
+        }
+        icons
+    }
+
+    pub fn default_output(&self) -> Option<&OutputDevice> {
+        self.outputs.iter().find(|device| device.default)
+    }
+
+    /// Icon carrying the sleep timer's badge and remaining time: the play icon
+    /// if it is shown, otherwise the first one.
+    pub fn sleep_badge_icon(&self) -> TrayIcon {
+        let icons = self.visible_icons();
+        if icons.contains(&TrayIcon::Play) {
+            TrayIcon::Play
+        } else {
+            icons[0]
+        }
+    }
+}
+
+pub fn update(mut state: State, event: Event) -> (State, Vec<Effect>) {
+    let mut effects = Vec::new();
+
+    match event {
+        Event::Init { system_dark, system_language } => {
+            state.system_dark = system_dark;
+            state.language = state.settings.language.unwrap_or(system_language);
+            apply_icon_theme(&mut state);
+            effects.push(Effect::QueryStatus);
+            effects.push(Effect::QueryModes);
+            if state.settings.progress_ring {
+                effects.push(Effect::QueryTimeline);
+            }
+            effects.push(Effect::RebuildIcons);
+        }
+        Event::Tick => {
+            // The art query went unanswered for a whole poll; announce without it
+            if let Some(track) = state.pending_balloon.take() {
+                effects.push(Effect::ShowBalloon { title: track.title, text: track.artist, art: None });
+            }
+            // Check playback state every tick; the track also feeds the menu header
+            effects.push(Effect::QueryStatus);
+            effects.push(Effect::QueryTrack);
+            if state.flyout_open {
+                effects.push(Effect::QueryTimeline);
+            }
+            // Also read without their icons, so the speed menu knows if it applies
+            effects.push(Effect::QueryModes);
+
+            if state.settings.pause_on_idle {
+                effects.push(Effect::QueryIdle);
+            }
+            if state.settings.pause_on_call {
+                effects.push(Effect::QueryCapture);
+            }
+
+            state.theme_check_counter += 1;
+            if state.theme_check_counter >= THEME_CHECK_TICKS {
+                state.theme_check_counter = 0;
+                effects.push(Effect::QueryTheme);
+            }
+        }
+        Event::AnimationFrame { now } => {
+            if let Some(fade) = state.fade {
+                if now.duration_since(fade.start) >= CROSSFADE_DURATION {
+                    state.fade = None;
+                }
+            }
+            if let Some(start) = state.rollback_cue {
+                if now.duration_since(start) >= ROLLBACK_CUE_DURATION {
+                    state.rollback_cue = None;
+                }
+            }
+            if state.buffering {
+                state.spinner_step = (state.spinner_step + 1) % SPINNER_DOTS;
+            }
+            redraw_play(&state, &mut effects);
+        }
+        Event::ProgressTick => {
+            effects.push(Effect::QueryTimeline);
+        }
+        Event::SleepTick { now, wall } => {
+            if state.sleep.waits_for_track_end() {
+                effects.push(Effect::QueryTimeline);
+            }
+            let steps = state.sleep.tick(now, wall, state.settings.sleep_fade);
+            run_sleep_steps(&mut state, steps, now, &mut effects);
+        }
+        Event::Status { now, status } => {
+            let playing = status == Some(PlaybackStatus::Playing);
+            state.auto_pause.observe(playing);
+            if reconcile_play(&mut state, playing, now) && playing != state.playing {
+                state.playing = playing;
+                start_play_crossfade(&mut state, !playing, now);
+                redraw_play(&state, &mut effects);
+                redraw_flyout(&state, &mut effects);
+            }
+
+            let buffering = matches!(status, Some(PlaybackStatus::Changing) | Some(PlaybackStatus::Opened));
+            if buffering != state.buffering {
+                state.buffering = buffering;
+                redraw_play(&state, &mut effects);
+            }
+
+            if state.auto_hide.observe(now, hides_transport(state.settings.auto_hide, status)) {
+                effects.push(Effect::RebuildIcons);
+            }
+        }
+        Event::Track { now, track } => {
+            state.sleep.observe_track(track.as_ref());
+            if track != state.track {
+                state.track = track.clone();
+                if state.flyout_open {
+                    state.art = None;
+                    effects.push(Effect::QueryArt);
+                    redraw_flyout(&state, &mut effects);
+                }
+            }
+            if state.settings.notify_track {
+                let muted = &state.settings.notify_mute;
+                if let Some(track) = state.notifier.observe(now, track, muted) {
+                    state.pending_balloon = Some(track);
+                    effects.push(Effect::QueryArt);
+                }
+            }
+        }
+        Event::Modes(modes) => refresh_modes(&mut state, modes, &mut effects),
+        Event::ShuffleChanged(active) => {
+            let modes = ModeState { shuffle: Some(active), ..state.modes };
+            refresh_modes(&mut state, modes, &mut effects);
+        }
+        Event::RepeatChanged(mode) => {
+            let modes = ModeState { repeat: Some(mode), ..state.modes };
+            refresh_modes(&mut state, modes, &mut effects);
+        }
+        Event::SpeedChanged(speed) => {
+            let modes = ModeState { speed: Some(speed), ..state.modes };
+            refresh_modes(&mut state, modes, &mut effects);
+        }
+        Event::Timeline { now, timeline } => {
+            state.sleep.observe_timeline(now, timeline, state.playing);
+            if update_progress(&mut state, now, timeline) {
+                redraw_play(&state, &mut effects);
+            }
+            if state.flyout_open && timeline != state.timeline {
+                state.timeline = timeline;
+                redraw_flyout(&state, &mut effects);
+            }
+        }
+        Event::SystemTheme { dark } => {
+            if dark != state.system_dark {
+                state.system_dark = dark;
+                // Only the derived icon color changes; nothing to save
+                if apply_icon_theme(&mut state) {
+                    effects.push(Effect::RebuildIcons);
+                }
+            }
+        }
+        Event::Mouse { now, icon, gesture } => handle_mouse(&mut state, icon, gesture, now, &mut effects),
+        Event::ClickTimeout { now } => {
+            effects.push(Effect::StopTimer(Timer::Click));
+            if let Some(icon) = state.pending_click.take() {
+                let action = resolve_action(&state.settings, icon, Gesture::Left);
+                run_action(&mut state, icon, action, now, &mut effects);
+            }
+        }
+        Event::Wheel { now, icon, delta } => {
+            let settings = &state.settings;
+            let cooldown = Duration::from_millis(settings.wheel_cooldown_ms as u64);
+            if let Some(gesture) = state.wheel.feed(now, icon, delta, settings.wheel_step, cooldown) {
+                handle_mouse(&mut state, icon, gesture, now, &mut effects);
+            }
+        }
+        Event::FlyoutInput { now, hit } => {
+            // The flyout belongs to the note icon, so it acts like that icon
+            let icon = TrayIcon::Default;
+            match hit {
+                FlyoutHit::Prev => run_action(&mut state, icon, Action::Prev, now, &mut effects),
+                FlyoutHit::PlayPause => run_action(&mut state, icon, Action::PlayPause, now, &mut effects),
+                FlyoutHit::Next => run_action(&mut state, icon, Action::Next, now, &mut effects),
+                FlyoutHit::Seek(fraction) => {
+                    effects.push(Effect::SeekTo(fraction.clamp(0.0, 1.0)));
+                    effects.push(Effect::QueryTimeline);
+                }
+            }
+        }
+        Event::FlyoutClosed { now } => {
+            if state.flyout_open {
+                state.flyout_open = false;
+                state.flyout_closed_at = Some(now);
+            }
+        }
+        Event::Art(art) => {
+            if let Some(track) = state.pending_balloon.take() {
+                effects.push(Effect::ShowBalloon { title: track.title, text: track.artist, art: art.clone() });
+            }
+            if state.flyout_open {
+                state.art = art;
+                redraw_flyout(&state, &mut effects);
+            }
+        }
+        Event::Hover { icon, open } => {
+            if open {
+                state.hovered = Some(icon);
+            } else if state.hovered == Some(icon) {
+                state.hovered = None;
+            }
+        }
+        Event::PlayerApp(app_id) => {
+            if let Some(app_id) = app_id {
+                effects.push(Effect::LaunchApp(app_id));
+            }
+        }
+        Event::Paused(sessions) => {
+            // A second pause-all adds to the set rather than forgetting the first
+            let known = state.paused_sessions.len();
+            for session in sessions {
+                if !state.paused_sessions[..known].contains(&session) {
+                    state.paused_sessions.push(session);
+                }
+            }
+        }
+        Event::Away { reason, active } => set_away(&mut state, reason, active, &mut effects),
+        Event::Idle(sample) => {
+            let limit = Duration::from_secs(state.settings.idle_minutes as u64 * 60);
+            if let Some(idle) = state.idle.observe(sample, limit) {
+                set_away(&mut state, Away::Idle, idle, &mut effects);
+            }
+        }
+        Event::Capture(snapshot) => {
+            let settings = &state.settings;
+            let call = !capture::callers(&snapshot, &settings.call_apps, &settings.call_ignore).is_empty();
+            set_away(&mut state, Away::Call, call, &mut effects);
+        }
+        Event::Outputs(devices) => {
+            let icons = state.visible_icons();
+            let shown = state.default_output().cloned();
+            state.outputs = devices;
+            if state.visible_icons() != icons {
+                effects.push(Effect::RebuildIcons);
+            } else if state.default_output() != shown.as_ref() && icons.contains(&TrayIcon::Output) {
+                effects.push(Effect::RedrawIcon(TrayIcon::Output));
+            }
+
+            let class = state.default_output().map(|device| device.class);
+            if let Some(left) = state.output.observe(class, &state.settings.unplug_classes) {
+                set_away(&mut state, Away::Unplugged, left, &mut effects);
+            }
+        }
+        Event::Mixer(sessions) => {
+            let playing = state.track.as_ref().map(|track| track.app_id.as_str());
+            state.mixer = mixer::apps(sessions, playing);
+        }
+        Event::Menu { now, command } => handle_menu_command(&mut state, command, now, &mut effects),
+    }
+
+    sync_timers(&mut state, &mut effects);
+    (state, effects)
+}
+
+/// Resolves a gesture through the bindings. A left click on an icon with a
+/// double-click binding is held back until the double-click time has passed.
+fn handle_mouse(state: &mut State, icon: TrayIcon, gesture: Gesture, now: Instant, effects: &mut Vec<Effect>) {
+    let settings = &state.settings;
+    let has_double = resolve_action(settings, icon, Gesture::Double) != Action::None;
+
+    // The double-click's own button-up, whatever keys were held by then
+    let left_up = matches!(gesture, Gesture::Left | Gesture::ShiftLeft | Gesture::CtrlLeft);
+    if left_up && std::mem::take(&mut state.swallow_left_up) {
+        return;
+    }
+
+    match gesture {
+        Gesture::Left => {
+            // A click on another icon settles the one still waiting
+            if let Some(pending) = state.pending_click.take() {
+                let action = resolve_action(settings, pending, Gesture::Left);
+                effects.push(Effect::StopTimer(Timer::Click));
+                run_action(state, pending, action, now, effects);
+            }
+            if has_double {
+                state.pending_click = Some(icon);
+                effects.push(Effect::StartTimer(Timer::Click));
+                return;
+            }
+        }
+        Gesture::Double => {
+            // Without a binding both halves already ran as single clicks
+            if !has_double {
+                return;
+            }
+            if state.pending_click.take().is_some() {
+                effects.push(Effect::StopTimer(Timer::Click));
+            }
+            state.swallow_left_up = true;
+        }
+        _ => {}
+    }
+
+    let action = resolve_action(&state.settings, icon, gesture);
+    run_action(state, icon, action, now, effects);
+}
+
+fn resolve_action(settings: &Settings, icon: TrayIcon, gesture: Gesture) -> Action {
+    match bindings::resolve(&settings.bindings, icon, gesture) {
+        // With the flyout enabled, it replaces the menu on the note icon's click
+        Action::ShowMenu if settings.flyout && icon == TrayIcon::Default && gesture == Gesture::Left => {
+            Action::ShowFlyout
+        }
+        action => action,
+    }
+}
+
+fn run_action(state: &mut State, icon: TrayIcon, action: Action, now: Instant, effects: &mut Vec<Effect>) {
+    // Only these change the track; others (volume, shuffle, ...) leave the balloon alone
+    if matches!(action, Action::PlayPause | Action::Next | Action::Prev) {
+        state.notifier.suppress(now);
+    }
+
+    match action {
+        Action::None => {}
+        Action::PlayPause => {
+            effects.push(transport(&state.settings, MediaKey::PlayPause));
+            state.playing = !state.playing;
+            state.pending_play = Some(PendingPlay { target: state.playing, deadline: now + PENDING_PLAY_TIMEOUT });
+            state.rollback_cue = None;
+            start_play_crossfade(state, !state.playing, now);
+            redraw_play(state, effects);
+            redraw_flyout(state, effects);
+        }
+        Action::Next => effects.push(transport(&state.settings, MediaKey::Next)),
+        Action::Prev => effects.push(transport(&state.settings, MediaKey::Prev)),
+        Action::Stop => effects.push(transport(&state.settings, MediaKey::Stop)),
+        Action::Mute => effects.push(Effect::SendKey(MediaKey::Mute)),
+        Action::Seek(seconds) => {
+            effects.push(Effect::Seek(seconds));
+            if state.settings.progress_ring {
+                effects.push(Effect::QueryTimeline);
+            }
+        }
+        Action::OpenPlayer => effects.push(Effect::OpenPlayer),
+        Action::PauseAll => {
+            effects.push(Effect::PauseAll);
+            effects.push(Effect::QueryStatus);
+        }
+        Action::ResumePaused => {
+            if !state.paused_sessions.is_empty() {
+                effects.push(Effect::Resume(std::mem::take(&mut state.paused_sessions)));
+                effects.push(Effect::QueryStatus);
+            }
+        }
+        Action::ShowMenu => {
+            // Answered before the menu is built, so the mixer shows current levels
+            effects.push(Effect::QueryMixer);
+            effects.push(Effect::ShowMenu(icon));
+        }
+        Action::ShowFlyout => {
+            let refocus = state.flyout_closed_at.is_some_and(|at| now.duration_since(at) < FLYOUT_TOGGLE_GUARD);
+            if state.flyout_open {
+                state.flyout_open = false;
+                effects.push(Effect::CloseFlyout);
+            } else if !refocus {
+                state.flyout_open = true;
+                state.timeline = None;
+                state.art = None;
+                effects.push(Effect::OpenFlyout(icon));
+                effects.push(Effect::QueryTrack);
+                effects.push(Effect::QueryTimeline);
+                effects.push(Effect::QueryArt);
+            }
+        }
+        Action::ToggleShuffle => {
+            if let Some(active) = state.modes.shuffle {
+                effects.push(Effect::SetShuffle(!active));
+            }
+        }
+        Action::CycleRepeat => {
+            if let Some(mode) = state.modes.repeat {
+                effects.push(Effect::SetRepeat(mode.next()));
+            }
+        }
+        Action::NextOutput => {
+            if let Some(device) = output::next_output(&state.outputs) {
+                effects.push(Effect::SetOutput(device.id.clone()));
+            }
+        }
+        Action::SpeedUp | Action::SpeedDown => {
+            if let Some(speed) = state.modes.speed.and_then(|speed| step_speed(speed, action == Action::SpeedUp)) {
+                effects.push(Effect::SetSpeed(speed));
+            }
+        }
+    }
+}
+
+/// Media keys reach whichever session Windows picks, so with session rules
+/// the transport goes to the selected session instead.
+fn transport(settings: &Settings, key: MediaKey) -> Effect {
+    if settings.session_rules.is_empty() {
+        Effect::SendKey(key)
+    } else {
+        Effect::Control(key)
+    }
+}
+
+fn handle_menu_command(state: &mut State, command: MenuCommand, now: Instant, effects: &mut Vec<Effect>) {
+    let settings = &mut state.settings;
+
+    match command {
+        MenuCommand::Run(action) => {
+            run_action(state, TrayIcon::Default, action, now, effects);
+            return;
+        }
+        MenuCommand::ToggleShowPrev => {
+            settings.show_prev = !settings.show_prev;
+            effects.push(Effect::RebuildIcons);
+        }
+        MenuCommand::ToggleShowPlay => {
+            settings.show_play = !settings.show_play;
+            effects.push(Effect::RebuildIcons);
+        }
+        MenuCommand::ToggleShowNext => {
+            settings.show_next = !settings.show_next;
+            effects.push(Effect::RebuildIcons);
+        }
+        MenuCommand::ToggleShowShuffle => {
+            settings.show_shuffle = !settings.show_shuffle;
+            effects.push(Effect::QueryModes);
+            effects.push(Effect::RebuildIcons);
+        }
+        MenuCommand::ToggleShowRepeat => {
+            settings.show_repeat = !settings.show_repeat;
+            effects.push(Effect::QueryModes);
+            effects.push(Effect::RebuildIcons);
+        }
+        MenuCommand::ToggleShowSpeed => {
+            settings.show_speed = !settings.show_speed;
+            effects.push(Effect::RebuildIcons);
+        }
+        MenuCommand::ToggleShowOutput => {
+            settings.show_output = !settings.show_output;
+            effects.push(Effect::RebuildIcons);
+        }
+        MenuCommand::SetTheme(theme) => {
+            settings.icon_theme = theme;
+            apply_icon_theme(state);
+            effects.push(Effect::RebuildIcons);
+        }
+        MenuCommand::SetAutoHide(auto_hide) => {
+            settings.auto_hide = auto_hide;
+            // Show the configured icons again and let the next status decide without settling
+            state.auto_hide = AutoHider::default();
+            effects.push(Effect::QueryStatus);
+            effects.push(Effect::RebuildIcons);
+        }
+        MenuCommand::ToggleAnimations => {
+            settings.animations = !settings.animations;
+            if !settings.animations {
+                state.fade = None;
+            }
+            redraw_play(state, effects);
+        }
+        MenuCommand::ToggleProgressRing => {
+            settings.progress_ring = !settings.progress_ring;
+            if settings.progress_ring {
+                effects.push(Effect::QueryTimeline);
+            } else {
+                state.progress = None;
+            }
+            redraw_play(state, effects);
+        }
+        MenuCommand::ToggleNotifyTrack => {
+            settings.notify_track = !settings.notify_track;
+            // Don't announce whatever changed while notifications were off
+            state.notifier.reset();
+        }
+        MenuCommand::ToggleMuteCurrentApp => {
+            if let Some(app_id) = state.notifier.current_app() {
+                if is_app_muted(&app_id, &settings.notify_mute) {
+                    settings.notify_mute.retain(|m| !app_id.eq_ignore_ascii_case(m));
+                } else {
+                    settings.notify_mute.push(app_id);
+                }
+            }
+        }
+        MenuCommand::ToggleWheel => {
+            settings.wheel = !settings.wheel;
+            state.wheel = WheelAccumulator::default();
+        }
+        MenuCommand::ToggleFlyout => {
+            settings.flyout = !settings.flyout;
+            if !settings.flyout && state.flyout_open {
+                state.flyout_open = false;
+                effects.push(Effect::CloseFlyout);
+            }
+        }
+        MenuCommand::SetSleep(choice) => {
+            let steps = state.sleep.arm(choice, now, state.track.as_ref());
+            if state.sleep.waits_for_track_end() {
+                effects.push(Effect::QueryTimeline);
+            }
+            run_sleep_steps(state, steps, now, effects);
+            // The timer itself isn't kept across restarts
+            return;
+        }
+        MenuCommand::ToggleSleepFade => settings.sleep_fade = !settings.sleep_fade,
+        MenuCommand::SetSpeed(speed) => {
+            // The speed belongs to the session, not the settings
+            if state.modes.speed.is_some() {
+                effects.push(Effect::SetSpeed(speed));
+            }
+            return;
+        }
+        MenuCommand::SetOutput(index) => {
+            // The new default comes back through `Event::Outputs`
+            if let Some(device) = state.outputs.get(index).filter(|device| !device.default) {
+                effects.push(Effect::SetOutput(device.id.clone()));
+            }
+            return;
+        }
+        MenuCommand::AppVolume(index, change) => {
+            if let Some(app) = state.mixer.get(index) {
+                effects.push(Effect::AppVolume { process: app.process.clone(), change });
+            }
+            return;
+        }
+        MenuCommand::TogglePauseOnLock => {
+            settings.pause_on_lock = !settings.pause_on_lock;
+            for reason in [Away::Locked, Away::DisplayOff, Away::Suspended] {
+                state.auto_pause.abandon(reason);
+            }
+        }
+        MenuCommand::TogglePauseOnIdle => {
+            settings.pause_on_idle = !settings.pause_on_idle;
+            state.auto_pause.abandon(Away::Idle);
+            state.idle = IdleWatch::default();
+        }
+        MenuCommand::TogglePauseOnCall => {
+            settings.pause_on_call = !settings.pause_on_call;
+            state.auto_pause.abandon(Away::Call);
+        }
+        MenuCommand::TogglePauseOnUnplug => {
+            settings.pause_on_unplug = !settings.pause_on_unplug;
+            state.auto_pause.abandon(Away::Unplugged);
+        }
+        MenuCommand::ToggleResumeOnReconnect => settings.resume_on_reconnect = !settings.resume_on_reconnect,
+        MenuCommand::Exit => {
+            effects.push(Effect::Exit);
+            return;
+        }
+    }
+
+    effects.push(Effect::SaveSettings);
+}
+
+/// Starts or ends a reason to be away. Only reasons whose setting is on can
+/// pause; any can end, so turning a setting off never strands a pause.
+fn set_away(state: &mut State, reason: Away, active: bool, effects: &mut Vec<Effect>) {
+    let enabled = match reason {
+        Away::Locked | Away::DisplayOff | Away::Suspended => state.settings.pause_on_lock,
+        Away::Idle => state.settings.pause_on_idle,
+        Away::Call => state.settings.pause_on_call,
+        Away::Unplugged => state.settings.pause_on_unplug,
+    };
+    let step = if !active {
+        if reason == Away::Unplugged && !state.settings.resume_on_reconnect {
+            state.auto_pause.abandon(reason);
+            return;
+        }
+        state.auto_pause.end(reason)
+    } else if enabled {
+        let playing = state.track.as_ref().filter(|_| state.playing).map(|track| PausedSession {
+            app_id: track.app_id.clone(),
+            title: track.title.clone(),
+        });
+        state.auto_pause.begin(reason, playing.as_ref())
+    } else {
+        None
+    };
+    match step {
+        Some(AutoStep::Pause) => effects.extend([Effect::Pause, Effect::QueryStatus]),
+        Some(AutoStep::Resume(session)) => effects.extend([Effect::Resume(vec![session]), Effect::QueryStatus]),
+        None => {}
+    }
+}
+
+fn run_sleep_steps(state: &mut State, steps: Vec<SleepStep>, now: Instant, effects: &mut Vec<Effect>) {
+    for step in steps {
+        match step {
+            SleepStep::Volume(level) => {
+                if let Some(track) = &state.track {
+                    effects.push(Effect::FadeVolume { app_id: track.app_id.clone(), level });
+                }
+            }
+            SleepStep::Pause => {
+                effects.push(Effect::Pause);
+                effects.push(Effect::QueryStatus);
+            }
+            SleepStep::RestoreVolume => effects.push(Effect::RestoreVolume),
+        }
+    }
+
+    let shown = state.sleep.badge(now).map(|badge| (badge, state.sleep.minutes_left(now)));
+    if shown != state.sleep_shown {
+        state.sleep_shown = shown;
+        effects.push(Effect::RedrawIcon(state.sleep_badge_icon()));
+    }
+}
+
+fn redraw_play(state: &State, effects: &mut Vec<Effect>) {
+    if state.settings.show_play {
+        effects.push(Effect::RedrawIcon(TrayIcon::Play));
+    }
+}
+
+fn redraw_flyout(state: &State, effects: &mut Vec<Effect>) {
+    if state.flyout_open {
+        effects.push(Effect::RedrawFlyout);
+    }
+}
+
+/// Recomputes `dark_icons` from the theme setting. Returns true if it changed.
+fn apply_icon_theme(state: &mut State) -> bool {
+    let dark_icons = match state.settings.icon_theme {
+        IconTheme::Auto => !state.system_dark,
+        IconTheme::Light => false,
+        IconTheme::Dark => true,
+    };
+    let changed = dark_icons != state.settings.dark_icons;
+    state.settings.dark_icons = dark_icons;
+    changed
+}
+
+/// Settles a pending click against the reported play state. Returns true if
+/// the reported state should be displayed, false while the click is still
+/// waiting for the session to catch up.
+fn reconcile_play(state: &mut State, reported: bool, now: Instant) -> bool {
+    let Some(pending) = state.pending_play else {
+        return true;
+    };
+
+    if reported == pending.target {
+        state.pending_play = None;
+        true
+    } else if now >= pending.deadline {
+        // Nothing responded to the key; show the real state and flag it
+        state.pending_play = None;
+        state.rollback_cue = Some(now);
+        true
+    } else {
+        false
+    }
+}
+
+/// Starts a cross-fade away from the glyph for `from_playing`.
+fn start_play_crossfade(state: &mut State, from_playing: bool, now: Instant) {
+    if state.settings.animations && state.settings.show_play {
+        state.fade = Some(Fade { from_playing, start: now });
+    }
+}
+
+/// Applies new shuffle/repeat/speed state, re-adding icons only if support
+/// for a shown one changed.
+fn refresh_modes(state: &mut State, modes: ModeState, effects: &mut Vec<Effect>) {
+    let icons = state.visible_icons();
+    let old = state.modes;
+    state.modes = modes;
+
+    if state.visible_icons() != icons {
+        effects.push(Effect::RebuildIcons);
+        return;
+    }
+    if icons.contains(&TrayIcon::Shuffle) && old.shuffle != modes.shuffle {
+        effects.push(Effect::RedrawIcon(TrayIcon::Shuffle));
+    }
+    if icons.contains(&TrayIcon::Repeat) && old.repeat != modes.repeat {
+        effects.push(Effect::RedrawIcon(TrayIcon::Repeat));
+    }
+    if icons.contains(&TrayIcon::Speed) && old.speed != modes.speed {
+        effects.push(Effect::RedrawIcon(TrayIcon::Speed));
+    }
+}
+
+/// Runs the frame timer only while something is animating, and polls the
+/// timeline only while playing so a paused track keeps its arc.
+fn sync_timers(state: &mut State, effects: &mut Vec<Effect>) {
+    let settings = &state.settings;
+
+    // The rollback cue is feedback rather than decoration, so it runs even with animations off
+    let animating = settings.show_play
+        && (state.rollback_cue.is_some() || (settings.animations && (state.fade.is_some() || state.buffering)));
+    if animating != state.animation_timer {
+        state.animation_timer = animating;
+        effects.push(if animating { Effect::StartTimer(Timer::Animation) } else { Effect::StopTimer(Timer::Animation) });
+    }
+
+    let progress = settings.progress_ring && settings.show_play && state.playing;
+    if progress != state.progress_timer {
+        state.progress_timer = progress;
+        if progress {
+            // Time spent paused must not count towards extrapolation
+            state.position_seen_at = None;
+        }
+        effects.push(if progress { Effect::StartTimer(Timer::Progress) } else { Effect::StopTimer(Timer::Progress) });
+    }
+
+    let sleeping = state.sleep.active();
+    if sleeping != state.sleep_timer {
+        state.sleep_timer = sleeping;
+        effects.push(if sleeping { Effect::StartTimer(Timer::Sleep) } else { Effect::StopTimer(Timer::Sleep) });
+    }
+
+    if settings.wheel != state.wheel_hook {
+        state.wheel_hook = settings.wheel;
+        effects.push(Effect::WheelHook(settings.wheel));
+    }
+}
+
+// ============== Track Progress ==============
+
+/// Refreshes the progress fraction. Returns true if the drawn arc would change.
+fn update_progress(state: &mut State, now: Instant, timeline: Option<Timeline>) -> bool {
+    let fraction = if state.settings.progress_ring {
+        timeline.and_then(|timeline| {
+            // Many players only report position on seek or state change,
+            // so extrapolate from when the reported value last moved.
+            let seen_at = match state.position_seen_at {
+                Some(seen_at) if state.last_position == timeline.position => seen_at,
+                _ => now,
+            };
+            state.last_position = timeline.position;
+            state.position_seen_at = Some(seen_at);
+
+            let elapsed = if state.playing {
+                (now.duration_since(seen_at).as_nanos() / 100) as i64
+            } else {
+                0
+            };
+            timeline_fraction(timeline.position + elapsed, timeline.start, timeline.end)
+        })
+    } else {
+        None
+    };
+
+    let quantize = |f: Option<f32>| f.map(|f| (f * PROGRESS_STEPS).round() as i32);
+    let changed = quantize(fraction) != quantize(state.progress);
+    state.progress = fraction;
+    changed
+}
+
+/// Fraction of the track played, from timeline values in 100ns units.
+pub fn timeline_fraction(position: i64, start: i64, end: i64) -> Option<f32> {
+    if end <= start {
+        return None;
+    }
+    Some(((position - start) as f64 / (end - start) as f64).clamp(0.0, 1.0) as f32)
+}
+
+// ============== Auto-Hide ==============
+
+/// Whether `mode` wants the transport icons hidden for a reported status.
+pub fn hides_transport(mode: AutoHide, status: Option<PlaybackStatus>) -> bool {
+    match mode {
+        AutoHide::Never => false,
+        AutoHide::NoSession => status.is_none(),
+        AutoHide::Stopped => matches!(status, None | Some(PlaybackStatus::Closed | PlaybackStatus::Stopped)),
+    }
+}
+
+/// Debounces the hide decision. The first observation applies at once, so
+/// the icons start out right instead of changing after startup.
+#[derive(Clone, Debug, Default)]
+pub struct AutoHider {
+    initialized: bool,
+    /// Whether only the note icon is shown
+    pub hidden: bool,
+    changed_at: Option<Instant>,
+}
+
+impl AutoHider {
+    /// Records the wanted state. Returns true when `hidden` flips.
+    pub fn observe(&mut self, now: Instant, hide: bool) -> bool {
+        if !self.initialized {
+            self.initialized = true;
+            self.hidden = hide;
+            return hide;
+        }
+
+        if hide == self.hidden {
+            self.changed_at = None;
+            return false;
+        }
+        let changed_at = *self.changed_at.get_or_insert(now);
+        if now.duration_since(changed_at) < AUTO_HIDE_SETTLE {
+            return false;
+        }
+        self.hidden = hide;
+        self.changed_at = None;
+        true
+    }
+}
+
+// ============== Wheel Input ==============
+
+/// Turns raw wheel deltas into discrete steps. Deltas add up until they reach
+/// `step`, so high-resolution wheels and touchpads produce one action per
+/// notch, and at most one action fires per cooldown.
+#[derive(Clone, Debug, Default)]
+pub struct WheelAccumulator {
+    icon: Option<TrayIcon>,
+    total: i32,
+    last_input: Option<Instant>,
+    last_fired: Option<Instant>,
+}
+
+impl WheelAccumulator {
+    /// Adds a delta. Returns the gesture to run once a full step is reached.
+    pub fn feed(&mut self, now: Instant, icon: TrayIcon, delta: i32, step: i32, cooldown: Duration) -> Option<Gesture> {
+        let stale = self.last_input.is_none_or(|at| now.duration_since(at) >= WHEEL_IDLE_RESET);
+        let reversed = self.total.signum() * delta.signum() < 0;
+        if stale || reversed || self.icon != Some(icon) {
+            self.total = 0;
+        }
+        self.icon = Some(icon);
+        self.last_input = Some(now);
+        self.total += delta;
+
+        let step = step.max(1);
+        if self.total.abs() < step {
+            return None;
+        }
+        let up = self.total > 0;
+        self.total %= step;
+
+        if self.last_fired.is_some_and(|at| now.duration_since(at) < cooldown) {
+            return None;
+        }
+        self.last_fired = Some(now);
+        Some(if up { Gesture::WheelUp } else { Gesture::WheelDown })
+    }
+}
+
+// ============== Track Notifications ==============
+
+/// Debounces track changes into notifications.
+#[derive(Clone, Debug, Default)]
+pub struct TrackNotifier {
+    initialized: bool,
+    current: Option<TrackInfo>,
+    changed_at: Option<Instant>,
+    last_shown: Option<Instant>,
+    suppress_until: Option<Instant>,
+}
+
+impl TrackNotifier {
+    /// Forgets the current track; the next observation becomes the baseline.
+    pub fn reset(&mut self) {
+        self.initialized = false;
+        self.changed_at = None;
+    }
+
+    /// Track changes starting before `now + NOTIFY_CLICK_SUPPRESS` were caused by us.
+    pub fn suppress(&mut self, now: Instant) {
+        self.suppress_until = Some(now + NOTIFY_CLICK_SUPPRESS);
+    }
+
+    pub fn current_app(&self) -> Option<String> {
+        self.current.as_ref().map(|t| t.app_id.clone())
+    }
+
+    /// Records the latest track. Returns the track to announce, if any.
+    pub fn observe(&mut self, now: Instant, track: Option<TrackInfo>, muted: &[String]) -> Option<TrackInfo> {
+        if !self.initialized {
+            self.initialized = true;
+            self.current = track;
+            return None;
+        }
+
+        if track != self.current {
+            self.changed_at = track.as_ref().map(|_| now);
+            self.current = track;
+        }
+
+        let changed_at = self.changed_at?;
+        if now.duration_since(changed_at) < NOTIFY_SETTLE {
+            return None;
+        }
+        self.changed_at = None;
+
+        let track = self.current.clone()?;
+        if track.title.is_empty() || is_app_muted(&track.app_id, muted) {
+            return None;
+        }
+        if self.suppress_until.is_some_and(|until| changed_at < until) {
+            return None;
+        }
+        if self.last_shown.is_some_and(|shown| now.duration_since(shown) < NOTIFY_MIN_INTERVAL) {
+            return None;
+        }
+
+        self.last_shown = Some(now);
+        Some(track)
+    }
+}
+
+pub fn is_app_muted(app_id: &str, muted: &[String]) -> bool {
+    muted.iter().any(|m| app_id.eq_ignore_ascii_case(m))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bindings::Binding;
+    use crate::output::DeviceClass;
+
+    /// Runs one event through `update`, keeping the state in place.
+    fn send(state: &mut State, event: Event) -> Vec<Effect> {
+        let (next, effects) = update(std::mem::take(state), event);
+        *state = next;
+        effects
+    }
+
+    /// Fake clock: `at(ms)` is that many milliseconds after a fixed start.
+    fn clock() -> impl Fn(u64) -> Instant {
+        let start = Instant::now();
+        move |ms| start + Duration::from_millis(ms)
+    }
+
+    fn with_play_icon() -> State {
+        State::new(Settings { show_play: true, ..Settings::default() })
+    }
+
+    fn click(now: Instant, icon: TrayIcon, gesture: Gesture) -> Event {
+        Event::Mouse { now, icon, gesture }
+    }
+
+    fn status(now: Instant, status: PlaybackStatus) -> Event {
+        Event::Status { now, status: Some(status) }
+    }
+
+    fn paused(app_id: &str, title: &str) -> PausedSession {
+        PausedSession { app_id: app_id.to_string(), title: title.to_string() }
+    }
+
+    fn bind_double(state: &mut State, icon: TrayIcon, action: Action) {
+        state.settings.bindings.push(Binding { icon: Some(icon), gesture: Gesture::Double, action });
+    }
+
+    #[test]
+    fn init_queries_the_session_and_builds_the_icons() {
+        let mut state = with_play_icon();
+        let effects = send(&mut state, Event::Init { system_dark: true, system_language: Language::German });
+        assert_eq!(effects, vec![Effect::QueryStatus, Effect::QueryModes, Effect::RebuildIcons]);
+        assert_eq!(state.language, Language::German);
+        // Light icons on a dark taskbar
+        assert!(!state.settings.dark_icons);
+    }
+
+    #[test]
+    fn system_theme_change_rebuilds_the_icons_without_saving() {
+        let mut state = with_play_icon();
+        send(&mut state, Event::Init { system_dark: true, system_language: Language::English });
+        assert_eq!(send(&mut state, Event::SystemTheme { dark: false }), vec![Effect::RebuildIcons]);
+        assert!(state.settings.dark_icons);
+        assert_eq!(send(&mut state, Event::SystemTheme { dark: false }), vec![]);
+
+        // A fixed theme ignores the system
+        state.settings.icon_theme = IconTheme::Light;
+        send(&mut state, Event::SystemTheme { dark: true });
+        assert_eq!(send(&mut state, Event::SystemTheme { dark: false }), vec![]);
+    }
+
+    #[test]
+    fn every_fourth_tick_checks_the_theme() {
+        let mut state = with_play_icon();
+        for _ in 0..3 {
+            assert!(!send(&mut state, Event::Tick).contains(&Effect::QueryTheme));
+        }
+        let effects = send(&mut state, Event::Tick);
+        assert_eq!(effects, vec![Effect::QueryStatus, Effect::QueryTrack, Effect::QueryModes, Effect::QueryTheme]);
+    }
+
+    #[test]
+    fn single_click_runs_at_once_without_a_double_click_binding() {
+        let at = clock();
+        let mut state = with_play_icon();
+        let effects = send(&mut state, click(at(0), TrayIcon::Play, Gesture::Left));
+        assert_eq!(effects, vec![Effect::SendKey(MediaKey::PlayPause), Effect::RedrawIcon(TrayIcon::Play)]);
+        assert!(state.playing);
+        // The system's double-click message adds nothing
+        assert_eq!(send(&mut state, click(at(200), TrayIcon::Play, Gesture::Double)), vec![]);
+    }
+
+    #[test]
+    fn single_click_waits_for_the_double_click_time() {
+        let at = clock();
+        let mut state = with_play_icon();
+        bind_double(&mut state, TrayIcon::Play, Action::Next);
+
+        assert_eq!(send(&mut state, click(at(0), TrayIcon::Play, Gesture::Left)), vec![Effect::StartTimer(Timer::Click)]);
+        assert!(!state.playing);
+        let effects = send(&mut state, Event::ClickTimeout { now: at(500) });
+        assert_eq!(
+            effects,
+            vec![
+                Effect::StopTimer(Timer::Click),
+                Effect::SendKey(MediaKey::PlayPause),
+                Effect::RedrawIcon(TrayIcon::Play),
+            ]
+        );
+        assert!(state.playing);
+    }
+
+    #[test]
+    fn double_click_replaces_the_single_click() {
+        let at = clock();
+        let mut state = with_play_icon();
+        bind_double(&mut state, TrayIcon::Play, Action::Next);
+
+        // Button-up, double-click, and the double-click's own button-up
+        assert_eq!(send(&mut state, click(at(0), TrayIcon::Play, Gesture::Left)), vec![Effect::StartTimer(Timer::Click)]);
+        assert_eq!(
+            send(&mut state, click(at(150), TrayIcon::Play, Gesture::Double)),
+            vec![Effect::StopTimer(Timer::Click), Effect::SendKey(MediaKey::Next)]
+        );
+        assert_eq!(send(&mut state, click(at(200), TrayIcon::Play, Gesture::Left)), vec![]);
+        // A timeout already queued when the timer stopped does nothing
+        assert_eq!(send(&mut state, Event::ClickTimeout { now: at(500) }), vec![Effect::StopTimer(Timer::Click)]);
+        assert!(!state.playing);
+
+        // The next click is an ordinary one again
+        assert_eq!(send(&mut state, click(at(2000), TrayIcon::Play, Gesture::Left)), vec![Effect::StartTimer(Timer::Click)]);
+    }
+
+    #[test]
+    fn a_modified_button_up_ends_the_double_click() {
+        let at = clock();
+        let mut state = with_play_icon();
+        bind_double(&mut state, TrayIcon::Play, Action::Next);
+
+        send(&mut state, click(at(0), TrayIcon::Play, Gesture::Left));
+        send(&mut state, click(at(150), TrayIcon::Play, Gesture::Double));
+        // Ctrl went down before the second button-up
+        assert_eq!(send(&mut state, click(at(200), TrayIcon::Play, Gesture::CtrlLeft)), vec![]);
+        // So the next plain click isn't swallowed
+        assert_eq!(send(&mut state, click(at(2000), TrayIcon::Play, Gesture::Left)), vec![Effect::StartTimer(Timer::Click)]);
+    }
+
+    #[test]
+    fn clicking_another_icon_settles_the_waiting_click() {
+        let at = clock();
+        let mut state = with_play_icon();
+        bind_double(&mut state, TrayIcon::Play, Action::Next);
+
+        send(&mut state, click(at(0), TrayIcon::Play, Gesture::Left));
+        let effects = send(&mut state, click(at(100), TrayIcon::Next, Gesture::Left));
+        assert_eq!(
+            effects,
+            vec![
+                Effect::StopTimer(Timer::Click),
+                Effect::SendKey(MediaKey::PlayPause),
+                Effect::RedrawIcon(TrayIcon::Play),
+                Effect::SendKey(MediaKey::Next),
+            ]
+        );
+    }
+
+    #[test]
+    fn crossfade_runs_the_frame_timer_until_it_ends() {
+        let at = clock();
+        let mut state = with_play_icon();
+        state.settings.animations = true;
+        let effects = send(&mut state, click(at(0), TrayIcon::Play, Gesture::Left));
+        assert_eq!(
+            effects,
+            vec![
+                Effect::SendKey(MediaKey::PlayPause),
+                Effect::RedrawIcon(TrayIcon::Play),
+                Effect::StartTimer(Timer::Animation),
+            ]
+        );
+        assert_eq!(state.fade, Some(Fade { from_playing: false, start: at(0) }));
+
+        assert_eq!(send(&mut state, Event::AnimationFrame { now: at(100) }), vec![Effect::RedrawIcon(TrayIcon::Play)]);
+        let effects = send(&mut state, Event::AnimationFrame { now: at(200) });
+        assert_eq!(effects, vec![Effect::RedrawIcon(TrayIcon::Play), Effect::StopTimer(Timer::Animation)]);
+        assert_eq!(state.fade, None);
+    }
+
+    #[test]
+    fn unconfirmed_click_rolls_back_with_a_cue() {
+        let at = clock();
+        let mut state = with_play_icon();
+        send(&mut state, click(at(0), TrayIcon::Play, Gesture::Left));
+        assert!(state.playing);
+
+        // The session hasn't caught up yet; the clicked state stays
+        assert_eq!(send(&mut state, status(at(500), PlaybackStatus::Paused)), vec![]);
+        assert!(state.playing);
+
+        let effects = send(&mut state, status(at(1500), PlaybackStatus::Paused));
+        assert_eq!(effects, vec![Effect::RedrawIcon(TrayIcon::Play), Effect::StartTimer(Timer::Animation)]);
+        assert!(!state.playing);
+        assert_eq!(state.pending_play, None);
+        assert_eq!(state.rollback_cue, Some(at(1500)));
+
+        // The cue blinks until its time is up, then the frame timer stops
+        send(&mut state, Event::AnimationFrame { now: at(2000) });
+        assert!(state.rollback_cue.is_some());
+        let effects = send(&mut state, Event::AnimationFrame { now: at(2400) });
+        assert_eq!(effects, vec![Effect::RedrawIcon(TrayIcon::Play), Effect::StopTimer(Timer::Animation)]);
+        assert_eq!(state.rollback_cue, None);
+    }
+
+    fn pending(state: &mut State, target: bool, deadline: Instant) {
+        state.playing = target;
+        state.pending_play = Some(PendingPlay { target, deadline });
+    }
+
+    #[test]
+    fn pending_play_is_confirmed_by_the_session() {
+        let at = clock();
+        let mut state = with_play_icon();
+        pending(&mut state, true, at(1500));
+
+        assert!(!reconcile_play(&mut state, false, at(300)));
+        assert!(state.pending_play.is_some());
+        assert!(reconcile_play(&mut state, true, at(600)));
+        assert_eq!(state.pending_play, None);
+        assert_eq!(state.rollback_cue, None);
+    }
+
+    #[test]
+    fn pending_play_rolls_back_at_the_deadline() {
+        let at = clock();
+        let mut state = with_play_icon();
+        pending(&mut state, true, at(1500));
+
+        assert!(!reconcile_play(&mut state, false, at(1499)));
+        assert!(reconcile_play(&mut state, false, at(1500)));
+        assert_eq!(state.pending_play, None);
+        assert_eq!(state.rollback_cue, Some(at(1500)));
+    }
+
+    #[test]
+    fn late_confirmation_after_rollback_is_shown() {
+        let at = clock();
+        let mut state = with_play_icon();
+        send(&mut state, click(at(0), TrayIcon::Play, Gesture::Left));
+        send(&mut state, status(at(1500), PlaybackStatus::Paused));
+        assert!(!state.playing);
+
+        // The player got there after all; with nothing pending, it is simply believed
+        let effects = send(&mut state, status(at(2000), PlaybackStatus::Playing));
+        assert_eq!(effects, vec![Effect::RedrawIcon(TrayIcon::Play)]);
+        assert!(state.playing);
+        assert_eq!(state.pending_play, None);
+    }
+
+    #[test]
+    fn rapid_clicks_wait_for_the_last_target() {
+        let at = clock();
+        let mut state = with_play_icon();
+        send(&mut state, click(at(0), TrayIcon::Play, Gesture::Left));
+        send(&mut state, click(at(100), TrayIcon::Play, Gesture::Left));
+        assert!(!state.playing);
+        assert_eq!(state.pending_play, Some(PendingPlay { target: false, deadline: at(1600) }));
+
+        // A report of the first click's state is stale, not a confirmation
+        assert_eq!(send(&mut state, status(at(300), PlaybackStatus::Playing)), vec![]);
+        assert!(!state.playing);
+        assert_eq!(send(&mut state, status(at(800), PlaybackStatus::Paused)), vec![]);
+        assert_eq!(state.pending_play, None);
+        assert_eq!(state.rollback_cue, None);
+    }
+
+    fn track(app_id: &str, title: &str) -> Option<TrackInfo> {
+        Some(TrackInfo { app_id: app_id.to_string(), title: title.to_string(), artist: "Artist".to_string() })
+    }
+
+    /// Observes `title` from Spotify at each of `times`, returning what was announced.
+    fn observe_at(notifier: &mut TrackNotifier, at: &impl Fn(u64) -> Instant, title: &str, times: &[u64]) -> Vec<String> {
+        times
+            .iter()
+            .filter_map(|&ms| notifier.observe(at(ms), track("Spotify.exe", title), &[]))
+            .map(|track| track.title)
+            .collect()
+    }
+
+    #[test]
+    fn notifier_starts_from_the_current_track() {
+        let at = clock();
+        let mut notifier = TrackNotifier::default();
+        assert_eq!(observe_at(&mut notifier, &at, "A", &[0, 500, 5000]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn notifier_announces_a_change_once_it_settled() {
+        let at = clock();
+        let mut notifier = TrackNotifier::default();
+        observe_at(&mut notifier, &at, "A", &[0]);
+        assert!(observe_at(&mut notifier, &at, "B", &[1000, 2000, 2499]).is_empty());
+        assert_eq!(observe_at(&mut notifier, &at, "B", &[2500, 3000, 10_000]), vec!["B"]);
+    }
+
+    #[test]
+    fn notifier_only_announces_the_last_of_rapid_changes() {
+        let at = clock();
+        let mut notifier = TrackNotifier::default();
+        observe_at(&mut notifier, &at, "A", &[0]);
+        assert!(observe_at(&mut notifier, &at, "B", &[1000, 2000]).is_empty());
+        assert!(observe_at(&mut notifier, &at, "C", &[2500, 3500]).is_empty());
+        assert_eq!(observe_at(&mut notifier, &at, "D", &[3600, 4500, 5100]), vec!["D"]);
+    }
+
+    #[test]
+    fn notifier_drops_changes_within_the_minimum_interval() {
+        let at = clock();
+        let mut notifier = TrackNotifier::default();
+        observe_at(&mut notifier, &at, "A", &[0]);
+        assert_eq!(observe_at(&mut notifier, &at, "B", &[1000, 2500]), vec!["B"]);
+        // Settles 4.5s after B was shown
+        assert!(observe_at(&mut notifier, &at, "C", &[3000, 4500, 7000]).is_empty());
+        assert_eq!(observe_at(&mut notifier, &at, "D", &[8000, 9500]), vec!["D"]);
+    }
+
+    #[test]
+    fn notifier_skips_changes_caused_by_a_click() {
+        let at = clock();
+        let mut notifier = TrackNotifier::default();
+        observe_at(&mut notifier, &at, "A", &[0]);
+        notifier.suppress(at(500));
+        assert!(observe_at(&mut notifier, &at, "B", &[3400, 5000]).is_empty());
+        // Started after the suppression ran out
+        assert_eq!(observe_at(&mut notifier, &at, "C", &[3500, 5000]), vec!["C"]);
+    }
+
+    #[test]
+    fn notifier_skips_muted_apps_and_untitled_tracks() {
+        let at = clock();
+        let mut notifier = TrackNotifier::default();
+        let muted = vec!["spotify.exe".to_string()];
+        notifier.observe(at(0), track("Spotify.exe", "A"), &muted);
+        notifier.observe(at(1000), track("Spotify.exe", "B"), &muted);
+        assert_eq!(notifier.observe(at(2500), track("Spotify.exe", "B"), &muted), None);
+
+        notifier.observe(at(10_000), track("chrome.exe", ""), &muted);
+        assert_eq!(notifier.observe(at(11_500), track("chrome.exe", ""), &muted), None);
+        assert_eq!(notifier.current_app().as_deref(), Some("chrome.exe"));
+    }
+
+    #[test]
+    fn notifier_reset_takes_a_new_baseline() {
+        let at = clock();
+        let mut notifier = TrackNotifier::default();
+        observe_at(&mut notifier, &at, "A", &[0]);
+        observe_at(&mut notifier, &at, "B", &[1000]);
+        notifier.reset();
+        assert!(observe_at(&mut notifier, &at, "B", &[2000, 4000]).is_empty());
+    }
+
+    #[test]
+    fn track_events_turn_into_a_balloon() {
+        let at = clock();
+        let mut state = with_play_icon();
+        state.settings.notify_track = true;
+        let mut track_at = |ms, title| send(&mut state, Event::Track { now: at(ms), track: track("Spotify.exe", title) });
+        assert_eq!(track_at(0, "A"), vec![]);
+        assert_eq!(track_at(10_000, "B"), vec![]);
+        // The balloon waits for the new track's art
+        assert_eq!(track_at(11_500, "B"), vec![Effect::QueryArt]);
+        let art = AlbumArt { width: 1, height: 1, pixels: vec![0xFF102030] };
+        assert_eq!(
+            send(&mut state, Event::Art(Some(art.clone()))),
+            vec![Effect::ShowBalloon { title: "B".to_string(), text: "Artist".to_string(), art: Some(art) }]
+        );
+
+        // Skipping from the tray isn't announced
+        send(&mut state, click(at(20_000), TrayIcon::Next, Gesture::Left));
+        assert_eq!(send(&mut state, Event::Track { now: at(20_500), track: track("Spotify.exe", "C") }), vec![]);
+        assert_eq!(send(&mut state, Event::Track { now: at(22_000), track: track("Spotify.exe", "C") }), vec![]);
+    }
+
+    #[test]
+    fn actions_that_keep_the_track_dont_hide_its_balloon() {
+        let at = clock();
+        let mut state = with_play_icon();
+        state.settings.notify_track = true;
+        state.settings.bindings = vec![Binding::parse("bind.play.middle", "mute").unwrap()];
+        send(&mut state, Event::Track { now: at(0), track: track("Spotify.exe", "A") });
+        send(&mut state, click(at(9_000), TrayIcon::Play, Gesture::Middle));
+        send(&mut state, Event::Track { now: at(10_000), track: track("Spotify.exe", "B") });
+        assert_eq!(send(&mut state, Event::Track { now: at(11_500), track: track("Spotify.exe", "B") }), vec![Effect::QueryArt]);
+    }
+
+    #[test]
+    fn balloon_without_art_shows_at_the_next_tick() {
+        let at = clock();
+        let mut state = with_play_icon();
+        state.settings.notify_track = true;
+        send(&mut state, Event::Track { now: at(0), track: track("Spotify.exe", "A") });
+        send(&mut state, Event::Track { now: at(10_000), track: track("Spotify.exe", "B") });
+        assert_eq!(send(&mut state, Event::Track { now: at(11_500), track: track("Spotify.exe", "B") }), vec![Effect::QueryArt]);
+
+        let balloon = Effect::ShowBalloon { title: "B".to_string(), text: "Artist".to_string(), art: None };
+        assert_eq!(send(&mut state, Event::Tick).first(), Some(&balloon));
+        // Shown once; late art only reaches the flyout
+        assert!(!send(&mut state, Event::Tick).contains(&balloon));
+        assert_eq!(send(&mut state, Event::Art(None)), vec![]);
+    }
+
+    /// Feeds (ms, icon, delta) wheel input with a 120 step and 150ms cooldown.
+    fn wheel(input: &[(u64, TrayIcon, i32)]) -> Vec<Option<Gesture>> {
+        let at = clock();
+        let mut wheel = WheelAccumulator::default();
+        let cooldown = Duration::from_millis(150);
+        input.iter().map(|&(ms, icon, delta)| wheel.feed(at(ms), icon, delta, 120, cooldown)).collect()
+    }
+
+    #[test]
+    fn wheel_partial_deltas_add_up_to_a_notch() {
+        use TrayIcon::Play;
+        assert_eq!(wheel(&[(0, Play, 40), (10, Play, 40), (20, Play, 40)]), vec![None, None, Some(Gesture::WheelUp)]);
+        assert_eq!(wheel(&[(0, Play, -90), (10, Play, -30)]), vec![None, Some(Gesture::WheelDown)]);
+        // The remainder carries over into the next notch
+        assert_eq!(
+            wheel(&[(0, Play, 100), (10, Play, 100), (300, Play, 100)]),
+            vec![None, Some(Gesture::WheelUp), Some(Gesture::WheelUp)]
+        );
+    }
+
+    #[test]
+    fn wheel_cooldown_drops_extra_notches() {
+        use TrayIcon::Play;
+        assert_eq!(
+            wheel(&[(0, Play, 120), (50, Play, 120), (149, Play, 120), (150, Play, 120)]),
+            vec![Some(Gesture::WheelUp), None, None, Some(Gesture::WheelUp)]
+        );
+        // A fast flick reporting several notches at once is still one action
+        assert_eq!(wheel(&[(0, Play, 480), (160, Play, 40)]), vec![Some(Gesture::WheelUp), None]);
+    }
+
+    #[test]
+    fn wheel_direction_flip_starts_over() {
+        use TrayIcon::Play;
+        assert_eq!(
+            wheel(&[(0, Play, 80), (10, Play, -40), (20, Play, -80)]),
+            vec![None, None, Some(Gesture::WheelDown)]
+        );
+        assert_eq!(wheel(&[(0, Play, -100), (10, Play, 100)]), vec![None, None]);
+    }
+
+    #[test]
+    fn wheel_partial_delta_expires_or_stays_with_its_icon() {
+        use TrayIcon::{Next, Play};
+        assert_eq!(wheel(&[(0, Play, 80), (500, Play, 40)]), vec![None, None]);
+        assert_eq!(wheel(&[(0, Play, 80), (499, Play, 40)]), vec![None, Some(Gesture::WheelUp)]);
+        assert_eq!(wheel(&[(0, Play, 80), (10, Next, 40), (20, Next, 80)]), vec![None, None, Some(Gesture::WheelUp)]);
+    }
+
+    #[test]
+    fn wheel_over_an_icon_runs_its_binding() {
+        let at = clock();
+        let mut state = with_play_icon();
+        let effects = send(&mut state, Event::Wheel { now: at(0), icon: TrayIcon::Play, delta: -120 });
+        assert_eq!(effects, vec![Effect::SendKey(MediaKey::Prev)]);
+        let effects = send(&mut state, Event::Wheel { now: at(300), icon: TrayIcon::Next, delta: 120 });
+        assert_eq!(effects, vec![Effect::Seek(10)]);
+    }
+
+    #[test]
+    fn auto_hide_modes() {
+        use PlaybackStatus::*;
+        let cases = [
+            (AutoHide::Never, None, false),
+            (AutoHide::Never, Some(Stopped), false),
+            (AutoHide::NoSession, None, true),
+            (AutoHide::NoSession, Some(Closed), false),
+            (AutoHide::NoSession, Some(Stopped), false),
+            (AutoHide::NoSession, Some(Playing), false),
+            (AutoHide::Stopped, None, true),
+            (AutoHide::Stopped, Some(Closed), true),
+            (AutoHide::Stopped, Some(Stopped), true),
+            (AutoHide::Stopped, Some(Opened), false),
+            (AutoHide::Stopped, Some(Changing), false),
+            (AutoHide::Stopped, Some(Paused), false),
+            (AutoHide::Stopped, Some(Playing), false),
+        ];
+        for (mode, status, hides) in cases {
+            assert_eq!(hides_transport(mode, status), hides, "{:?} {:?}", mode, status);
+        }
+    }
+
+    #[test]
+    fn auto_hider_applies_the_first_observation_at_once() {
+        let at = clock();
+        let mut hider = AutoHider::default();
+        assert!(hider.observe(at(0), true));
+        assert!(hider.hidden);
+
+        let mut hider = AutoHider::default();
+        assert!(!hider.observe(at(0), false));
+        assert!(!hider.hidden);
+    }
+
+    #[test]
+    fn auto_hider_waits_for_a_change_to_hold() {
+        let at = clock();
+        let mut hider = AutoHider::default();
+        hider.observe(at(0), false);
+        assert!(!hider.observe(at(500), true));
+        assert!(!hider.observe(at(2499), true));
+        assert!(!hider.hidden);
+        assert!(hider.observe(at(2500), true));
+        assert!(hider.hidden);
+        assert!(!hider.observe(at(3000), true));
+    }
+
+    #[test]
+    fn auto_hider_ignores_flicker() {
+        let at = clock();
+        let mut hider = AutoHider::default();
+        hider.observe(at(0), false);
+        // Player closing and reopening between tracks
+        hider.observe(at(500), true);
+        assert!(!hider.observe(at(1500), false));
+        assert!(!hider.observe(at(2000), true));
+        assert!(!hider.observe(at(3999), true));
+        assert!(hider.observe(at(4000), true));
+    }
+
+    #[test]
+    fn visible_icons_follow_settings_support_and_hiding() {
+        let mut state = State::new(Settings {
+            show_prev: true,
+            show_play: true,
+            show_next: true,
+            show_shuffle: true,
+            show_repeat: true,
+            ..Settings::default()
+        });
+        // Unsupported modes keep their icons out
+        assert_eq!(state.visible_icons(), vec![TrayIcon::Prev, TrayIcon::Play, TrayIcon::Next]);
+        state.modes = ModeState { shuffle: Some(false), repeat: Some(RepeatMode::Off), speed: None };
+        assert_eq!(
+            state.visible_icons(),
+            vec![TrayIcon::Prev, TrayIcon::Play, TrayIcon::Next, TrayIcon::Shuffle, TrayIcon::Repeat]
+        );
+
+        state.auto_hide.hidden = true;
+        assert_eq!(state.visible_icons(), vec![TrayIcon::Default]);
+
+        let state = State::default();
+        assert_eq!(state.visible_icons(), vec![TrayIcon::Default]);
+    }
+
+    #[test]
+    fn resume_paused_resumes_only_what_pause_all_paused() {
+        let at = clock();
+        let mut state = with_play_icon();
+        let menu = |ms, action| Event::Menu { now: at(ms), command: MenuCommand::Run(action) };
+
+        // Nothing remembered yet
+        assert_eq!(send(&mut state, menu(0, Action::ResumePaused)), vec![]);
+
+        assert_eq!(send(&mut state, menu(0, Action::PauseAll)), vec![Effect::PauseAll, Effect::QueryStatus]);
+        send(&mut state, Event::Paused(vec![paused("Spotify.exe", "Song"), paused("Chrome", "Video")]));
+        // A second pause-all before resuming keeps the first set
+        send(&mut state, Event::Paused(vec![paused("Chrome", "Video"), paused("VLC", "Movie")]));
+
+        let sessions = vec![paused("Spotify.exe", "Song"), paused("Chrome", "Video"), paused("VLC", "Movie")];
+        assert_eq!(send(&mut state, menu(1000, Action::ResumePaused)), vec![Effect::Resume(sessions), Effect::QueryStatus]);
+        assert!(state.paused_sessions.is_empty());
+        assert_eq!(send(&mut state, menu(2000, Action::ResumePaused)), vec![]);
+    }
+
+    #[test]
+    fn sessions_of_one_app_are_remembered_apart() {
+        let at = clock();
+        let mut state = with_play_icon();
+        let menu = |ms, action| Event::Menu { now: at(ms), command: MenuCommand::Run(action) };
+
+        // Two tabs playing at once are two sessions; a third the user paused isn't reported
+        send(&mut state, menu(0, Action::PauseAll));
+        send(&mut state, Event::Paused(vec![paused("Chrome", "Video"), paused("Chrome", "Stream")]));
+        let sessions = vec![paused("Chrome", "Video"), paused("Chrome", "Stream")];
+        assert_eq!(send(&mut state, menu(1000, Action::ResumePaused)), vec![Effect::Resume(sessions), Effect::QueryStatus]);
+    }
+
+    #[test]
+    fn sleep_timer_pauses_and_clears_its_badge() {
+        let at = clock();
+        let wall = |ms| SystemTime::UNIX_EPOCH + Duration::from_millis(ms);
+        let mut state = with_play_icon();
+        let arm = Event::Menu { now: at(0), command: MenuCommand::SetSleep(SleepChoice::Minutes(15)) };
+        assert_eq!(send(&mut state, arm), vec![Effect::RedrawIcon(TrayIcon::Play), Effect::StartTimer(Timer::Sleep)]);
+
+        // Redrawn only when the minutes or the badge change
+        assert_eq!(send(&mut state, Event::SleepTick { now: at(1000), wall: wall(1000) }), vec![]);
+        let effects = send(&mut state, Event::SleepTick { now: at(60_000), wall: wall(60_000) });
+        assert_eq!(effects, vec![Effect::RedrawIcon(TrayIcon::Play)]);
+
+        let effects = send(&mut state, Event::SleepTick { now: at(900_000), wall: wall(900_000) });
+        assert_eq!(
+            effects,
+            vec![Effect::Pause, Effect::QueryStatus, Effect::RedrawIcon(TrayIcon::Play), Effect::StopTimer(Timer::Sleep)]
+        );
+    }
+
+    #[test]
+    fn sleep_fade_lowers_the_playing_app() {
+        let at = clock();
+        let wall = |ms| SystemTime::UNIX_EPOCH + Duration::from_millis(ms);
+        let mut state = with_play_icon();
+        state.settings.sleep_fade = true;
+        state.track = track("Spotify", "Song");
+        send(&mut state, Event::Menu { now: at(0), command: MenuCommand::SetSleep(SleepChoice::Minutes(15)) });
+
+        let effects = send(&mut state, Event::SleepTick { now: at(885_000), wall: wall(885_000) });
+        assert!(matches!(&effects[..], [Effect::FadeVolume { app_id, level }, ..] if app_id == "Spotify" && *level < 1.0));
+
+        // Nothing to fade without a media session
+        state.track = None;
+        let effects = send(&mut state, Event::SleepTick { now: at(890_000), wall: wall(890_000) });
+        assert!(!effects.iter().any(|effect| matches!(effect, Effect::FadeVolume { .. })));
+    }
+
+    #[test]
+    fn sleep_badge_goes_on_the_first_icon_without_a_play_icon() {
+        let mut state = State::new(Settings { show_next: true, show_play: true, ..Settings::default() });
+        assert_eq!(state.sleep_badge_icon(), TrayIcon::Play);
+        state.settings.show_play = false;
+        assert_eq!(state.sleep_badge_icon(), TrayIcon::Next);
+        state.auto_hide.hidden = true;
+        assert_eq!(state.sleep_badge_icon(), TrayIcon::Default);
+    }
+
+    #[test]
+    fn icons_come_back_once_a_session_settles() {
+        let at = clock();
+        let mut state = with_play_icon();
+        state.settings.auto_hide = AutoHide::NoSession;
+        assert_eq!(send(&mut state, Event::Status { now: at(0), status: None }), vec![Effect::RebuildIcons]);
+        assert_eq!(state.visible_icons(), vec![TrayIcon::Default]);
+
+        assert!(!send(&mut state, status(at(1000), PlaybackStatus::Paused)).contains(&Effect::RebuildIcons));
+        assert_eq!(send(&mut state, status(at(3000), PlaybackStatus::Paused)), vec![Effect::RebuildIcons]);
+        assert_eq!(state.visible_icons(), vec![TrayIcon::Play]);
+    }
+
+    #[test]
+    fn locking_pauses_only_when_enabled_and_unlocking_resumes() {
+        let at = clock();
+        let locked = |active| Event::Away { reason: Away::Locked, active };
+        let mut state = State::default();
+        send(&mut state, Event::Track { now: at(0), track: track("Spotify.exe", "Song") });
+        send(&mut state, status(at(0), PlaybackStatus::Playing));
+        assert_eq!(send(&mut state, locked(true)), vec![]);
+        assert_eq!(send(&mut state, locked(false)), vec![]);
+
+        state.settings.pause_on_lock = true;
+        assert_eq!(send(&mut state, locked(true)), vec![Effect::Pause, Effect::QueryStatus]);
+        send(&mut state, status(at(1000), PlaybackStatus::Paused));
+        assert_eq!(
+            send(&mut state, locked(false)),
+            vec![Effect::Resume(vec![paused("Spotify.exe", "Song")]), Effect::QueryStatus]
+        );
+    }
+
+    #[test]
+    fn toggling_lock_pausing_keeps_a_pause_from_another_reason() {
+        let at = clock();
+        let idle = |secs| Event::Idle(IdleSample { idle: Duration::from_secs(secs), fullscreen: false });
+        let mut state = State::new(Settings { pause_on_idle: true, idle_minutes: 5, ..Settings::default() });
+        send(&mut state, Event::Track { now: at(0), track: track("Spotify.exe", "Song") });
+        send(&mut state, status(at(0), PlaybackStatus::Playing));
+        assert_eq!(send(&mut state, idle(300)), vec![Effect::Pause, Effect::QueryStatus]);
+        send(&mut state, status(at(1000), PlaybackStatus::Paused));
+
+        send(&mut state, Event::Menu { now: at(2000), command: MenuCommand::TogglePauseOnLock });
+        assert_eq!(
+            send(&mut state, idle(0)),
+            vec![Effect::Resume(vec![paused("Spotify.exe", "Song")]), Effect::QueryStatus]
+        );
+    }
+
+    #[test]
+    fn idle_pauses_and_input_resumes() {
+        let at = clock();
+        let idle = |secs, fullscreen| Event::Idle(IdleSample { idle: Duration::from_secs(secs), fullscreen });
+        let mut state = State::new(Settings { pause_on_idle: true, idle_minutes: 5, ..Settings::default() });
+        assert_eq!(send(&mut state, Event::Tick).last(), Some(&Effect::QueryIdle));
+        send(&mut state, Event::Track { now: at(0), track: track("Spotify.exe", "Song") });
+        send(&mut state, status(at(0), PlaybackStatus::Playing));
+
+        assert_eq!(send(&mut state, idle(299, false)), vec![]);
+        assert_eq!(send(&mut state, idle(300, false)), vec![Effect::Pause, Effect::QueryStatus]);
+        send(&mut state, status(at(1000), PlaybackStatus::Paused));
+        assert_eq!(send(&mut state, idle(400, false)), vec![]);
+        assert_eq!(
+            send(&mut state, idle(0, false)),
+            vec![Effect::Resume(vec![paused("Spotify.exe", "Song")]), Effect::QueryStatus]
+        );
+    }
+
+    #[test]
+    fn toggling_idle_pausing_keeps_a_pause_from_another_reason() {
+        let at = clock();
+        let locked = |active| Event::Away { reason: Away::Locked, active };
+        let mut state = State::new(Settings { pause_on_lock: true, ..Settings::default() });
+        send(&mut state, Event::Track { now: at(0), track: track("Spotify.exe", "Song") });
+        send(&mut state, status(at(0), PlaybackStatus::Playing));
+        assert_eq!(send(&mut state, locked(true)), vec![Effect::Pause, Effect::QueryStatus]);
+        send(&mut state, status(at(1000), PlaybackStatus::Paused));
+
+        send(&mut state, Event::Menu { now: at(2000), command: MenuCommand::TogglePauseOnIdle });
+        assert_eq!(
+            send(&mut state, locked(false)),
+            vec![Effect::Resume(vec![paused("Spotify.exe", "Song")]), Effect::QueryStatus]
+        );
+    }
+
+    #[test]
+    fn calls_pause_until_the_microphone_is_released() {
+        let at = clock();
+        let mic = |last_stop| {
+            Event::Capture(vec![CaptureUse { key: "C:#Zoom#Zoom.exe".to_string(), last_start: 1, last_stop }])
+        };
+        let mut state = State::new(Settings {
+            pause_on_call: true,
+            call_ignore: vec!["Voice*".to_string()],
+            ..Settings::default()
+        });
+        assert_eq!(send(&mut state, Event::Tick).last(), Some(&Effect::QueryCapture));
+        send(&mut state, Event::Track { now: at(0), track: track("Spotify.exe", "Song") });
+        send(&mut state, status(at(0), PlaybackStatus::Playing));
+
+        assert_eq!(send(&mut state, mic(0)), vec![Effect::Pause, Effect::QueryStatus]);
+        send(&mut state, status(at(1000), PlaybackStatus::Paused));
+        assert_eq!(send(&mut state, mic(0)), vec![]);
+        assert_eq!(
+            send(&mut state, mic(2)),
+            vec![Effect::Resume(vec![paused("Spotify.exe", "Song")]), Effect::QueryStatus]
+        );
+
+        // Ignored apps don't count
+        send(&mut state, status(at(2000), PlaybackStatus::Playing));
+        let voice = CaptureUse { key: "C:#Windows#VoiceAccess.exe".to_string(), last_start: 1, last_stop: 0 };
+        assert_eq!(send(&mut state, Event::Capture(vec![voice])), vec![]);
+    }
+
+    #[test]
+    fn toggling_call_pausing_keeps_a_pause_from_another_reason() {
+        let at = clock();
+        let locked = |active| Event::Away { reason: Away::Locked, active };
+        let mut state = State::new(Settings { pause_on_lock: true, pause_on_call: true, ..Settings::default() });
+        send(&mut state, Event::Track { now: at(0), track: track("Spotify.exe", "Song") });
+        send(&mut state, status(at(0), PlaybackStatus::Playing));
+        assert_eq!(send(&mut state, locked(true)), vec![Effect::Pause, Effect::QueryStatus]);
+        send(&mut state, status(at(1000), PlaybackStatus::Paused));
+
+        send(&mut state, Event::Menu { now: at(2000), command: MenuCommand::TogglePauseOnCall });
+        assert_eq!(
+            send(&mut state, locked(false)),
+            vec![Effect::Resume(vec![paused("Spotify.exe", "Song")]), Effect::QueryStatus]
+        );
+    }
+
+    /// Headphones, speakers and a monitor, with `default` as the default.
+    fn outputs(default: &str) -> Event {
+        let devices = [
+            ("buds", DeviceClass::Headphones),
+            ("speakers", DeviceClass::Speakers),
+            ("monitor", DeviceClass::Display),
+        ];
+        let device = |(id, class): (&str, DeviceClass)| {
+            OutputDevice { id: id.to_string(), name: id.to_string(), class, default: id == default }
+        };
+        Event::Outputs(devices.into_iter().map(device).collect())
+    }
+
+    #[test]
+    fn toggling_unplug_pausing_keeps_a_pause_from_another_reason() {
+        let at = clock();
+        let locked = |active| Event::Away { reason: Away::Locked, active };
+        let mut state = State::new(Settings { pause_on_lock: true, pause_on_unplug: true, ..Settings::default() });
+        send(&mut state, Event::Track { now: at(0), track: track("Spotify.exe", "Song") });
+        send(&mut state, status(at(0), PlaybackStatus::Playing));
+        assert_eq!(send(&mut state, locked(true)), vec![Effect::Pause, Effect::QueryStatus]);
+        send(&mut state, status(at(1000), PlaybackStatus::Paused));
+
+        send(&mut state, Event::Menu { now: at(2000), command: MenuCommand::TogglePauseOnUnplug });
+        assert_eq!(
+            send(&mut state, locked(false)),
+            vec![Effect::Resume(vec![paused("Spotify.exe", "Song")]), Effect::QueryStatus]
+        );
+    }
+
+    #[test]
+    fn unplugging_headphones_pauses_and_reconnecting_resumes_if_asked() {
+        let at = clock();
+        let mut state = State::new(Settings { pause_on_unplug: true, ..Settings::default() });
+        send(&mut state, Event::Track { now: at(0), track: track("Spotify.exe", "Song") });
+        send(&mut state, status(at(0), PlaybackStatus::Playing));
+        send(&mut state, outputs("buds"));
+
+        assert_eq!(send(&mut state, outputs("speakers")), vec![Effect::Pause, Effect::QueryStatus]);
+        send(&mut state, status(at(1000), PlaybackStatus::Paused));
+        assert_eq!(send(&mut state, outputs("buds")), vec![]);
+
+        state.settings.resume_on_reconnect = true;
+        send(&mut state, status(at(2000), PlaybackStatus::Playing));
+        send(&mut state, outputs("speakers"));
+        send(&mut state, status(at(3000), PlaybackStatus::Paused));
+        assert_eq!(
+            send(&mut state, outputs("buds")),
+            vec![Effect::Resume(vec![paused("Spotify.exe", "Song")]), Effect::QueryStatus]
+        );
+    }
+
+    #[test]
+    fn output_menu_and_icon_switch_the_default() {
+        let at = clock();
+        let mut state = State::new(Settings { show_output: true, ..Settings::default() });
+        assert_eq!(state.visible_icons(), vec![TrayIcon::Default]);
+        assert_eq!(send(&mut state, outputs("buds")), vec![Effect::RebuildIcons]);
+        assert_eq!(state.visible_icons(), vec![TrayIcon::Output]);
+
+        let menu = |index| Event::Menu { now: at(0), command: MenuCommand::SetOutput(index) };
+        assert_eq!(send(&mut state, menu(2)), vec![Effect::SetOutput("monitor".to_string())]);
+        // Picking the default again, or a device that has gone, does nothing
+        assert_eq!(send(&mut state, menu(0)), vec![]);
+        assert_eq!(send(&mut state, menu(3)), vec![]);
+
+        let click = Event::Mouse { now: at(0), icon: TrayIcon::Output, gesture: Gesture::Left };
+        assert_eq!(send(&mut state, click), vec![Effect::SetOutput("speakers".to_string())]);
+        assert_eq!(send(&mut state, outputs("speakers")), vec![Effect::RedrawIcon(TrayIcon::Output)]);
+        assert_eq!(send(&mut state, outputs("speakers")), vec![]);
+        assert_eq!(send(&mut state, Event::Outputs(Vec::new())), vec![Effect::RebuildIcons]);
+    }
+
+    #[test]
+    fn mixer_is_read_before_the_menu_and_steps_by_app() {
+        let at = clock();
+        let mut state = State::default();
+        let right = Event::Mouse { now: at(0), icon: TrayIcon::Default, gesture: Gesture::Right };
+        assert_eq!(send(&mut state, right), vec![Effect::QueryMixer, Effect::ShowMenu(TrayIcon::Default)]);
+
+        send(&mut state, Event::Track { now: at(0), track: track("Spotify.exe", "Song") });
+        let session = |process: &str| AudioSession {
+            id: String::new(),
+            process: process.to_string(),
+            name: String::new(),
+            volume: 0.5,
+            muted: false,
+        };
+        send(&mut state, Event::Mixer(vec![session("chrome.exe"), session("Spotify.exe"), session("chrome.exe")]));
+        let processes: Vec<&str> = state.mixer.iter().map(|app| app.process.as_str()).collect();
+        assert_eq!(processes, ["Spotify.exe", "chrome.exe"]);
+
+        let menu = |index, change| Event::Menu { now: at(0), command: MenuCommand::AppVolume(index, change) };
+        assert_eq!(
+            send(&mut state, menu(1, VolumeChange::Down)),
+            vec![Effect::AppVolume { process: "chrome.exe".to_string(), change: VolumeChange::Down }]
+        );
+        assert_eq!(send(&mut state, menu(2, VolumeChange::ToggleMute)), vec![]);
+    }
+
+    #[test]
+    fn speed_steps_through_the_presets() {
+        assert_eq!(step_speed(1.0, true), Some(1.25));
+        assert_eq!(step_speed(1.25, false), Some(1.0));
+        assert_eq!(step_speed(2.0, true), None);
+        assert_eq!(step_speed(1.0, false), None);
+        // Rates between presets step to the nearest one in that direction
+        assert_eq!(step_speed(1.1, true), Some(1.25));
+        assert_eq!(step_speed(1.1, false), Some(1.0));
+        assert_eq!(step_speed(0.5, true), Some(1.0));
+        assert_eq!(step_speed(3.0, false), Some(2.0));
+        assert_eq!(SPEED_PRESETS.map(speed_label), ["1x", "1.25x", "1.5x", "2x"]);
+    }
+
+    #[test]
+    fn speed_actions_and_menu_need_a_session_that_supports_them() {
+        let at = clock();
+        let mut state = State::new(Settings { show_speed: true, ..Settings::default() });
+        let click = |gesture| Event::Mouse { now: at(0), icon: TrayIcon::Default, gesture };
+        let bind = |action: &str| Binding::parse("bind.any.middle", action).unwrap();
+        state.settings.bindings = vec![bind("speed_up")];
+        let menu = Event::Menu { now: at(0), command: MenuCommand::SetSpeed(1.5) };
+        assert_eq!(send(&mut state, click(Gesture::Middle)), vec![]);
+        assert_eq!(send(&mut state, menu.clone()), vec![]);
+
+        let modes = ModeState { speed: Some(1.0), ..ModeState::default() };
+        assert_eq!(send(&mut state, Event::Modes(modes)), vec![Effect::RebuildIcons]);
+        assert_eq!(state.visible_icons(), vec![TrayIcon::Speed]);
+        assert_eq!(send(&mut state, click(Gesture::Middle)), vec![Effect::SetSpeed(1.25)]);
+        assert_eq!(send(&mut state, menu), vec![Effect::SetSpeed(1.5)]);
+
+        assert_eq!(send(&mut state, Event::SpeedChanged(2.0)), vec![Effect::RedrawIcon(TrayIcon::Speed)]);
+        assert_eq!(send(&mut state, click(Gesture::Middle)), vec![]);
+        state.settings.bindings = vec![bind("speed_down")];
+        assert_eq!(send(&mut state, click(Gesture::Middle)), vec![Effect::SetSpeed(1.5)]);
+    }
+}
@@ -0,0 +1,330 @@
+// ============== Flyout Layout ==============
+//
+// Geometry of the mini-player flyout. Pure arithmetic on plain rectangles, so
+// the Win32 side only has to paint what this module places.
+
+// Layout at 96 DPI; everything is scaled by `dpi / 96`
+const WIDTH: i32 = 320;
+const HEIGHT: i32 = 156;
+const PADDING: i32 = 12;
+const ART_SIZE: i32 = 72;
+const TEXT_LEFT: i32 = PADDING + ART_SIZE + PADDING;
+const SEEK_TOP: i32 = 98;
+const SEEK_HEIGHT: i32 = 4;
+// Clicks this far above or below the bar still seek
+const SEEK_SLOP: i32 = 8;
+const BUTTON_SIZE: i32 = 32;
+const BUTTON_GAP: i32 = 16;
+const BUTTON_TOP: i32 = 112;
+// Gap between the flyout and the taskbar
+const MARGIN: i32 = 8;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Rect {
+    pub fn new(left: i32, top: i32, right: i32, bottom: i32) -> Rect {
+        Rect { left, top, right, bottom }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> i32 {
+        self.bottom - self.top
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.left && x < self.right && y >= self.top && y < self.bottom
+    }
+
+    fn scaled(left: i32, top: i32, width: i32, height: i32, dpi: u32) -> Rect {
+        Rect::new(scale(left, dpi), scale(top, dpi), scale(left + width, dpi), scale(top + height, dpi))
+    }
+}
+
+fn scale(value: i32, dpi: u32) -> i32 {
+    (value as i64 * dpi as i64 / 96) as i32
+}
+
+/// Screen edge the taskbar sits on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    Bottom,
+    Top,
+    Left,
+    Right,
+}
+
+/// Where the anchor lies relative to the work area. Anchors inside the work
+/// area (e.g. the overflow panel) are treated as a bottom taskbar.
+pub fn taskbar_edge(anchor: Rect, work_area: Rect) -> Edge {
+    if anchor.bottom <= work_area.top {
+        Edge::Top
+    } else if anchor.right <= work_area.left {
+        Edge::Left
+    } else if anchor.left >= work_area.right {
+        Edge::Right
+    } else {
+        Edge::Bottom
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlyoutHit {
+    Prev,
+    PlayPause,
+    Next,
+    /// Fraction of the track to seek to
+    Seek(f32),
+}
+
+/// Element positions; `window` is in screen coordinates, the rest in client
+/// coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlyoutLayout {
+    pub window: Rect,
+    pub art: Rect,
+    pub session: Rect,
+    pub title: Rect,
+    pub artist: Rect,
+    pub seek_bar: Rect,
+    pub prev: Rect,
+    pub play: Rect,
+    pub next: Rect,
+    /// Font heights for the session line and the title/artist lines
+    pub small_font: i32,
+    pub font: i32,
+}
+
+impl FlyoutLayout {
+    /// Lays out the flyout next to `anchor` (the icon rect), kept inside
+    /// `work_area` and away from the taskbar edge.
+    pub fn new(dpi: u32, anchor: Rect, work_area: Rect) -> FlyoutLayout {
+        let width = scale(WIDTH, dpi);
+        let height = scale(HEIGHT, dpi);
+        let margin = scale(MARGIN, dpi);
+        let text_width = WIDTH - TEXT_LEFT - PADDING;
+        let center_x = (anchor.left + anchor.right) / 2;
+        let center_y = (anchor.top + anchor.bottom) / 2;
+
+        let (x, y) = match taskbar_edge(anchor, work_area) {
+            Edge::Bottom => (center_x - width / 2, work_area.bottom.min(anchor.top) - height - margin),
+            Edge::Top => (center_x - width / 2, work_area.top + margin),
+            Edge::Left => (work_area.left + margin, center_y - height / 2),
+            Edge::Right => (work_area.right - width - margin, center_y - height / 2),
+        };
+        let x = x.min(work_area.right - width - margin).max(work_area.left + margin);
+        let y = y.min(work_area.bottom - height - margin).max(work_area.top + margin);
+
+        let buttons_left = (WIDTH - 3 * BUTTON_SIZE - 2 * BUTTON_GAP) / 2;
+        let button = |index: i32| {
+            let left = buttons_left + index * (BUTTON_SIZE + BUTTON_GAP);
+            Rect::scaled(left, BUTTON_TOP, BUTTON_SIZE, BUTTON_SIZE, dpi)
+        };
+
+        FlyoutLayout {
+            window: Rect::new(x, y, x + width, y + height),
+            art: Rect::scaled(PADDING, PADDING, ART_SIZE, ART_SIZE, dpi),
+            session: Rect::scaled(TEXT_LEFT, PADDING, text_width, 16, dpi),
+            title: Rect::scaled(TEXT_LEFT, PADDING + 20, text_width, 22, dpi),
+            artist: Rect::scaled(TEXT_LEFT, PADDING + 44, text_width, 20, dpi),
+            seek_bar: Rect::scaled(PADDING, SEEK_TOP, WIDTH - 2 * PADDING, SEEK_HEIGHT, dpi),
+            prev: button(0),
+            play: button(1),
+            next: button(2),
+            small_font: scale(12, dpi),
+            font: scale(15, dpi),
+        }
+    }
+
+    /// What a click at client coordinates lands on.
+    pub fn hit_test(&self, x: i32, y: i32) -> Option<FlyoutHit> {
+        let bar = self.seek_bar;
+        let slop = self.seek_bar.height().max(1) * SEEK_SLOP / SEEK_HEIGHT;
+        let seek_area = Rect::new(bar.left, bar.top - slop, bar.right, bar.bottom + slop);
+
+        if self.prev.contains(x, y) {
+            Some(FlyoutHit::Prev)
+        } else if self.play.contains(x, y) {
+            Some(FlyoutHit::PlayPause)
+        } else if self.next.contains(x, y) {
+            Some(FlyoutHit::Next)
+        } else if seek_area.contains(x, y) && bar.width() > 0 {
+            Some(FlyoutHit::Seek((x - bar.left) as f32 / bar.width() as f32))
+        } else {
+            None
+        }
+    }
+}
+
+/// Readable name for a session's app ID: the last part of a packaged app ID
+/// (`Microsoft.ZuneMusic_8wekyb3d8bbwe!Microsoft.ZuneMusic` -> `ZuneMusic`),
+/// or the executable name without `.exe`.
+pub fn session_name(app_id: &str) -> String {
+    match app_id.split_once('!') {
+        Some((_, app)) => app.rsplit('.').next().unwrap_or(app).to_string(),
+        None => {
+            let lower = app_id.to_ascii_lowercase();
+            match lower.strip_suffix(".exe") {
+                Some(stem) => app_id[..stem.len()].to_string(),
+                None => app_id.to_string(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DPIS: [u32; 4] = [96, 120, 144, 192];
+
+    /// Work area and icon rect on a 1920x1080 screen with the taskbar on `edge`.
+    fn screen(edge: Edge) -> (Rect, Rect) {
+        match edge {
+            Edge::Bottom => (Rect::new(0, 0, 1920, 1040), Rect::new(1700, 1048, 1724, 1072)),
+            Edge::Top => (Rect::new(0, 40, 1920, 1080), Rect::new(1700, 8, 1724, 32)),
+            Edge::Left => (Rect::new(48, 0, 1920, 1080), Rect::new(12, 900, 36, 924)),
+            Edge::Right => (Rect::new(0, 0, 1872, 1080), Rect::new(1884, 900, 1908, 924)),
+        }
+    }
+
+    #[test]
+    fn finds_the_taskbar_edge() {
+        for edge in [Edge::Bottom, Edge::Top, Edge::Left, Edge::Right] {
+            let (work_area, anchor) = screen(edge);
+            assert_eq!(taskbar_edge(anchor, work_area), edge);
+        }
+        // Icons in the overflow panel sit inside the work area
+        assert_eq!(taskbar_edge(Rect::new(1700, 900, 1724, 924), Rect::new(0, 0, 1920, 1040)), Edge::Bottom);
+    }
+
+    #[test]
+    fn window_sits_next_to_the_icon_at_each_edge() {
+        let cases = [
+            (Edge::Bottom, Rect::new(1552, 876, 1872, 1032)),
+            (Edge::Top, Rect::new(1552, 48, 1872, 204)),
+            (Edge::Left, Rect::new(56, 834, 376, 990)),
+            (Edge::Right, Rect::new(1544, 834, 1864, 990)),
+        ];
+        for (edge, window) in cases {
+            let (work_area, anchor) = screen(edge);
+            assert_eq!(FlyoutLayout::new(96, anchor, work_area).window, window, "{:?}", edge);
+        }
+    }
+
+    #[test]
+    fn window_scales_with_dpi() {
+        // 150% on a 2880x1620 screen
+        let work_area = Rect::new(0, 0, 2880, 1560);
+        let anchor = Rect::new(2550, 1572, 2586, 1608);
+        let layout = FlyoutLayout::new(144, anchor, work_area);
+        assert_eq!(layout.window, Rect::new(2328, 1314, 2808, 1548));
+        assert_eq!(layout.art, Rect::new(18, 18, 126, 126));
+        assert_eq!(layout.seek_bar, Rect::new(18, 147, 462, 153));
+        assert_eq!(layout.prev, Rect::new(144, 168, 192, 216));
+        assert_eq!(layout.play, Rect::new(216, 168, 264, 216));
+        assert_eq!(layout.next, Rect::new(288, 168, 336, 216));
+        assert_eq!((layout.small_font, layout.font), (18, 22));
+    }
+
+    #[test]
+    fn window_stays_inside_the_work_area() {
+        for dpi in DPIS {
+            for edge in [Edge::Bottom, Edge::Top, Edge::Left, Edge::Right] {
+                let (work_area, _) = screen(edge);
+                // Icons in the far corners of each taskbar
+                let anchors = match edge {
+                    Edge::Bottom => [Rect::new(1896, 1048, 1920, 1072), Rect::new(0, 1048, 24, 1072)],
+                    Edge::Top => [Rect::new(1896, 8, 1920, 32), Rect::new(0, 8, 24, 32)],
+                    Edge::Left => [Rect::new(12, 1056, 36, 1080), Rect::new(12, 0, 36, 24)],
+                    Edge::Right => [Rect::new(1884, 1056, 1908, 1080), Rect::new(1884, 0, 1908, 24)],
+                };
+                for anchor in anchors {
+                    let window = FlyoutLayout::new(dpi, anchor, work_area).window;
+                    let margin = scale(MARGIN, dpi);
+                    assert!(window.left >= work_area.left + margin, "{:?} {} {:?}", edge, dpi, window);
+                    assert!(window.right <= work_area.right - margin, "{:?} {} {:?}", edge, dpi, window);
+                    assert!(window.top >= work_area.top + margin, "{:?} {} {:?}", edge, dpi, window);
+                    assert!(window.bottom <= work_area.bottom - margin, "{:?} {} {:?}", edge, dpi, window);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn elements_fit_the_window_without_overlapping() {
+        for dpi in DPIS {
+            let (work_area, anchor) = screen(Edge::Bottom);
+            let layout = FlyoutLayout::new(dpi, anchor, work_area);
+            let (width, height) = (layout.window.width(), layout.window.height());
+            assert_eq!((width, height), (scale(WIDTH, dpi), scale(HEIGHT, dpi)));
+
+            let elements = [
+                layout.art,
+                layout.session,
+                layout.title,
+                layout.artist,
+                layout.seek_bar,
+                layout.prev,
+                layout.play,
+                layout.next,
+            ];
+            for (i, a) in elements.iter().enumerate() {
+                assert!(a.left >= 0 && a.top >= 0 && a.right <= width && a.bottom <= height, "{} {:?}", dpi, a);
+                for b in &elements[i + 1..] {
+                    let overlap = a.left < b.right && b.left < a.right && a.top < b.bottom && b.top < a.bottom;
+                    assert!(!overlap, "{} {:?} {:?}", dpi, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hit_testing() {
+        for dpi in DPIS {
+            let (work_area, anchor) = screen(Edge::Bottom);
+            let layout = FlyoutLayout::new(dpi, anchor, work_area);
+            let center = |r: Rect| ((r.left + r.right) / 2, (r.top + r.bottom) / 2);
+            let hit = |(x, y): (i32, i32)| layout.hit_test(x, y);
+
+            assert_eq!(hit(center(layout.prev)), Some(FlyoutHit::Prev));
+            assert_eq!(hit(center(layout.play)), Some(FlyoutHit::PlayPause));
+            assert_eq!(hit(center(layout.next)), Some(FlyoutHit::Next));
+            assert_eq!(hit(center(layout.art)), None);
+
+            let bar = layout.seek_bar;
+            assert_eq!(hit((bar.left, bar.top)), Some(FlyoutHit::Seek(0.0)));
+            let Some(FlyoutHit::Seek(middle)) = hit((center(bar).0, bar.top)) else {
+                panic!("{}: no seek at the bar's middle", dpi);
+            };
+            assert!((middle - 0.5).abs() < 0.01, "{} {}", dpi, middle);
+            // Slightly above or below the thin bar still seeks
+            let slop = scale(SEEK_SLOP, dpi);
+            assert!(matches!(hit((bar.left, bar.top - slop)), Some(FlyoutHit::Seek(_))), "{}", dpi);
+            assert!(matches!(hit((bar.left, bar.bottom + slop - 1)), Some(FlyoutHit::Seek(_))), "{}", dpi);
+            assert_eq!(hit((bar.left, bar.top - slop - 1)), None, "{}", dpi);
+        }
+    }
+
+    #[test]
+    fn session_names() {
+        let cases = [
+            ("Microsoft.ZuneMusic_8wekyb3d8bbwe!Microsoft.ZuneMusic", "ZuneMusic"),
+            ("SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify", "Spotify"),
+            ("Spotify.exe", "Spotify"),
+            ("VLC.EXE", "VLC"),
+            ("chrome", "chrome"),
+        ];
+        for (app_id, name) in cases {
+            assert_eq!(session_name(app_id), name);
+        }
+    }
+}
@@ -0,0 +1,466 @@
+// ============== Localization ==============
+//
+// Every user-visible string is a `Msg`, looked up in a per-language table.
+// Each table is an exhaustive `match`, so a message missing from any locale
+// is a compile error rather than a runtime fallback.
+
+/// Declares `Language`, its `ALL` list and the codes `as_str` and `parse`
+/// use from one list of variants and codes.
+macro_rules! languages {
+    ($($(#[$attr:meta])* $name:ident => $code:literal,)*) => {
+        #[derive(Clone, Copy, Debug, Default, PartialEq)]
+        pub enum Language {
+            $($(#[$attr])* $name,)*
+        }
+
+        impl Language {
+            #[cfg(test)]
+            pub const ALL: &'static [Language] = &[$(Language::$name,)*];
+
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(Language::$name => $code,)*
+                }
+            }
+
+            pub fn parse(value: &str) -> Option<Language> {
+                match value {
+                    $($code => Some(Language::$name),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+languages! {
+    #[default]
+    English => "en",
+    German => "de",
+    Chinese => "zh",
+    Japanese => "ja",
+}
+
+impl Language {
+
+    /// Maps a Windows LANGID by its primary language; unknown ones get English.
+    pub fn from_langid(langid: u16) -> Language {
+        match langid & 0x3FF {
+            0x07 => Language::German,
+            0x04 => Language::Chinese,
+            0x11 => Language::Japanese,
+            _ => Language::English,
+        }
+    }
+}
+
+/// Declares `Msg` and its `ALL` list from one list of variants, so the list
+/// the tests walk can't miss a message.
+macro_rules! messages {
+    ($($(#[$attr:meta])* $name:ident,)*) => {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub enum Msg {
+            $($(#[$attr])* $name,)*
+        }
+
+        impl Msg {
+            #[cfg(test)]
+            pub const ALL: &'static [Msg] = &[$(Msg::$name,)*];
+        }
+    };
+}
+
+messages! {
+    NothingPlaying,
+    Play,
+    Pause,
+    Next,
+    Previous,
+    Stop,
+    PauseAll,
+    ResumePaused,
+    SleepTimer,
+    SleepOff,
+    /// Takes `{n}`
+    SleepMinutes,
+    /// Takes `{n}`
+    SleepCustom,
+    SleepEndOfTrack,
+    SleepFade,
+    /// Takes `{n}`
+    SleepIn,
+    SleepAtTrackEnd,
+    PlaybackSpeed,
+    OutputDevice,
+    VolumeMixer,
+    MuteAudio,
+    VolumeUp,
+    VolumeDown,
+    Settings,
+    Exit,
+    ShowPrevious,
+    ShowPlayPause,
+    ShowNext,
+    ShowShuffle,
+    ShowRepeat,
+    ShowSpeed,
+    ShowOutput,
+    AutoHide,
+    AutoHideNever,
+    AutoHideNoSession,
+    AutoHideStopped,
+    IconTheme,
+    ThemeAuto,
+    ThemeLight,
+    ThemeDark,
+    AnimatePlayPause,
+    ShowProgressRing,
+    TrackNotifications,
+    NotifyOnTrackChange,
+    /// Takes `{app}`
+    MuteApp,
+    MouseWheelControl,
+    MiniPlayer,
+    AutoPause,
+    PauseOnLock,
+    /// Takes `{n}`
+    PauseOnIdle,
+    PauseOnCall,
+    PauseOnUnplug,
+    ResumeOnReconnect,
+    ShuffleOn,
+    ShuffleOff,
+    RepeatOff,
+    RepeatTrack,
+    RepeatAll,
+}
+
+pub fn text(language: Language, msg: Msg) -> &'static str {
+    match language {
+        Language::English => english(msg),
+        Language::German => german(msg),
+        Language::Chinese => chinese(msg),
+        Language::Japanese => japanese(msg),
+    }
+}
+
+/// `Msg::MuteApp` with the app filled in.
+pub fn mute_app(language: Language, app: &str) -> String {
+    text(language, Msg::MuteApp).replace("{app}", app)
+}
+
+/// A message taking `{n}`, with the number filled in.
+pub fn count(language: Language, msg: Msg, n: u64) -> String {
+    text(language, msg).replace("{n}", &n.to_string())
+}
+
+fn english(msg: Msg) -> &'static str {
+    match msg {
+        Msg::NothingPlaying => "Nothing playing",
+        Msg::Play => "Play",
+        Msg::Pause => "Pause",
+        Msg::Next => "Next",
+        Msg::Previous => "Previous",
+        Msg::Stop => "Stop",
+        Msg::PauseAll => "Pause All",
+        Msg::ResumePaused => "Resume Paused",
+        Msg::SleepTimer => "Sleep Timer",
+        Msg::SleepOff => "Off",
+        Msg::SleepMinutes => "{n} Minutes",
+        Msg::SleepCustom => "Custom ({n} Minutes)",
+        Msg::SleepEndOfTrack => "End of Track",
+        Msg::SleepFade => "Fade Out",
+        Msg::SleepIn => "Sleep in {n} min",
+        Msg::SleepAtTrackEnd => "Sleep at end of track",
+        Msg::PlaybackSpeed => "Playback Speed",
+        Msg::OutputDevice => "Output Device",
+        Msg::VolumeMixer => "Volume Mixer",
+        Msg::MuteAudio => "Mute",
+        Msg::VolumeUp => "Volume Up",
+        Msg::VolumeDown => "Volume Down",
+        Msg::Settings => "Settings",
+        Msg::Exit => "Exit",
+        Msg::ShowPrevious => "Show Previous",
+        Msg::ShowPlayPause => "Show Play/Pause",
+        Msg::ShowNext => "Show Next",
+        Msg::ShowShuffle => "Show Shuffle",
+        Msg::ShowRepeat => "Show Repeat",
+        Msg::ShowSpeed => "Show Playback Speed",
+        Msg::ShowOutput => "Show Output Device",
+        Msg::AutoHide => "Hide Controls",
+        Msg::AutoHideNever => "Never",
+        Msg::AutoHideNoSession => "Without a Player",
+        Msg::AutoHideStopped => "When Stopped",
+        Msg::IconTheme => "Icon Theme",
+        Msg::ThemeAuto => "Auto",
+        Msg::ThemeLight => "Light Icons",
+        Msg::ThemeDark => "Dark Icons",
+        Msg::AnimatePlayPause => "Animate Play/Pause",
+        Msg::ShowProgressRing => "Show Progress Ring",
+        Msg::TrackNotifications => "Track Notifications",
+        Msg::NotifyOnTrackChange => "Notify on Track Change",
+        Msg::MuteApp => "Mute {app}",
+        Msg::MouseWheelControl => "Mouse Wheel Control",
+        Msg::MiniPlayer => "Mini Player",
+        Msg::AutoPause => "Auto Pause",
+        Msg::PauseOnLock => "When Locked or Asleep",
+        Msg::PauseOnIdle => "When Idle for {n} Minutes",
+        Msg::PauseOnCall => "When Mic or Camera Is in Use",
+        Msg::PauseOnUnplug => "When Headphones Disconnect",
+        Msg::ResumeOnReconnect => "Resume When Reconnected",
+        Msg::ShuffleOn => "Shuffle: On",
+        Msg::ShuffleOff => "Shuffle: Off",
+        Msg::RepeatOff => "Repeat: Off",
+        Msg::RepeatTrack => "Repeat: Track",
+        Msg::RepeatAll => "Repeat: All",
+    }
+}
+
+fn german(msg: Msg) -> &'static str {
+    match msg {
+        Msg::NothingPlaying => "Keine Wiedergabe",
+        Msg::Play => "Wiedergabe",
+        Msg::Pause => "Pause",
+        Msg::Next => "Weiter",
+        Msg::Previous => "Zurück",
+        Msg::Stop => "Stopp",
+        Msg::PauseAll => "Alles pausieren",
+        Msg::ResumePaused => "Pausierte fortsetzen",
+        Msg::SleepTimer => "Sleep-Timer",
+        Msg::SleepOff => "Aus",
+        Msg::SleepMinutes => "{n} Minuten",
+        Msg::SleepCustom => "Eigene ({n} Minuten)",
+        Msg::SleepEndOfTrack => "Ende des Titels",
+        Msg::SleepFade => "Ausblenden",
+        Msg::SleepIn => "Stopp in {n} Min.",
+        Msg::SleepAtTrackEnd => "Stopp am Titelende",
+        Msg::PlaybackSpeed => "Wiedergabegeschwindigkeit",
+        Msg::OutputDevice => "Ausgabegerät",
+        Msg::VolumeMixer => "Lautstärkemixer",
+        Msg::MuteAudio => "Stummschalten",
+        Msg::VolumeUp => "Lauter",
+        Msg::VolumeDown => "Leiser",
+        Msg::Settings => "Einstellungen",
+        Msg::Exit => "Beenden",
+        Msg::ShowPrevious => "„Zurück“ anzeigen",
+        Msg::ShowPlayPause => "„Wiedergabe/Pause“ anzeigen",
+        Msg::ShowNext => "„Weiter“ anzeigen",
+        Msg::ShowShuffle => "„Zufallswiedergabe“ anzeigen",
+        Msg::ShowRepeat => "„Wiederholen“ anzeigen",
+        Msg::ShowSpeed => "„Wiedergabegeschwindigkeit“ anzeigen",
+        Msg::ShowOutput => "„Ausgabegerät“ anzeigen",
+        Msg::AutoHide => "Steuerung ausblenden",
+        Msg::AutoHideNever => "Nie",
+        Msg::AutoHideNoSession => "Ohne Player",
+        Msg::AutoHideStopped => "Wenn gestoppt",
+        Msg::IconTheme => "Symbolfarbe",
+        Msg::ThemeAuto => "Automatisch",
+        Msg::ThemeLight => "Helle Symbole",
+        Msg::ThemeDark => "Dunkle Symbole",
+        Msg::AnimatePlayPause => "Wiedergabe/Pause animieren",
+        Msg::ShowProgressRing => "Fortschrittsring anzeigen",
+        Msg::TrackNotifications => "Titelbenachrichtigungen",
+        Msg::NotifyOnTrackChange => "Bei Titelwechsel benachrichtigen",
+        Msg::MuteApp => "{app} stummschalten",
+        Msg::MouseWheelControl => "Mausradsteuerung",
+        Msg::MiniPlayer => "Mini-Player",
+        Msg::AutoPause => "Automatisch pausieren",
+        Msg::PauseOnLock => "Bei Sperre oder Standby",
+        Msg::PauseOnIdle => "Nach {n} Min. ohne Eingabe",
+        Msg::PauseOnCall => "Bei aktivem Mikrofon oder Kamera",
+        Msg::PauseOnUnplug => "Beim Trennen von Kopfhörern",
+        Msg::ResumeOnReconnect => "Nach dem Verbinden fortsetzen",
+        Msg::ShuffleOn => "Zufallswiedergabe: Ein",
+        Msg::ShuffleOff => "Zufallswiedergabe: Aus",
+        Msg::RepeatOff => "Wiederholen: Aus",
+        Msg::RepeatTrack => "Wiederholen: Titel",
+        Msg::RepeatAll => "Wiederholen: Alle",
+    }
+}
+
+fn chinese(msg: Msg) -> &'static str {
+    match msg {
+        Msg::NothingPlaying => "未在播放",
+        Msg::Play => "播放",
+        Msg::Pause => "暂停",
+        Msg::Next => "下一首",
+        Msg::Previous => "上一首",
+        Msg::Stop => "停止",
+        Msg::PauseAll => "全部暂停",
+        Msg::ResumePaused => "恢复已暂停的播放",
+        Msg::SleepTimer => "睡眠定时",
+        Msg::SleepOff => "关闭",
+        Msg::SleepMinutes => "{n} 分钟",
+        Msg::SleepCustom => "自定义（{n} 分钟）",
+        Msg::SleepEndOfTrack => "当前曲目结束时",
+        Msg::SleepFade => "音量渐弱",
+        Msg::SleepIn => "{n} 分钟后停止",
+        Msg::SleepAtTrackEnd => "当前曲目结束时停止",
+        Msg::PlaybackSpeed => "播放速度",
+        Msg::OutputDevice => "输出设备",
+        Msg::VolumeMixer => "音量合成器",
+        Msg::MuteAudio => "静音",
+        Msg::VolumeUp => "增大音量",
+        Msg::VolumeDown => "减小音量",
+        Msg::Settings => "设置",
+        Msg::Exit => "退出",
+        Msg::ShowPrevious => "显示上一首",
+        Msg::ShowPlayPause => "显示播放/暂停",
+        Msg::ShowNext => "显示下一首",
+        Msg::ShowShuffle => "显示随机播放",
+        Msg::ShowRepeat => "显示循环播放",
+        Msg::ShowSpeed => "显示播放速度",
+        Msg::ShowOutput => "显示输出设备",
+        Msg::AutoHide => "自动隐藏控制按钮",
+        Msg::AutoHideNever => "从不",
+        Msg::AutoHideNoSession => "没有播放器时",
+        Msg::AutoHideStopped => "停止时",
+        Msg::IconTheme => "图标主题",
+        Msg::ThemeAuto => "自动",
+        Msg::ThemeLight => "浅色图标",
+        Msg::ThemeDark => "深色图标",
+        Msg::AnimatePlayPause => "播放/暂停动画",
+        Msg::ShowProgressRing => "显示进度环",
+        Msg::TrackNotifications => "曲目通知",
+        Msg::NotifyOnTrackChange => "切换曲目时通知",
+        Msg::MuteApp => "不再通知 {app}",
+        Msg::MouseWheelControl => "鼠标滚轮控制",
+        Msg::MiniPlayer => "迷你播放器",
+        Msg::AutoPause => "自动暂停",
+        Msg::PauseOnLock => "锁定或睡眠时",
+        Msg::PauseOnIdle => "空闲 {n} 分钟后",
+        Msg::PauseOnCall => "麦克风或摄像头使用时",
+        Msg::PauseOnUnplug => "耳机断开时",
+        Msg::ResumeOnReconnect => "重新连接后继续",
+        Msg::ShuffleOn => "随机播放：开",
+        Msg::ShuffleOff => "随机播放：关",
+        Msg::RepeatOff => "循环播放：关",
+        Msg::RepeatTrack => "循环播放：单曲",
+        Msg::RepeatAll => "循环播放：全部",
+    }
+}
+
+fn japanese(msg: Msg) -> &'static str {
+    match msg {
+        Msg::NothingPlaying => "再生していません",
+        Msg::Play => "再生",
+        Msg::Pause => "一時停止",
+        Msg::Next => "次へ",
+        Msg::Previous => "前へ",
+        Msg::Stop => "停止",
+        Msg::PauseAll => "すべて一時停止",
+        Msg::ResumePaused => "一時停止したものを再開",
+        Msg::SleepTimer => "スリープタイマー",
+        Msg::SleepOff => "オフ",
+        Msg::SleepMinutes => "{n} 分",
+        Msg::SleepCustom => "カスタム（{n} 分）",
+        Msg::SleepEndOfTrack => "曲の終わり",
+        Msg::SleepFade => "フェードアウト",
+        Msg::SleepIn => "あと {n} 分で停止",
+        Msg::SleepAtTrackEnd => "曲の終わりで停止",
+        Msg::PlaybackSpeed => "再生速度",
+        Msg::OutputDevice => "出力デバイス",
+        Msg::VolumeMixer => "音量ミキサー",
+        Msg::MuteAudio => "ミュート",
+        Msg::VolumeUp => "音量を上げる",
+        Msg::VolumeDown => "音量を下げる",
+        Msg::Settings => "設定",
+        Msg::Exit => "終了",
+        Msg::ShowPrevious => "「前へ」を表示",
+        Msg::ShowPlayPause => "「再生/一時停止」を表示",
+        Msg::ShowNext => "「次へ」を表示",
+        Msg::ShowShuffle => "「シャッフル」を表示",
+        Msg::ShowRepeat => "「リピート」を表示",
+        Msg::ShowSpeed => "「再生速度」を表示",
+        Msg::ShowOutput => "「出力デバイス」を表示",
+        Msg::AutoHide => "コントロールを隠す",
+        Msg::AutoHideNever => "しない",
+        Msg::AutoHideNoSession => "プレーヤーがないとき",
+        Msg::AutoHideStopped => "停止中",
+        Msg::IconTheme => "アイコンのテーマ",
+        Msg::ThemeAuto => "自動",
+        Msg::ThemeLight => "明るいアイコン",
+        Msg::ThemeDark => "暗いアイコン",
+        Msg::AnimatePlayPause => "再生/一時停止をアニメーション",
+        Msg::ShowProgressRing => "進行状況リングを表示",
+        Msg::TrackNotifications => "曲の通知",
+        Msg::NotifyOnTrackChange => "曲が変わったら通知",
+        Msg::MuteApp => "{app} の通知をオフ",
+        Msg::MouseWheelControl => "マウスホイール操作",
+        Msg::MiniPlayer => "ミニプレーヤー",
+        Msg::AutoPause => "自動一時停止",
+        Msg::PauseOnLock => "ロック時やスリープ時",
+        Msg::PauseOnIdle => "{n} 分間操作がないとき",
+        Msg::PauseOnCall => "マイクやカメラの使用中",
+        Msg::PauseOnUnplug => "ヘッドホンが外れたとき",
+        Msg::ResumeOnReconnect => "再接続で再開",
+        Msg::ShuffleOn => "シャッフル: オン",
+        Msg::ShuffleOff => "シャッフル: オフ",
+        Msg::RepeatOff => "リピート: オフ",
+        Msg::RepeatTrack => "リピート: 1曲",
+        Msg::RepeatAll => "リピート: すべて",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_language_parses_from_its_code() {
+        for &language in Language::ALL {
+            assert_eq!(Language::parse(language.as_str()), Some(language));
+        }
+        assert_eq!(Language::parse("fr"), None);
+    }
+
+    /// Placeholders each message takes, as documented on `Msg`.
+    fn placeholders(msg: Msg) -> &'static [&'static str] {
+        match msg {
+            Msg::MuteApp => &["{app}"],
+            Msg::SleepMinutes | Msg::SleepCustom | Msg::SleepIn | Msg::PauseOnIdle => &["{n}"],
+            _ => &[],
+        }
+    }
+
+    #[test]
+    fn every_translation_keeps_its_placeholders() {
+        for &language in Language::ALL {
+            for &msg in Msg::ALL {
+                let text = text(language, msg);
+                assert!(!text.is_empty(), "{:?} {:?} is empty", language, msg);
+                for placeholder in ["{n}", "{app}"] {
+                    let expected = usize::from(placeholders(msg).contains(&placeholder));
+                    assert_eq!(
+                        text.matches(placeholder).count(),
+                        expected,
+                        "{:?} {:?}: {} in {:?}",
+                        language,
+                        msg,
+                        placeholder,
+                        text
+                    );
+                }
+                // A misspelled placeholder would show up verbatim
+                let filled = text.replace("{n}", "").replace("{app}", "");
+                assert!(!filled.contains('{') && !filled.contains('}'), "{:?} {:?}: {:?}", language, msg, text);
+            }
+        }
+    }
+
+    #[test]
+    fn fills_placeholders() {
+        assert_eq!(mute_app(Language::English, "Spotify"), "Mute Spotify");
+        assert_eq!(mute_app(Language::Japanese, "Spotify"), "Spotify の通知をオフ");
+        assert_eq!(count(Language::German, Msg::SleepIn, 12), "Stopp in 12 Min.");
+    }
+
+    #[test]
+    fn maps_langids() {
+        assert_eq!(Language::from_langid(0x0407), Language::German);
+        assert_eq!(Language::from_langid(0x0807), Language::German);
+        assert_eq!(Language::from_langid(0x0804), Language::Chinese);
+        assert_eq!(Language::from_langid(0x0411), Language::Japanese);
+        assert_eq!(Language::from_langid(0x040C), Language::English);
+    }
+}
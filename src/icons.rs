@@ -0,0 +1,972 @@
+// ============== Icon Rasterization ==============
+//
+// Pure pixel code: every glyph is a 32x32 ARGB buffer. Turning a buffer into
+// an HICON happens in the Win32 shell.
+
+use std::time::Instant;
+
+use crate::core::{
+    self, AlbumArt, RepeatMode, State, TrayIcon, CROSSFADE_DURATION, ROLLBACK_CUE_DURATION, SPINNER_DOTS,
+};
+use crate::output::DeviceClass;
+
+// Icon size - 32x32
+pub const ICON_SIZE: i32 = 32;
+pub const ICON_PIXELS: usize = (ICON_SIZE * ICON_SIZE) as usize;
+
+// ============== Icon Color ==============
+
+pub fn icon_colors(dark_icons: bool) -> (u8, u8, u8) {
+    if dark_icons {
+        (0, 0, 0)
+    } else {
+        (255, 255, 255)
+    }
+}
+
+fn blend_color(r: u8, g: u8, b: u8, alpha: f32) -> u32 {
+    let a = (alpha * 255.0).clamp(0.0, 255.0) as u8;
+    ((a as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32)
+}
+
+fn set_pixel(pixels: &mut Vec<u32>, x: i32, y: i32, color: u32) {
+    if x >= 0 && x < ICON_SIZE && y >= 0 && y < ICON_SIZE {
+        pixels[(y * ICON_SIZE + x) as usize] = color;
+    }
+}
+
+fn draw_filled_circle(pixels: &mut Vec<u32>, cx: f32, cy: f32, radius: f32, r: u8, g: u8, b: u8) {
+    for py in 0..ICON_SIZE {
+        for px in 0..ICON_SIZE {
+            let dx = px as f32 + 0.5 - cx;
+            let dy = py as f32 + 0.5 - cy;
+            let dist = (dx * dx + dy * dy).sqrt();
+            
+            if dist <= radius - 0.7 {
+                set_pixel(pixels, px, py, blend_color(r, g, b, 1.0));
+            } else if dist <= radius + 0.7 {
+                let alpha = 1.0 - (dist - radius + 0.7) / 1.4;
+                let existing = pixels[(py * ICON_SIZE + px) as usize];
+                let existing_alpha = (existing >> 24) as f32 / 255.0;
+                let new_alpha = alpha.max(existing_alpha);
+                set_pixel(pixels, px, py, blend_color(r, g, b, new_alpha));
+            }
+        }
+    }
+}
+
+const PROGRESS_RING_RADIUS: f32 = 15.0;
+const PROGRESS_RING_WIDTH: f32 = 1.5;
+
+/// Draws a ring around the center of a `size` x `size` buffer, clockwise
+/// from 12 o'clock, covering `fraction` of the full circle.
+fn draw_progress_arc(pixels: &mut [u32], size: i32, radius: f32, fraction: f32, r: u8, g: u8, b: u8) {
+    let fraction = fraction.clamp(0.0, 1.0);
+    if fraction <= 0.0 {
+        return;
+    }
+
+    let center = size as f32 / 2.0;
+    let half_width = PROGRESS_RING_WIDTH / 2.0;
+    let end_angle = fraction * std::f32::consts::TAU;
+
+    for py in 0..size {
+        for px in 0..size {
+            let dx = px as f32 + 0.5 - center;
+            let dy = py as f32 + 0.5 - center;
+            let dist = (dx * dx + dy * dy).sqrt();
+
+            let radial = ((half_width - (dist - radius).abs() + 0.7) / 1.4).clamp(0.0, 1.0);
+            if radial <= 0.0 {
+                continue;
+            }
+
+            // Signed distance in pixels along the circle to the nearest arc end
+            let angle = dx.atan2(-dy).rem_euclid(std::f32::consts::TAU);
+            let along = if fraction >= 1.0 {
+                f32::MAX
+            } else if angle <= end_angle {
+                angle.min(end_angle - angle) * dist
+            } else {
+                -(angle - end_angle).min(std::f32::consts::TAU - angle) * dist
+            };
+            let angular = ((along + 0.7) / 1.4).clamp(0.0, 1.0);
+
+            let alpha = radial * angular;
+            if alpha <= 0.0 {
+                continue;
+            }
+            let idx = (py * size + px) as usize;
+            let existing_alpha = (pixels[idx] >> 24) as f32 / 255.0;
+            pixels[idx] = blend_color(r, g, b, alpha.max(existing_alpha));
+        }
+    }
+}
+
+/// Draws a straight stroke with round caps.
+#[allow(clippy::too_many_arguments)]
+fn draw_line(pixels: &mut [u32], x1: f32, y1: f32, x2: f32, y2: f32, width: f32, r: u8, g: u8, b: u8) {
+    let half_width = width / 2.0;
+    let (vx, vy) = (x2 - x1, y2 - y1);
+    let len_sq = (vx * vx + vy * vy).max(f32::EPSILON);
+
+    for py in 0..ICON_SIZE {
+        for px in 0..ICON_SIZE {
+            let fx = px as f32 + 0.5;
+            let fy = py as f32 + 0.5;
+
+            // Distance to the closest point on the segment
+            let t = (((fx - x1) * vx + (fy - y1) * vy) / len_sq).clamp(0.0, 1.0);
+            let dx = fx - (x1 + t * vx);
+            let dy = fy - (y1 + t * vy);
+            let dist_to_edge = half_width - (dx * dx + dy * dy).sqrt();
+
+            let idx = (py * ICON_SIZE + px) as usize;
+            if dist_to_edge > 0.7 {
+                pixels[idx] = blend_color(r, g, b, 1.0);
+            } else if dist_to_edge > -0.7 {
+                let alpha = (dist_to_edge + 0.7) / 1.4;
+                let existing_alpha = (pixels[idx] >> 24) as f32 / 255.0;
+                pixels[idx] = blend_color(r, g, b, alpha.max(existing_alpha));
+            }
+        }
+    }
+}
+
+/// Strokes a circular arc as short straight segments. Angles are in degrees,
+/// clockwise from 3 o'clock.
+#[allow(clippy::too_many_arguments)]
+fn draw_arc(pixels: &mut [u32], cx: f32, cy: f32, radius: f32, from: f32, to: f32, width: f32, r: u8, g: u8, b: u8) {
+    const SEGMENTS: u32 = 12;
+    let point = |i: u32| {
+        let angle = (from + (to - from) * i as f32 / SEGMENTS as f32).to_radians();
+        (cx + radius * angle.cos(), cy + radius * angle.sin())
+    };
+    for i in 0..SEGMENTS {
+        let ((x1, y1), (x2, y2)) = (point(i), point(i + 1));
+        draw_line(pixels, x1, y1, x2, y2, width, r, g, b);
+    }
+}
+
+/// Multiplies the alpha of every pixel by `factor`.
+fn scale_alpha(pixels: &mut [u32], factor: f32) {
+    for p in pixels.iter_mut() {
+        let alpha = ((*p >> 24) as f32 * factor) as u32;
+        *p = (alpha << 24) | (*p & 0x00FFFFFF);
+    }
+}
+
+fn draw_rounded_rect(pixels: &mut Vec<u32>, x1: f32, y1: f32, x2: f32, y2: f32, corner_radius: f32, r: u8, g: u8, b: u8) {
+    let cr = corner_radius.min((x2 - x1) / 2.0).min((y2 - y1) / 2.0);
+    
+    for py in 0..ICON_SIZE {
+        for px in 0..ICON_SIZE {
+            let fx = px as f32 + 0.5;
+            let fy = py as f32 + 0.5;
+            
+            if fx < x1 - 0.7 || fx > x2 + 0.7 || fy < y1 - 0.7 || fy > y2 + 0.7 {
+                continue;
+            }
+            
+            let in_left = fx < x1 + cr;
+            let in_right = fx > x2 - cr;
+            let in_top = fy < y1 + cr;
+            let in_bottom = fy > y2 - cr;
+            
+            let dist_to_edge = if in_left && in_top {
+                let ccx = x1 + cr;
+                let ccy = y1 + cr;
+                cr - ((fx - ccx).powi(2) + (fy - ccy).powi(2)).sqrt()
+            } else if in_right && in_top {
+                let ccx = x2 - cr;
+                let ccy = y1 + cr;
+                cr - ((fx - ccx).powi(2) + (fy - ccy).powi(2)).sqrt()
+            } else if in_left && in_bottom {
+                let ccx = x1 + cr;
+                let ccy = y2 - cr;
+                cr - ((fx - ccx).powi(2) + (fy - ccy).powi(2)).sqrt()
+            } else if in_right && in_bottom {
+                let ccx = x2 - cr;
+                let ccy = y2 - cr;
+                cr - ((fx - ccx).powi(2) + (fy - ccy).powi(2)).sqrt()
+            } else {
+                (fx - x1).min(x2 - fx).min(fy - y1).min(y2 - fy)
+            };
+            
+            if dist_to_edge > 0.7 {
+                set_pixel(pixels, px, py, blend_color(r, g, b, 1.0));
+            } else if dist_to_edge > -0.7 {
+                let alpha = (dist_to_edge + 0.7) / 1.4;
+                let existing = pixels[(py * ICON_SIZE + px) as usize];
+                let existing_alpha = (existing >> 24) as f32 / 255.0;
+                let new_alpha = alpha.max(existing_alpha);
+                set_pixel(pixels, px, py, blend_color(r, g, b, new_alpha));
+            }
+        }
+    }
+}
+
+fn draw_triangle_right(pixels: &mut Vec<u32>, x1: f32, y_center: f32, width: f32, height: f32, r: u8, g: u8, b: u8) {
+    let half_height = height / 2.0;
+    let x2 = x1 + width;
+    
+    for py in 0..ICON_SIZE {
+        for px in 0..ICON_SIZE {
+            let fx = px as f32 + 0.5;
+            let fy = py as f32 + 0.5;
+            
+            let dy = (fy - y_center).abs();
+            if dy > half_height + 1.0 {
+                continue;
+            }
+            
+            let progress = dy / half_height;
+            let edge_x = x2 - progress * width;
+            
+            if fx >= x1 - 0.7 && fx <= edge_x + 0.7 {
+                let left_dist = fx - x1;
+                let right_dist = edge_x - fx;
+                let tb_dist = half_height - dy;
+                let min_dist = left_dist.min(right_dist).min(tb_dist);
+                
+                if min_dist > 0.7 {
+                    set_pixel(pixels, px, py, blend_color(r, g, b, 1.0));
+                } else if min_dist > -0.7 {
+                    let alpha = (min_dist + 0.7) / 1.4;
+                    let existing = pixels[(py * ICON_SIZE + px) as usize];
+                    let existing_alpha = (existing >> 24) as f32 / 255.0;
+                    let new_alpha = alpha.max(existing_alpha);
+                    set_pixel(pixels, px, py, blend_color(r, g, b, new_alpha));
+                }
+            }
+        }
+    }
+}
+
+fn draw_triangle_left(pixels: &mut Vec<u32>, x_right: f32, y_center: f32, width: f32, height: f32, r: u8, g: u8, b: u8) {
+    let half_height = height / 2.0;
+    let x_left = x_right - width;
+    
+    for py in 0..ICON_SIZE {
+        for px in 0..ICON_SIZE {
+            let fx = px as f32 + 0.5;
+            let fy = py as f32 + 0.5;
+            
+            let dy = (fy - y_center).abs();
+            if dy > half_height + 1.0 {
+                continue;
+            }
+            
+            let progress = dy / half_height;
+            let edge_x = x_left + progress * width;
+            
+            if fx <= x_right + 0.7 && fx >= edge_x - 0.7 {
+                let left_dist = fx - edge_x;
+                let right_dist = x_right - fx;
+                let tb_dist = half_height - dy;
+                let min_dist = left_dist.min(right_dist).min(tb_dist);
+                
+                if min_dist > 0.7 {
+                    set_pixel(pixels, px, py, blend_color(r, g, b, 1.0));
+                } else if min_dist > -0.7 {
+                    let alpha = (min_dist + 0.7) / 1.4;
+                    let existing = pixels[(py * ICON_SIZE + px) as usize];
+                    let existing_alpha = (existing >> 24) as f32 / 255.0;
+                    let new_alpha = alpha.max(existing_alpha);
+                    set_pixel(pixels, px, py, blend_color(r, g, b, new_alpha));
+                }
+            }
+        }
+    }
+}
+
+// ============== Icon Definitions (32x32) ==============
+
+fn default_glyph(r: u8, g: u8, b: u8) -> Vec<u32> {
+    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
+    
+    // Music note head
+    draw_filled_circle(&mut pixels, 10.0, 23.0, 7.0, r, g, b);
+    
+    // Note stem
+    draw_rounded_rect(&mut pixels, 13.0, 5.0, 17.0, 23.0, 2.0, r, g, b);
+    
+    // Note flag
+    draw_rounded_rect(&mut pixels, 14.0, 5.0, 25.0, 8.0, 2.0, r, g, b);
+    draw_rounded_rect(&mut pixels, 22.0, 7.5, 24.5, 13.0, 1.5, r, g, b);
+    draw_rounded_rect(&mut pixels, 21.0, 12.0, 23.0, 16.0, 1.0, r, g, b);
+
+    pixels
+}
+
+fn prev_glyph(r: u8, g: u8, b: u8) -> Vec<u32> {
+    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
+    
+    // Left bar (y: 6-26 = height 20)
+    draw_rounded_rect(&mut pixels, 5.0, 6.0, 10.0, 26.0, 1.5, r, g, b);
+    
+    // Triangle pointing left
+    draw_triangle_left(&mut pixels, 27.0, 16.0, 19.0, 20.0, r, g, b);
+
+    pixels
+}
+
+fn play_glyph(r: u8, g: u8, b: u8) -> Vec<u32> {
+    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
+
+    // Play triangle
+    draw_triangle_right(&mut pixels, 7.0, 16.0, 21.0, 20.0, r, g, b);
+
+    pixels
+}
+
+fn pause_glyph(r: u8, g: u8, b: u8) -> Vec<u32> {
+    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
+
+    // Two vertical bars
+    draw_rounded_rect(&mut pixels, 6.0, 6.0, 12.0, 26.0, 1.5, r, g, b);
+    draw_rounded_rect(&mut pixels, 20.0, 6.0, 26.0, 26.0, 1.5, r, g, b);
+
+    pixels
+}
+
+/// Glyph shown on the play icon: pause while playing, play otherwise.
+fn play_state_glyph(playing: bool, r: u8, g: u8, b: u8) -> Vec<u32> {
+    if playing {
+        pause_glyph(r, g, b)
+    } else {
+        play_glyph(r, g, b)
+    }
+}
+
+fn next_glyph(r: u8, g: u8, b: u8) -> Vec<u32> {
+    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
+    
+    // Triangle pointing right
+    draw_triangle_right(&mut pixels, 5.0, 16.0, 19.0, 20.0, r, g, b);
+    
+    // Right bar (y: 6-26 = height 20)
+    draw_rounded_rect(&mut pixels, 22.0, 6.0, 27.0, 26.0, 1.5, r, g, b);
+
+    pixels
+}
+
+// Inactive shuffle/repeat glyphs are drawn at reduced opacity
+const INACTIVE_ALPHA: f32 = 0.4;
+
+fn shuffle_glyph(active: bool, r: u8, g: u8, b: u8) -> Vec<u32> {
+    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
+
+    // Two crossing paths
+    draw_line(&mut pixels, 4.0, 9.5, 9.0, 9.5, 2.5, r, g, b);
+    draw_line(&mut pixels, 9.0, 9.5, 17.0, 22.5, 2.5, r, g, b);
+    draw_line(&mut pixels, 17.0, 22.5, 22.0, 22.5, 2.5, r, g, b);
+    draw_line(&mut pixels, 4.0, 22.5, 9.0, 22.5, 2.5, r, g, b);
+    draw_line(&mut pixels, 9.0, 22.5, 17.0, 9.5, 2.5, r, g, b);
+    draw_line(&mut pixels, 17.0, 9.5, 22.0, 9.5, 2.5, r, g, b);
+
+    // Arrow heads
+    draw_triangle_right(&mut pixels, 21.0, 9.5, 7.0, 9.0, r, g, b);
+    draw_triangle_right(&mut pixels, 21.0, 22.5, 7.0, 9.0, r, g, b);
+
+    if !active {
+        scale_alpha(&mut pixels, INACTIVE_ALPHA);
+    }
+    pixels
+}
+
+fn repeat_glyph(mode: RepeatMode, r: u8, g: u8, b: u8) -> Vec<u32> {
+    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
+
+    // Upper half of the loop, arrow pointing right
+    draw_line(&mut pixels, 5.5, 18.0, 5.5, 9.0, 2.5, r, g, b);
+    draw_line(&mut pixels, 5.5, 9.0, 21.0, 9.0, 2.5, r, g, b);
+    draw_triangle_right(&mut pixels, 20.0, 9.0, 7.0, 9.0, r, g, b);
+
+    // Lower half of the loop, arrow pointing left
+    draw_line(&mut pixels, 26.5, 14.0, 26.5, 23.0, 2.5, r, g, b);
+    draw_line(&mut pixels, 26.5, 23.0, 11.0, 23.0, 2.5, r, g, b);
+    draw_triangle_left(&mut pixels, 12.0, 23.0, 7.0, 9.0, r, g, b);
+
+    match mode {
+        RepeatMode::Off => scale_alpha(&mut pixels, INACTIVE_ALPHA),
+        // "1" in the middle of the loop
+        RepeatMode::Track => {
+            draw_rounded_rect(&mut pixels, 15.0, 12.0, 17.5, 20.0, 0.5, r, g, b);
+            draw_rounded_rect(&mut pixels, 13.0, 12.0, 17.5, 14.0, 0.5, r, g, b);
+        }
+        RepeatMode::List => {}
+    }
+    pixels
+}
+
+fn headphones_glyph(r: u8, g: u8, b: u8) -> Vec<u32> {
+    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
+
+    // Headband over the top
+    draw_arc(&mut pixels, 16.0, 17.0, 10.5, 180.0, 360.0, 2.5, r, g, b);
+
+    // Ear cups
+    draw_rounded_rect(&mut pixels, 4.0, 16.0, 10.0, 27.0, 2.0, r, g, b);
+    draw_rounded_rect(&mut pixels, 22.0, 16.0, 28.0, 27.0, 2.0, r, g, b);
+
+    pixels
+}
+
+fn speakers_glyph(r: u8, g: u8, b: u8) -> Vec<u32> {
+    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
+
+    // Speaker body and cone
+    draw_rounded_rect(&mut pixels, 3.0, 12.0, 9.0, 20.0, 1.0, r, g, b);
+    draw_triangle_left(&mut pixels, 16.0, 16.0, 10.0, 20.0, r, g, b);
+
+    // Sound waves
+    draw_arc(&mut pixels, 14.0, 16.0, 6.0, -40.0, 40.0, 2.0, r, g, b);
+    draw_arc(&mut pixels, 14.0, 16.0, 11.0, -45.0, 45.0, 2.0, r, g, b);
+
+    pixels
+}
+
+fn display_glyph(r: u8, g: u8, b: u8) -> Vec<u32> {
+    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
+
+    // Screen outline
+    draw_line(&mut pixels, 4.0, 6.0, 28.0, 6.0, 2.5, r, g, b);
+    draw_line(&mut pixels, 28.0, 6.0, 28.0, 21.0, 2.5, r, g, b);
+    draw_line(&mut pixels, 28.0, 21.0, 4.0, 21.0, 2.5, r, g, b);
+    draw_line(&mut pixels, 4.0, 21.0, 4.0, 6.0, 2.5, r, g, b);
+
+    // Stand
+    draw_line(&mut pixels, 16.0, 21.0, 16.0, 26.0, 2.5, r, g, b);
+    draw_line(&mut pixels, 10.0, 26.5, 22.0, 26.5, 2.5, r, g, b);
+
+    pixels
+}
+
+/// Glyph for the default output, by its kind. Devices of unknown kind are
+/// drawn as speakers.
+fn output_glyph(class: DeviceClass, r: u8, g: u8, b: u8) -> Vec<u32> {
+    match class {
+        DeviceClass::Headphones => headphones_glyph(r, g, b),
+        DeviceClass::Display => display_glyph(r, g, b),
+        DeviceClass::Speakers | DeviceClass::Other => speakers_glyph(r, g, b),
+    }
+}
+
+// ============== Bitmap Font ==============
+
+const FONT_HEIGHT: i32 = 5;
+// Largest pixel size of a font cell, so short labels don't fill the icon
+const FONT_MAX_SCALE: f32 = 3.0;
+// Samples per pixel along each axis when scaling the cells down
+const FONT_SUBSAMPLES: i32 = 4;
+
+/// Tiny glyphs for the speed label: each row is a bit mask of `width`
+/// cells, the leftmost in the highest bit.
+const FONT: &[(char, i32, [u8; FONT_HEIGHT as usize])] = &[
+    ('0', 3, [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', 3, [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', 3, [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', 3, [0b111, 0b001, 0b011, 0b001, 0b111]),
+    ('4', 3, [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', 3, [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', 3, [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', 3, [0b111, 0b001, 0b001, 0b010, 0b010]),
+    ('8', 3, [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', 3, [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('.', 1, [0b0, 0b0, 0b0, 0b0, 0b1]),
+    ('x', 3, [0b000, 0b000, 0b101, 0b010, 0b101]),
+];
+
+/// Lit cells of `text` as rows of columns, one blank column between
+/// characters. Characters the font lacks are skipped.
+fn text_cells(text: &str) -> Vec<Vec<bool>> {
+    let mut rows = vec![Vec::new(); FONT_HEIGHT as usize];
+    for c in text.chars() {
+        let Some(&(_, width, bits)) = FONT.iter().find(|glyph| glyph.0 == c) else {
+            continue;
+        };
+        for (row, mask) in rows.iter_mut().zip(bits) {
+            if !row.is_empty() {
+                row.push(false);
+            }
+            row.extend((0..width).rev().map(|bit| mask >> bit & 1 == 1));
+        }
+    }
+    rows
+}
+
+/// Draws `text` centered, scaled to fit the icon's width. Cells scale by a
+/// fraction of a pixel, so edges are anti-aliased by coverage.
+fn draw_text(pixels: &mut [u32], text: &str, r: u8, g: u8, b: u8) {
+    let cells = text_cells(text);
+    let columns = cells[0].len() as i32;
+    if columns == 0 {
+        return;
+    }
+    let scale = ((ICON_SIZE - 2) as f32 / columns as f32).min(FONT_MAX_SCALE);
+    // Whole-pixel origin, so whole-pixel scales stay sharp
+    let left = ((ICON_SIZE as f32 - columns as f32 * scale) / 2.0).round();
+    let top = ((ICON_SIZE as f32 - FONT_HEIGHT as f32 * scale) / 2.0).round();
+    let lit = |x: f32, y: f32| {
+        let (column, row) = (((x - left) / scale).floor(), ((y - top) / scale).floor());
+        column >= 0.0 && row >= 0.0 && cells.get(row as usize).and_then(|cells| cells.get(column as usize)) == Some(&true)
+    };
+
+    for py in 0..ICON_SIZE {
+        for px in 0..ICON_SIZE {
+            let mut hits = 0;
+            for sy in 0..FONT_SUBSAMPLES {
+                for sx in 0..FONT_SUBSAMPLES {
+                    let step = 1.0 / FONT_SUBSAMPLES as f32;
+                    if lit(px as f32 + (sx as f32 + 0.5) * step, py as f32 + (sy as f32 + 0.5) * step) {
+                        hits += 1;
+                    }
+                }
+            }
+            if hits > 0 {
+                let alpha = hits as f32 / (FONT_SUBSAMPLES * FONT_SUBSAMPLES) as f32;
+                let idx = (py * ICON_SIZE + px) as usize;
+                let existing_alpha = (pixels[idx] >> 24) as f32 / 255.0;
+                pixels[idx] = blend_color(r, g, b, alpha.max(existing_alpha));
+            }
+        }
+    }
+}
+
+/// The playback rate as text, e.g. "1.5x".
+fn speed_glyph(speed: f64, r: u8, g: u8, b: u8) -> Vec<u32> {
+    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
+    draw_text(&mut pixels, &core::speed_label(speed), r, g, b);
+    pixels
+}
+
+// ============== Icon Animation ==============
+
+const SPINNER_DOT_RADIUS: f32 = 2.5;
+
+/// Blends two glyphs. `t` = 0.0 yields `a`, `t` = 1.0 yields `b`.
+fn crossfade_frame(a: &[u32], b: &[u32], t: f32) -> Vec<u32> {
+    let t = t.clamp(0.0, 1.0);
+    let lerp = |pa: u32, pb: u32, shift: u32| {
+        let ca = ((pa >> shift) & 0xFF) as f32;
+        let cb = ((pb >> shift) & 0xFF) as f32;
+        ((ca + (cb - ca) * t).round() as u32) << shift
+    };
+
+    a.iter()
+        .zip(b.iter())
+        .map(|(&pa, &pb)| lerp(pa, pb, 24) | lerp(pa, pb, 16) | lerp(pa, pb, 8) | lerp(pa, pb, 0))
+        .collect()
+}
+
+/// Dims the glyph and draws a ring of dots whose brightest dot is `step`.
+fn spinner_frame(glyph: &[u32], step: u32, r: u8, g: u8, b: u8) -> Vec<u32> {
+    let mut pixels = glyph.to_vec();
+    scale_alpha(&mut pixels, 0.35);
+
+    let center = ICON_SIZE as f32 / 2.0;
+    for i in 0..SPINNER_DOTS {
+        let angle = i as f32 / SPINNER_DOTS as f32 * std::f32::consts::TAU;
+        let age = (step + SPINNER_DOTS - i) % SPINNER_DOTS;
+        let alpha = 1.0 - age as f32 / SPINNER_DOTS as f32;
+        let cx = center + angle.sin() * 12.0;
+        let cy = center - angle.cos() * 12.0;
+        draw_dot(&mut pixels, cx, cy, r, g, b, alpha);
+    }
+
+    pixels
+}
+
+fn draw_dot(pixels: &mut [u32], cx: f32, cy: f32, r: u8, g: u8, b: u8, alpha: f32) {
+    let radius = SPINNER_DOT_RADIUS;
+    for py in 0..ICON_SIZE {
+        for px in 0..ICON_SIZE {
+            let dx = px as f32 + 0.5 - cx;
+            let dy = py as f32 + 0.5 - cy;
+            let coverage = (radius + 0.7 - (dx * dx + dy * dy).sqrt()) / 1.4;
+            if coverage <= 0.0 {
+                continue;
+            }
+            let idx = (py * ICON_SIZE + px) as usize;
+            let existing_alpha = (pixels[idx] >> 24) as f32 / 255.0;
+            let new_alpha = (coverage.min(1.0) * alpha).max(existing_alpha);
+            pixels[idx] = blend_color(r, g, b, new_alpha);
+        }
+    }
+}
+
+/// The play icon as currently displayed: cross-fade, buffering spinner and
+/// progress ring are layered over the play/pause glyph.
+pub fn play_frame(state: &State, now: Instant) -> Vec<u32> {
+    let settings = &state.settings;
+    let (r, g, b) = icon_colors(settings.dark_icons);
+    let target = play_state_glyph(state.playing, r, g, b);
+
+    let mut frame = match state.fade {
+        Some(fade) if settings.animations => {
+            let t = now.duration_since(fade.start).as_secs_f32() / CROSSFADE_DURATION.as_secs_f32();
+            let from = play_state_glyph(fade.from_playing, r, g, b);
+            crossfade_frame(&from, &target, t)
+        }
+        _ => target,
+    };
+
+    if settings.animations && state.buffering {
+        frame = spinner_frame(&frame, state.spinner_step, r, g, b);
+    }
+
+    if settings.progress_ring {
+        if let Some(fraction) = state.progress {
+            draw_progress_arc(&mut frame, ICON_SIZE, PROGRESS_RING_RADIUS, fraction, r, g, b);
+        }
+    }
+
+    if let Some(start) = state.rollback_cue {
+        rollback_cue_frame(&mut frame, now.duration_since(start).as_secs_f32());
+    }
+
+    frame
+}
+
+const SLEEP_BADGE_SIZE: i32 = 14;
+
+/// Sleep timer badge in the bottom-right corner: a ring showing the part of
+/// the countdown left around a center dot, on a cleared disc so it stays
+/// readable over any glyph.
+fn draw_sleep_badge(pixels: &mut [u32], remaining: f32, r: u8, g: u8, b: u8) {
+    let size = SLEEP_BADGE_SIZE;
+    let mut badge = vec![0u32; (size * size) as usize];
+    let radius = size as f32 / 2.0 - 1.5;
+    draw_progress_arc(&mut badge, size, radius, remaining, r, g, b);
+
+    let offset = ICON_SIZE - size;
+    let center = size as f32 / 2.0;
+    for y in 0..size {
+        for x in 0..size {
+            let dx = x as f32 + 0.5 - center;
+            let dy = y as f32 + 0.5 - center;
+            if dx * dx + dy * dy <= center * center {
+                pixels[((offset + y) * ICON_SIZE + offset + x) as usize] = badge[(y * size + x) as usize];
+            }
+        }
+    }
+    draw_dot(pixels, offset as f32 + center, offset as f32 + center, r, g, b, 1.0);
+}
+
+/// Blinks the icon three times over `ROLLBACK_CUE_DURATION`.
+fn rollback_cue_frame(pixels: &mut [u32], elapsed: f32) {
+    let blink = ROLLBACK_CUE_DURATION.as_secs_f32() / 6.0;
+    if ((elapsed / blink) as u32).is_multiple_of(2) {
+        scale_alpha(pixels, 0.25);
+    }
+}
+
+pub fn icon_pixels(state: &State, icon: TrayIcon, now: Instant) -> Vec<u32> {
+    let (r, g, b) = icon_colors(state.settings.dark_icons);
+    let mut pixels = match icon {
+        TrayIcon::Default => default_glyph(r, g, b),
+        TrayIcon::Prev => prev_glyph(r, g, b),
+        TrayIcon::Play => play_frame(state, now),
+        TrayIcon::Next => next_glyph(r, g, b),
+        TrayIcon::Shuffle => shuffle_glyph(state.modes.shuffle.unwrap_or(false), r, g, b),
+        TrayIcon::Repeat => repeat_glyph(state.modes.repeat.unwrap_or(RepeatMode::Off), r, g, b),
+        TrayIcon::Speed => speed_glyph(state.modes.speed.unwrap_or(1.0), r, g, b),
+        TrayIcon::Output => {
+            output_glyph(state.default_output().map_or(DeviceClass::Other, |device| device.class), r, g, b)
+        }
+    };
+    if icon == state.sleep_badge_icon() {
+        if let Some(remaining) = state.sleep.badge(now) {
+            draw_sleep_badge(&mut pixels, remaining, r, g, b);
+        }
+    }
+    pixels
+}
+
+/// Plain glyph for the flyout's buttons and art placeholder, without the
+/// tray-only layers (fade, spinner, ring).
+pub fn flyout_glyph(icon: TrayIcon, playing: bool, dark_icons: bool) -> Vec<u32> {
+    let (r, g, b) = icon_colors(dark_icons);
+    match icon {
+        TrayIcon::Prev => prev_glyph(r, g, b),
+        TrayIcon::Play => play_state_glyph(playing, r, g, b),
+        TrayIcon::Next => next_glyph(r, g, b),
+        _ => default_glyph(r, g, b),
+    }
+}
+
+/// Album art scaled to fit an icon for the track balloon, centered on a
+/// transparent background.
+pub fn art_icon(art: &AlbumArt) -> Vec<u32> {
+    let mut pixels = vec![0u32; ICON_PIXELS];
+    if art.width <= 0 || art.height <= 0 || art.pixels.len() < (art.width * art.height) as usize {
+        return pixels;
+    }
+    let longest = art.width.max(art.height);
+    let (width, height) = (art.width * ICON_SIZE / longest, art.height * ICON_SIZE / longest);
+    let (left, top) = ((ICON_SIZE - width) / 2, (ICON_SIZE - height) / 2);
+    for y in 0..height {
+        for x in 0..width {
+            let (sx, sy) = (x * longest / ICON_SIZE, y * longest / ICON_SIZE);
+            pixels[((top + y) * ICON_SIZE + left + x) as usize] = art.pixels[(sy * art.width + sx) as usize];
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Float results differ slightly between platforms' math libraries
+    const GOLDEN_TOLERANCE: i32 = 2;
+
+    /// Compares `pixels` with `testdata/icons/<name>.txt`, one row per line and
+    /// one `AARRGGBB` value per pixel. `UPDATE_GOLDEN=1 cargo test` rewrites
+    /// the files instead.
+    fn assert_golden(name: &str, size: i32, pixels: &[u32]) {
+        assert_eq!(pixels.len(), (size * size) as usize);
+        let path = format!("{}/testdata/icons/{}.txt", env!("CARGO_MANIFEST_DIR"), name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            let rows: Vec<String> = pixels
+                .chunks(size as usize)
+                .map(|row| row.iter().map(|p| format!("{:08x}", p)).collect::<Vec<_>>().join(" "))
+                .collect();
+            fs::write(&path, rows.join("\n") + "\n").unwrap();
+            return;
+        }
+
+        let golden = fs::read_to_string(&path).unwrap_or_else(|_| panic!("missing golden {}", path));
+        let expected: Vec<u32> =
+            golden.split_whitespace().map(|p| u32::from_str_radix(p, 16).unwrap()).collect();
+        assert_eq!(expected.len(), pixels.len(), "{}: size", name);
+        for (i, (&actual, &expected)) in pixels.iter().zip(&expected).enumerate() {
+            let off = (0..4).any(|c| {
+                let channel = |p: u32| ((p >> (c * 8)) & 0xFF) as i32;
+                (channel(actual) - channel(expected)).abs() > GOLDEN_TOLERANCE
+            });
+            assert!(
+                !off,
+                "{}: pixel ({}, {}) is {:08x}, expected {:08x}",
+                name,
+                i as i32 % size,
+                i as i32 / size,
+                actual,
+                expected
+            );
+        }
+    }
+
+    fn alpha(pixel: u32) -> u32 {
+        pixel >> 24
+    }
+
+    fn arc(size: i32, fraction: f32) -> Vec<u32> {
+        let mut pixels = vec![0; (size * size) as usize];
+        draw_progress_arc(&mut pixels, size, size as f32 / 2.0 - 1.0, fraction, 255, 255, 255);
+        pixels
+    }
+
+    #[test]
+    fn progress_arc_goldens() {
+        for size in [16, 20, 24, 32] {
+            for (fraction, name) in [(0.1, "10"), (0.5, "50"), (0.875, "875"), (1.0, "100")] {
+                assert_golden(&format!("arc_{}_{}", size, name), size, &arc(size, fraction));
+            }
+        }
+    }
+
+    #[test]
+    fn progress_arc_is_empty_at_zero() {
+        for size in [16, 32] {
+            assert!(arc(size, 0.0).iter().all(|&p| p == 0));
+            assert!(arc(size, -0.5).iter().all(|&p| p == 0));
+        }
+    }
+
+    #[test]
+    fn progress_arc_stays_on_its_side() {
+        for size in [16, 20, 24, 32] {
+            let pixels = arc(size, 0.5);
+            for y in 0..size {
+                for x in 0..size {
+                    let a = alpha(pixels[(y * size + x) as usize]);
+                    // Only the anti-aliased arc ends reach across the center line
+                    if x < size / 2 - 1 {
+                        assert_eq!(a, 0, "{}px: ({}, {})", size, x, y);
+                    }
+                }
+            }
+            assert!(pixels.iter().any(|&p| alpha(p) >= 0xC0), "{}px: nothing drawn", size);
+        }
+    }
+
+    #[test]
+    fn full_progress_ring_is_symmetric() {
+        for size in [16, 20, 24, 32] {
+            let pixels = arc(size, 1.0);
+            let at = |x: i32, y: i32| alpha(pixels[(y * size + x) as usize]) as i32;
+            for y in 0..size {
+                for x in 0..size {
+                    assert!((at(x, y) - at(size - 1 - x, y)).abs() <= 1, "{}px: ({}, {})", size, x, y);
+                    assert!((at(x, y) - at(x, size - 1 - y)).abs() <= 1, "{}px: ({}, {})", size, x, y);
+                }
+            }
+            // Over-full is full
+            assert_eq!(arc(size, 1.5), pixels);
+        }
+    }
+
+    #[test]
+    fn progress_arc_grows_with_the_fraction() {
+        let coverage = |fraction| arc(24, fraction).iter().map(|&p| alpha(p)).sum::<u32>();
+        let steps = [0.1, 0.25, 0.5, 0.75, 0.9, 1.0].map(coverage);
+        assert!(steps.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", steps);
+    }
+
+    const WHITE: (u8, u8, u8) = (255, 255, 255);
+
+    fn play() -> Vec<u32> {
+        play_glyph(WHITE.0, WHITE.1, WHITE.2)
+    }
+
+    fn pause() -> Vec<u32> {
+        pause_glyph(WHITE.0, WHITE.1, WHITE.2)
+    }
+
+    #[test]
+    fn crossfade_goldens() {
+        for (t, name) in [(0.25, "25"), (0.5, "50"), (0.75, "75")] {
+            assert_golden(&format!("crossfade_play_pause_{}", name), ICON_SIZE, &crossfade_frame(&play(), &pause(), t));
+        }
+    }
+
+    #[test]
+    fn crossfade_ends_on_its_glyphs() {
+        assert_eq!(crossfade_frame(&play(), &pause(), 0.0), play());
+        assert_eq!(crossfade_frame(&play(), &pause(), 1.0), pause());
+        // Progress outside 0..1 is clamped
+        assert_eq!(crossfade_frame(&play(), &pause(), -1.0), play());
+        assert_eq!(crossfade_frame(&play(), &pause(), 2.0), pause());
+    }
+
+    #[test]
+    fn crossfade_halfway_is_symmetric() {
+        assert_eq!(crossfade_frame(&play(), &pause(), 0.5), crossfade_frame(&pause(), &play(), 0.5));
+    }
+
+    #[test]
+    fn spinner_goldens() {
+        for step in [0, 3] {
+            let (r, g, b) = WHITE;
+            assert_golden(&format!("spinner_play_{}", step), ICON_SIZE, &spinner_frame(&play(), step, r, g, b));
+        }
+    }
+
+    #[test]
+    fn output_glyphs_differ_by_class() {
+        let (r, g, b) = WHITE;
+        let classes = [DeviceClass::Headphones, DeviceClass::Speakers, DeviceClass::Display];
+        let glyphs = classes.map(|class| output_glyph(class, r, g, b));
+        for (i, glyph) in glyphs.iter().enumerate() {
+            assert!(glyph.iter().any(|&p| alpha(p) == 0xFF), "{}: nothing drawn", i);
+            for other in &glyphs[i + 1..] {
+                assert_ne!(glyph, other);
+            }
+        }
+        assert_eq!(output_glyph(DeviceClass::Other, r, g, b), glyphs[1]);
+    }
+
+    #[test]
+    fn speed_glyph_goldens() {
+        let (r, g, b) = WHITE;
+        for (speed, name) in [(1.0, "1"), (1.25, "125"), (1.5, "15"), (2.0, "2")] {
+            assert_golden(&format!("speed_{}", name), ICON_SIZE, &speed_glyph(speed, r, g, b));
+        }
+    }
+
+    #[test]
+    fn text_is_laid_out_from_the_font() {
+        let row = |text: &str, row: usize| -> String {
+            text_cells(text)[row].iter().map(|&lit| if lit { '#' } else { '.' }).collect()
+        };
+        assert_eq!(row("1.5x", 0), ".#....###....");
+        assert_eq!(row("1.5x", 4), "###.#.###.#.#");
+        // Unknown characters are left out
+        assert_eq!(row("2?", 2), "###");
+        assert!(text_cells("").iter().all(Vec::is_empty));
+
+        let mut pixels = vec![0; ICON_PIXELS];
+        draw_text(&mut pixels, "?", 255, 255, 255);
+        assert!(pixels.iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    fn spinner_cycles_through_its_dots() {
+        let (r, g, b) = WHITE;
+        let frames: Vec<Vec<u32>> = (0..SPINNER_DOTS).map(|step| spinner_frame(&play(), step, r, g, b)).collect();
+        for (i, frame) in frames.iter().enumerate() {
+            for other in &frames[i + 1..] {
+                assert_ne!(frame, other);
+            }
+        }
+        assert_eq!(spinner_frame(&play(), SPINNER_DOTS, r, g, b), frames[0]);
+    }
+
+    #[test]
+    fn play_frame_shows_the_crossfade_in_progress() {
+        use crate::config::Settings;
+        use crate::core::Fade;
+        use std::time::Duration;
+
+        let start = Instant::now();
+        let mut state = State::new(Settings { show_play: true, animations: true, ..Settings::default() });
+        state.playing = true;
+        state.fade = Some(Fade { from_playing: false, start });
+        let halfway = start + CROSSFADE_DURATION / 2;
+        assert_eq!(play_frame(&state, halfway), crossfade_frame(&play(), &pause(), 0.5));
+        assert_eq!(play_frame(&state, start + CROSSFADE_DURATION + Duration::from_millis(1)), pause());
+
+        state.settings.animations = false;
+        assert_eq!(play_frame(&state, halfway), pause());
+    }
+
+    #[test]
+    fn sleep_badge_goldens() {
+        let (r, g, b) = WHITE;
+        for (remaining, name) in [(1.0, "100"), (0.25, "25")] {
+            let mut pixels = pause();
+            draw_sleep_badge(&mut pixels, remaining, r, g, b);
+            assert_golden(&format!("sleep_badge_pause_{}", name), ICON_SIZE, &pixels);
+        }
+    }
+
+    #[test]
+    fn sleep_badge_only_shows_while_armed() {
+        use crate::config::Settings;
+        use crate::sleep::SleepChoice;
+
+        let now = Instant::now();
+        let mut state = State::new(Settings { show_play: true, show_next: true, ..Settings::default() });
+        state.playing = true;
+        assert_eq!(icon_pixels(&state, TrayIcon::Play, now), pause());
+        state.sleep.arm(SleepChoice::Minutes(30), now, None);
+        assert_ne!(icon_pixels(&state, TrayIcon::Play, now), pause());
+        // Only one icon carries it
+        assert_eq!(icon_pixels(&state, TrayIcon::Next, now), next_glyph(WHITE.0, WHITE.1, WHITE.2));
+    }
+
+    #[test]
+    fn art_icon_keeps_the_aspect_ratio() {
+        // 64x32 art, left half red, right half blue
+        let pixels = (0..64 * 32).map(|i| if i % 64 < 32 { 0xFFFF0000 } else { 0xFF0000FF }).collect();
+        let icon = art_icon(&AlbumArt { width: 64, height: 32, pixels });
+        let at = |x: i32, y: i32| icon[(y * ICON_SIZE + x) as usize];
+        // Scaled to 32x16 and centered vertically
+        assert_eq!((at(0, 7), at(0, 8), at(15, 8), at(16, 8), at(31, 23), at(31, 24)), (0, 0xFFFF0000, 0xFFFF0000, 0xFF0000FF, 0xFF0000FF, 0));
+
+        assert_eq!(art_icon(&AlbumArt { width: 2, height: 2, pixels: vec![] }), vec![0; ICON_PIXELS]);
+    }
+}
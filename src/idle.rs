@@ -0,0 +1,34 @@
+// ============== Input Idle Time ==============
+
+use std::mem::size_of;
+use std::time::Duration;
+
+use windows::Win32::System::SystemInformation::GetTickCount;
+use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+use windows::Win32::UI::Shell::{
+    SHQueryUserNotificationState, QUNS_BUSY, QUNS_PRESENTATION_MODE, QUNS_RUNNING_D3D_FULL_SCREEN,
+};
+
+use crate::autopause::{IdleSample, IdleSource};
+
+/// Idle time from the last input the system saw. Full-screen detection uses
+/// the shell's notification state, which is what keeps toasts out of movies.
+pub struct SystemIdle;
+
+impl IdleSource for SystemIdle {
+    fn sample(&self) -> Option<IdleSample> {
+        unsafe {
+            let mut info = LASTINPUTINFO { cbSize: size_of::<LASTINPUTINFO>() as u32, dwTime: 0 };
+            if !GetLastInputInfo(&mut info).as_bool() {
+                return None;
+            }
+            // Both are 32-bit tick counts, so wrapping after 49 days cancels out
+            let idle = Duration::from_millis(GetTickCount().wrapping_sub(info.dwTime) as u64);
+            let fullscreen = matches!(
+                SHQueryUserNotificationState(),
+                Ok(QUNS_BUSY | QUNS_RUNNING_D3D_FULL_SCREEN | QUNS_PRESENTATION_MODE)
+            );
+            Some(IdleSample { idle, fullscreen })
+        }
+    }
+}
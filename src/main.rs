@@ -1,90 +1,185 @@
 #![windows_subsystem = "windows"]
 
-use std::env;
-use std::fs;
+mod audio;
+mod autopause;
+mod bindings;
+mod capture;
+mod config;
+mod consent;
+mod core;
+mod flyout;
+mod i18n;
+mod icons;
+mod idle;
+mod media;
+mod menu;
+mod mixer;
+mod output;
+mod sessions;
+mod sleep;
+mod worker;
+
+use std::cell::RefCell;
 use std::mem::{size_of, zeroed};
-use std::path::PathBuf;
 use std::ptr::null_mut;
-use windows::core::w;
-use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM, HMODULE, POINT};
+use std::sync::atomic::{AtomicIsize, AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use windows::core::{w, HSTRING, PCWSTR};
+use windows::Win32::Foundation::{COLORREF, HANDLE, HWND, LPARAM, LRESULT, WPARAM, HMODULE, POINT, RECT};
+use windows::Win32::Globalization::GetUserDefaultUILanguage;
+use windows::Win32::System::Com::{CoInitializeEx, COINIT_APARTMENTTHREADED};
 use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::System::Power::{
+    RegisterPowerSettingNotification, UnregisterPowerSettingNotification, HPOWERNOTIFY, POWERBROADCAST_SETTING,
+};
+use windows::Win32::System::RemoteDesktop::{
+    WTSRegisterSessionNotification, WTSUnRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+};
+use windows::Win32::System::SystemServices::GUID_CONSOLE_DISPLAY_STATE;
 use windows::Win32::System::Registry::{
     RegOpenKeyExW, RegQueryValueExW, HKEY, HKEY_CURRENT_USER, KEY_READ, REG_VALUE_TYPE,
 };
 use windows::Win32::UI::Input::KeyboardAndMouse::{
-    SendInput, INPUT, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS, KEYEVENTF_KEYUP,
-    VIRTUAL_KEY, VK_MEDIA_NEXT_TRACK, VK_MEDIA_PLAY_PAUSE, VK_MEDIA_PREV_TRACK,
+    GetDoubleClickTime, GetKeyState, SendInput, INPUT, INPUT_KEYBOARD, KEYBDINPUT,
+    KEYBD_EVENT_FLAGS, KEYEVENTF_KEYUP, VIRTUAL_KEY, VK_CONTROL, VK_MEDIA_NEXT_TRACK,
+    VK_ESCAPE, VK_MEDIA_PLAY_PAUSE, VK_MEDIA_PREV_TRACK, VK_MEDIA_STOP, VK_SHIFT, VK_VOLUME_MUTE,
 };
 use windows::Win32::UI::Shell::{
-    Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE, NIM_MODIFY,
-    NOTIFYICONDATAW,
+    ShellExecuteW, Shell_NotifyIconGetRect, Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_MESSAGE,
+    NIF_SHOWTIP, NIF_TIP, NIIF_LARGE_ICON, NIIF_NOSOUND, NIIF_USER, NIM_ADD, NIM_DELETE,
+    NIM_MODIFY, NIM_SETVERSION, NINF_KEY, NIN_POPUPCLOSE, NIN_POPUPOPEN, NIN_SELECT,
+    NOTIFYICONDATAW, NOTIFYICONIDENTIFIER, NOTIFYICON_VERSION_4,
 };
 use windows::Win32::UI::WindowsAndMessaging::*;
 use windows::Win32::Graphics::Gdi::{
-    CreateCompatibleDC, CreateDIBSection, DeleteDC, DeleteObject,
-    BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS,
-};
-use windows::Media::Control::{
-    GlobalSystemMediaTransportControlsSessionManager,
-    GlobalSystemMediaTransportControlsSessionPlaybackStatus,
+    BeginPaint, BitBlt, CreateCompatibleBitmap, CreateCompatibleDC, CreateDIBSection, CreateFontW,
+    CreateSolidBrush, DeleteDC, DeleteObject, DrawTextW, EndPaint, FillRect, GetDC, GetDeviceCaps,
+    GetMonitorInfoW, InvalidateRect, MonitorFromRect, ReleaseDC, SelectObject, SetBkMode,
+    SetTextColor, StretchDIBits, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, CLEARTYPE_QUALITY,
+    CLIP_DEFAULT_PRECIS, DEFAULT_CHARSET, DEFAULT_PITCH, DIB_RGB_COLORS, DT_END_ELLIPSIS,
+    DT_NOPREFIX, DT_SINGLELINE, DT_VCENTER, FONT_WEIGHT, FW_NORMAL, FW_SEMIBOLD, HDC, LOGPIXELSY,
+    MONITORINFO, MONITOR_DEFAULTTONEAREST, OUT_DEFAULT_PRECIS, PAINTSTRUCT, SRCCOPY, TRANSPARENT,
 };
 
-static mut MEDIA_MANAGER: Option<GlobalSystemMediaTransportControlsSessionManager> = None;
+use crate::autopause::{Away, IdleSource};
+use crate::capture::CaptureDetector;
+use crate::bindings::Gesture;
+use crate::flyout::FlyoutLayout;
+use crate::i18n::{Language, Msg};
+use crate::core::{
+    Effect, Event, MediaKey, MenuCommand, RepeatMode, State, Timer, TrayIcon,
+    ANIMATION_FRAME_MS, POLL_INTERVAL_MS, PROGRESS_INTERVAL_MS,
+};
+use crate::icons::{ICON_PIXELS, ICON_SIZE};
+use crate::media::{MediaCommand, MediaUpdate, MediaWorker};
+use crate::menu::MenuItem;
+use crate::mixer::MixerBackend;
+use crate::output::OutputBackend;
+use crate::sleep::SLEEP_TICK_MS;
 
 const WM_TRAYICON: u32 = WM_USER + 1;
+const NIN_KEYSELECT: u32 = NIN_SELECT | NINF_KEY;
+// Enter sends NIN_KEYSELECT twice; the second one is dropped
+const KEY_SELECT_REPEAT: Duration = Duration::from_millis(200);
+// Icons may be dragged around without a rebuild; moving the cursor over one
+// re-reads their positions at most this often
+const ICON_RECTS_REFRESH: Duration = Duration::from_millis(500);
+// Posted by the media worker when updates are waiting
+const WM_MEDIA_UPDATE: u32 = WM_USER + 2;
+// Posted by the mouse hook for wheel events over an icon; wparam is the delta
+// in the low word and the icon ID in the high word
+const WM_WHEEL_INPUT: u32 = WM_USER + 3;
+// Posted from an audio notification thread when the output devices change
+const WM_OUTPUT_CHANGED: u32 = WM_USER + 4;
 const TIMER_ID_PLAYBACK: usize = 1;
+const TIMER_ID_ANIMATION: usize = 2;
+const TIMER_ID_PROGRESS: usize = 3;
+const TIMER_ID_CLICK: usize = 4;
+const TIMER_ID_SLEEP: usize = 5;
 
 const ICON_ID_DEFAULT: u32 = 1;
 const ICON_ID_PREV: u32 = 2;
 const ICON_ID_PLAY: u32 = 3;
 const ICON_ID_NEXT: u32 = 4;
-
-const MENU_SHOW_PREV: u16 = 101;
-const MENU_SHOW_PLAY: u16 = 102;
-const MENU_SHOW_NEXT: u16 = 103;
-const MENU_EXIT: u16 = 105;
-
-#[derive(Clone, Copy, Default, PartialEq)]
-struct AppSettings {
-    show_prev: bool,
-    show_play: bool,
-    show_next: bool,
-    dark_icons: bool,
+const ICON_ID_SHUFFLE: u32 = 5;
+const ICON_ID_REPEAT: u32 = 6;
+const ICON_ID_OUTPUT: u32 = 7;
+const ICON_ID_SPEED: u32 = 8;
+
+const FLYOUT_CLASS: PCWSTR = w!("ClickPlayFlyout");
+
+// Window that receives WM_WHEEL_INPUT; the hook procedure has no other way to find it
+static WHEEL_TARGET: AtomicIsize = AtomicIsize::new(0);
+// Screen rectangles of the visible icons by icon ID, so the hook can ignore
+// wheel events elsewhere without asking the shell each time
+static ICON_RECTS: Mutex<Vec<(u32, RECT)>> = Mutex::new(Vec::new());
+// Sent to every top-level window when Explorer (re)starts
+static TASKBAR_CREATED: AtomicU32 = AtomicU32::new(0);
+
+/// Everything the window needs. Owned by the window through `GWLP_USERDATA`
+/// and freed on `WM_NCDESTROY`.
+struct App {
+    hwnd: HWND,
+    media: Option<MediaWorker>,
+    wheel_hook: Option<HHOOK>,
+    last_key_select: Option<(TrayIcon, Instant)>,
+    icon_rects_at: Option<Instant>,
+    flyout: Option<HWND>,
+    flyout_layout: Option<FlyoutLayout>,
+    /// The playing app's sessions the sleep timer is fading, with their levels
+    /// from before the fade
+    sleep_fade: Option<mixer::AppFade>,
+    /// Display on/off notifications
+    display_notify: Option<HPOWERNOTIFY>,
+    output_watcher: Option<audio::OutputWatcher>,
+    state: State,
 }
 
-static mut APP_SETTINGS: AppSettings = AppSettings {
-    show_prev: false,
-    show_play: false,
-    show_next: false,
-    dark_icons: false,
-};
-static mut MAIN_HWND: HWND = HWND(null_mut());
-static mut H_MODULE: HMODULE = HMODULE(null_mut());
-static mut IS_PLAYING: bool = false;
-static mut LAST_THEME_DARK: bool = true;
-static mut THEME_CHECK_COUNTER: u32 = 0;
-
 fn main() {
     unsafe {
-        H_MODULE = GetModuleHandleW(None).unwrap_or(HMODULE(null_mut()));
-        APP_SETTINGS = load_settings();
-        
-        let system_uses_dark = !is_system_light_theme();
-        LAST_THEME_DARK = system_uses_dark;
-        APP_SETTINGS.dark_icons = !system_uses_dark;
+        let h_module = GetModuleHandleW(None).unwrap_or(HMODULE(null_mut()));
+        // For the endpoint volume; the media worker sets up its own thread
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+        let app = Box::new(RefCell::new(App {
+            hwnd: HWND(null_mut()),
+            media: None,
+            wheel_hook: None,
+            last_key_select: None,
+            icon_rects_at: None,
+            flyout: None,
+            flyout_layout: None,
+            sleep_fade: None,
+            display_notify: None,
+            output_watcher: None,
+            state: State::new(config::load_settings()),
+        }));
+
+        TASKBAR_CREATED.store(RegisterWindowMessageW(w!("TaskbarCreated")), Ordering::Relaxed);
 
         let class_name = w!("ClickPlayClass");
         let wc = WNDCLASSEXW {
             cbSize: size_of::<WNDCLASSEXW>() as u32,
             lpfnWndProc: Some(window_proc),
-            hInstance: H_MODULE.into(),
+            hInstance: h_module.into(),
             lpszClassName: class_name,
             ..zeroed()
         };
 
         RegisterClassExW(&wc);
 
-        MAIN_HWND = CreateWindowExW(
+        let flyout_class = WNDCLASSEXW {
+            cbSize: size_of::<WNDCLASSEXW>() as u32,
+            style: CS_DROPSHADOW,
+            lpfnWndProc: Some(flyout_proc),
+            hInstance: h_module.into(),
+            hCursor: LoadCursorW(None, IDC_ARROW).unwrap_or_default(),
+            lpszClassName: FLYOUT_CLASS,
+            ..zeroed()
+        };
+        RegisterClassExW(&flyout_class);
+
+        let hwnd = CreateWindowExW(
             WINDOW_EX_STYLE::default(),
             class_name,
             w!("ClickPlay"),
@@ -92,100 +187,394 @@ fn main() {
             0, 0, 0, 0,
             None,
             None,
-            H_MODULE,
-            None,
+            h_module,
+            Some(Box::into_raw(app) as *const std::ffi::c_void),
         ).unwrap_or(HWND(null_mut()));
 
-        // Initialize media session manager once
-        MEDIA_MANAGER = init_media_manager();
-
-        IS_PLAYING = check_media_playing();
-        update_tray_icons();
-
-        // Single timer at 500ms handles both playback and theme checks
-        SetTimer(MAIN_HWND, TIMER_ID_PLAYBACK, 500, None);
+        if let Some(app) = app_from_hwnd(hwnd) {
+            // The worker owns the media session manager; it only talks back
+            // by posting WM_MEDIA_UPDATE, so the HWND crosses as an integer.
+            let hwnd_raw = hwnd.0 as isize;
+            let rules = app.borrow().state.settings.session_rules.clone();
+            app.borrow_mut().media = Some(MediaWorker::spawn(rules, move || {
+                let hwnd = HWND(hwnd_raw as *mut std::ffi::c_void);
+                let _ = PostMessageW(hwnd, WM_MEDIA_UPDATE, WPARAM(0), LPARAM(0));
+            }));
+
+            dispatch(app, Event::Init {
+                system_dark: !is_system_light_theme(),
+                system_language: Language::from_langid(GetUserDefaultUILanguage()),
+            });
+
+            // Single poll timer handles both playback and theme checks
+            SetTimer(hwnd, TIMER_ID_PLAYBACK, POLL_INTERVAL_MS, None);
+
+            // Lock/unlock and display state, for pausing while away. Suspend
+            // and resume arrive as WM_POWERBROADCAST without registering.
+            let _ = WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION);
+            app.borrow_mut().display_notify = RegisterPowerSettingNotification(
+                HANDLE(hwnd.0),
+                &GUID_CONSOLE_DISPLAY_STATE,
+                DEVICE_NOTIFY_WINDOW_HANDLE,
+            ).ok();
+            app.borrow_mut().output_watcher = audio::OutputWatcher::start(move || {
+                let hwnd = HWND(hwnd_raw as *mut std::ffi::c_void);
+                let _ = PostMessageW(hwnd, WM_OUTPUT_CHANGED, WPARAM(0), LPARAM(0));
+            });
+            dispatch(app, Event::Outputs(audio::SystemOutputs.outputs()));
+        }
 
         let mut msg: MSG = zeroed();
         while GetMessageW(&mut msg, None, 0, 0).as_bool() {
             let _ = TranslateMessage(&msg);
             DispatchMessageW(&msg);
         }
-
-        let _ = KillTimer(MAIN_HWND, TIMER_ID_PLAYBACK);
-        MEDIA_MANAGER = None;
-        remove_all_icons();
     }
 }
 
+unsafe fn app_from_hwnd<'a>(hwnd: HWND) -> Option<&'a RefCell<App>> {
+    let ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *const RefCell<App>;
+    ptr.as_ref()
+}
+
 unsafe extern "system" fn window_proc(
     hwnd: HWND,
     msg: u32,
     wparam: WPARAM,
     lparam: LPARAM,
 ) -> LRESULT {
+    if msg == WM_NCCREATE {
+        let create = &*(lparam.0 as *const CREATESTRUCTW);
+        let app = create.lpCreateParams as *const RefCell<App>;
+        (*app).borrow_mut().hwnd = hwnd;
+        SetWindowLongPtrW(hwnd, GWLP_USERDATA, app as isize);
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    }
+
+    let Some(app) = app_from_hwnd(hwnd) else {
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    };
+
     match msg {
         WM_TIMER => {
-            let timer_id = wparam.0;
-            if timer_id == TIMER_ID_PLAYBACK {
-                // Check playback state every tick (500ms)
-                let playing = check_media_playing();
-                if playing != IS_PLAYING {
-                    IS_PLAYING = playing;
-                    if APP_SETTINGS.show_play {
-                        update_play_icon_only();
-                    }
+            let now = Instant::now();
+            match wparam.0 {
+                TIMER_ID_PLAYBACK => dispatch(app, Event::Tick),
+                TIMER_ID_ANIMATION => dispatch(app, Event::AnimationFrame { now }),
+                TIMER_ID_PROGRESS => dispatch(app, Event::ProgressTick),
+                TIMER_ID_CLICK => dispatch(app, Event::ClickTimeout { now }),
+                TIMER_ID_SLEEP => dispatch(app, Event::SleepTick { now, wall: SystemTime::now() }),
+                _ => {}
+            }
+            LRESULT(0)
+        }
+        WM_TRAYICON => {
+            // Version 4 layout: event in the low word of lparam, icon ID in the
+            // high word; wparam holds anchor coordinates, which we don't need.
+            let tray_event = (lparam.0 & 0xFFFF) as u32;
+            let Some(icon) = tray_icon_from_id(((lparam.0 >> 16) & 0xFFFF) as u32) else {
+                return LRESULT(0);
+            };
+            let now = Instant::now();
+
+            let gesture = match tray_event {
+                WM_LBUTTONUP if GetKeyState(VK_CONTROL.0 as i32) < 0 => Some(Gesture::CtrlLeft),
+                WM_LBUTTONUP if GetKeyState(VK_SHIFT.0 as i32) < 0 => Some(Gesture::ShiftLeft),
+                WM_LBUTTONUP => Some(Gesture::Left),
+                WM_LBUTTONDBLCLK => Some(Gesture::Double),
+                WM_MBUTTONUP => Some(Gesture::Middle),
+                // Right-click as well as Shift+F10 and the menu key
+                WM_CONTEXTMENU => Some(Gesture::Right),
+                // Enter or space on a focused icon acts like a left click
+                NIN_KEYSELECT => {
+                    let mut app = app.borrow_mut();
+                    let repeat = app.last_key_select
+                        .is_some_and(|(last, at)| last == icon && now.duration_since(at) < KEY_SELECT_REPEAT);
+                    app.last_key_select = Some((icon, now));
+                    (!repeat).then_some(Gesture::Left)
                 }
-
-                // Check theme every ~4th tick (~2s)
-                THEME_CHECK_COUNTER += 1;
-                if THEME_CHECK_COUNTER >= 4 {
-                    THEME_CHECK_COUNTER = 0;
-                    let system_dark = !is_system_light_theme();
-                    if system_dark != LAST_THEME_DARK {
-                        LAST_THEME_DARK = system_dark;
-                        APP_SETTINGS.dark_icons = !system_dark;
-                        update_tray_icons();
-                        save_settings();
+                WM_MOUSEMOVE => {
+                    let stale = app.borrow().icon_rects_at.is_none_or(|at| now.duration_since(at) >= ICON_RECTS_REFRESH);
+                    if stale {
+                        refresh_icon_rects(app);
                     }
+                    None
+                }
+                NIN_POPUPOPEN => {
+                    dispatch(app, Event::Hover { icon, open: true });
+                    None
                 }
+                NIN_POPUPCLOSE => {
+                    dispatch(app, Event::Hover { icon, open: false });
+                    None
+                }
+                _ => None,
+            };
+            if let Some(gesture) = gesture {
+                dispatch(app, Event::Mouse { now, icon, gesture });
             }
             LRESULT(0)
         }
-        WM_TRAYICON => {
-            let icon_id = wparam.0 as u32;
-            let mouse_msg = (lparam.0 & 0xFFFF) as u32;
-
-            match mouse_msg {
-                WM_LBUTTONUP => handle_left_click(icon_id),
-                WM_RBUTTONUP => show_context_menu(hwnd),
+        WM_WHEEL_INPUT => {
+            let delta = (wparam.0 & 0xFFFF) as i16 as i32;
+            let icon = tray_icon_from_id(((wparam.0 >> 16) & 0xFFFF) as u32);
+            if let Some(icon) = icon.filter(|icon| app.borrow().state.visible_icons().contains(icon)) {
+                dispatch(app, Event::Wheel { now: Instant::now(), icon, delta });
+            }
+            LRESULT(0)
+        }
+        WM_WTSSESSION_CHANGE => {
+            match wparam.0 as u32 {
+                WTS_SESSION_LOCK => dispatch(app, Event::Away { reason: Away::Locked, active: true }),
+                WTS_SESSION_UNLOCK => dispatch(app, Event::Away { reason: Away::Locked, active: false }),
                 _ => {}
             }
             LRESULT(0)
         }
-        WM_COMMAND => {
-            let menu_id = (wparam.0 & 0xFFFF) as u16;
-            handle_menu_command(menu_id);
+        WM_POWERBROADCAST => {
+            match wparam.0 as u32 {
+                PBT_APMSUSPEND => dispatch(app, Event::Away { reason: Away::Suspended, active: true }),
+                PBT_APMRESUMEAUTOMATIC => {
+                    // Timers don't fire while suspended; catch the sleep timer up right away
+                    if app.borrow().state.sleep.active() {
+                        dispatch(app, Event::SleepTick { now: Instant::now(), wall: SystemTime::now() });
+                    }
+                    dispatch(app, Event::Away { reason: Away::Suspended, active: false });
+                }
+                PBT_POWERSETTINGCHANGE => {
+                    let setting = &*(lparam.0 as *const POWERBROADCAST_SETTING);
+                    if setting.PowerSetting == GUID_CONSOLE_DISPLAY_STATE {
+                        // 0 is off, 1 on and 2 dimmed
+                        let off = setting.Data[0] == 0;
+                        dispatch(app, Event::Away { reason: Away::DisplayOff, active: off });
+                    }
+                }
+                _ => {}
+            }
+            LRESULT(1)
+        }
+        WM_OUTPUT_CHANGED => {
+            dispatch(app, Event::Outputs(audio::SystemOutputs.outputs()));
+            LRESULT(0)
+        }
+        WM_MEDIA_UPDATE => {
+            let updates = app.borrow().media.as_ref().map(|m| m.drain()).unwrap_or_default();
+            for update in updates {
+                dispatch(app, media_event(update));
+            }
             LRESULT(0)
         }
         WM_DESTROY => {
+            let _ = KillTimer(hwnd, TIMER_ID_PLAYBACK);
+            let _ = KillTimer(hwnd, TIMER_ID_ANIMATION);
+            let _ = KillTimer(hwnd, TIMER_ID_PROGRESS);
+            let _ = KillTimer(hwnd, TIMER_ID_CLICK);
+            let _ = KillTimer(hwnd, TIMER_ID_SLEEP);
+            let _ = WTSUnRegisterSessionNotification(hwnd);
+            if let Some(notify) = app.borrow_mut().display_notify.take() {
+                let _ = UnregisterPowerSettingNotification(notify);
+            }
+            if let Some(watcher) = app.borrow_mut().output_watcher.take() {
+                watcher.stop();
+            }
+            set_wheel_hook(app, false);
+            remove_all_icons(hwnd);
             PostQuitMessage(0);
             LRESULT(0)
         }
+        WM_NCDESTROY => {
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
+            drop(Box::from_raw(app as *const RefCell<App> as *mut RefCell<App>));
+            DefWindowProcW(hwnd, msg, wparam, lparam)
+        }
+        // Explorer restarted and forgot our icons
+        _ if msg != 0 && msg == TASKBAR_CREATED.load(Ordering::Relaxed) => {
+            update_tray_icons(app);
+            refresh_icon_rects(app);
+            LRESULT(0)
+        }
         _ => DefWindowProcW(hwnd, msg, wparam, lparam),
     }
 }
 
-unsafe fn handle_left_click(icon_id: u32) {
-    match icon_id {
-        ICON_ID_DEFAULT => show_context_menu(MAIN_HWND),
-        ICON_ID_PREV => send_media_key(VK_MEDIA_PREV_TRACK),
-        ICON_ID_PLAY => {
-            send_media_key(VK_MEDIA_PLAY_PAUSE);
-            IS_PLAYING = !IS_PLAYING;
-            update_play_icon_only();
+// ============== Event Dispatch ==============
+
+/// Runs an event through the core and performs the resulting effects. The
+/// state is only borrowed while `update` runs, so effects may re-enter
+/// `window_proc` (e.g. the menu's modal loop) without conflict.
+unsafe fn dispatch(app: &RefCell<App>, event: Event) {
+    let effects = {
+        let mut app = app.borrow_mut();
+        let state = std::mem::take(&mut app.state);
+        let (state, effects) = core::update(state, event);
+        app.state = state;
+        effects
+    };
+
+    for effect in effects {
+        run_effect(app, effect);
+    }
+}
+
+fn media_event(update: MediaUpdate) -> Event {
+    let now = Instant::now();
+    match update {
+        MediaUpdate::Status(status) => Event::Status { now, status },
+        MediaUpdate::Track(track) => Event::Track { now, track },
+        MediaUpdate::Modes(modes) => Event::Modes(modes),
+        MediaUpdate::Timeline(timeline) => Event::Timeline { now, timeline },
+        MediaUpdate::Art(art) => Event::Art(art),
+        MediaUpdate::ShuffleChanged(active) => Event::ShuffleChanged(active),
+        MediaUpdate::RepeatChanged(mode) => Event::RepeatChanged(mode),
+        MediaUpdate::SpeedChanged(speed) => Event::SpeedChanged(speed),
+        MediaUpdate::Player(app_id) => Event::PlayerApp(app_id),
+        MediaUpdate::Paused(sessions) => Event::Paused(sessions),
+    }
+}
+
+unsafe fn send_media_command(app: &RefCell<App>, command: MediaCommand) {
+    if let Some(media) = app.borrow_mut().media.as_mut() {
+        media.send(command);
+    }
+}
+
+unsafe fn run_effect(app: &RefCell<App>, effect: Effect) {
+    let hwnd = app.borrow().hwnd;
+
+    match effect {
+        Effect::QueryStatus => send_media_command(app, MediaCommand::QueryStatus),
+        Effect::QueryTrack => send_media_command(app, MediaCommand::QueryTrack),
+        Effect::QueryModes => send_media_command(app, MediaCommand::QueryModes),
+        Effect::QueryTimeline => send_media_command(app, MediaCommand::QueryTimeline),
+        Effect::QueryArt => send_media_command(app, MediaCommand::QueryArt),
+        Effect::QueryTheme => {
+            dispatch(app, Event::SystemTheme { dark: !is_system_light_theme() });
+        }
+        Effect::QueryIdle => {
+            if let Some(sample) = idle::SystemIdle.sample() {
+                dispatch(app, Event::Idle(sample));
+            }
+        }
+        Effect::QueryCapture => dispatch(app, Event::Capture(consent::ConsentStore.snapshot())),
+        Effect::QueryMixer => dispatch(app, Event::Mixer(audio::SystemMixer.sessions())),
+        Effect::SendKey(key) => send_media_key(match key {
+            MediaKey::Prev => VK_MEDIA_PREV_TRACK,
+            MediaKey::PlayPause => VK_MEDIA_PLAY_PAUSE,
+            MediaKey::Next => VK_MEDIA_NEXT_TRACK,
+            MediaKey::Stop => VK_MEDIA_STOP,
+            MediaKey::Mute => VK_VOLUME_MUTE,
+        }),
+        Effect::SetShuffle(active) => send_media_command(app, MediaCommand::SetShuffle(active)),
+        Effect::SetRepeat(mode) => send_media_command(app, MediaCommand::SetRepeat(mode)),
+        Effect::SetSpeed(speed) => send_media_command(app, MediaCommand::SetSpeed(speed)),
+        Effect::Seek(seconds) => send_media_command(app, MediaCommand::Seek(seconds)),
+        Effect::SeekTo(fraction) => send_media_command(app, MediaCommand::SeekTo(fraction)),
+        Effect::OpenPlayer => send_media_command(app, MediaCommand::QueryPlayer),
+        Effect::Control(key) => send_media_command(app, MediaCommand::Control(key)),
+        Effect::PauseAll => send_media_command(app, MediaCommand::PauseAll),
+        Effect::Resume(sessions) => send_media_command(app, MediaCommand::Resume(sessions)),
+        Effect::Pause => send_media_command(app, MediaCommand::Pause),
+        Effect::FadeVolume { app_id, level } => {
+            let fade = app.borrow_mut().sleep_fade.take();
+            if let Some(mut fade) = fade.or_else(|| mixer::AppFade::start(&audio::SystemMixer, &app_id)) {
+                fade.step(&audio::SystemMixer, level);
+                app.borrow_mut().sleep_fade = Some(fade);
+            }
+        }
+        Effect::RestoreVolume => {
+            let fade = app.borrow_mut().sleep_fade.take();
+            if let Some(fade) = fade {
+                fade.restore(&audio::SystemMixer);
+            }
+        }
+        Effect::SetOutput(id) => audio::SystemOutputs.set_default(&id),
+        Effect::AppVolume { process, change } => mixer::apply(&audio::SystemMixer, &process, change),
+        Effect::LaunchApp(app_id) => launch_app(hwnd, &app_id),
+        Effect::RebuildIcons => {
+            update_tray_icons(app);
+            refresh_icon_rects(app);
+        }
+        Effect::RedrawIcon(icon) => {
+            let redrawn = {
+                let app = app.borrow();
+                app.state.visible_icons().contains(&icon).then(|| create_tray_icon(&app.state, icon))
+            };
+            if let Some((hicon, tip)) = redrawn {
+                modify_tray_icon(hwnd, tray_icon_id(icon), hicon, &tip);
+            }
+        }
+        Effect::ShowMenu(icon) => show_context_menu(app, icon),
+        Effect::ShowBalloon { title, text, art } => {
+            let first = app.borrow().state.visible_icons()[0];
+            show_balloon(hwnd, tray_icon_id(first), &title, &text, art.as_ref());
+        }
+        Effect::OpenFlyout(icon) => open_flyout(app, icon),
+        Effect::CloseFlyout => {
+            let flyout = app.borrow().flyout;
+            if let Some(flyout) = flyout {
+                let _ = PostMessageW(flyout, WM_CLOSE, WPARAM(0), LPARAM(0));
+            }
+        }
+        Effect::RedrawFlyout => {
+            let flyout = app.borrow().flyout;
+            if let Some(flyout) = flyout {
+                let _ = InvalidateRect(flyout, None, false);
+            }
+        }
+        Effect::StartTimer(timer) => {
+            let (id, interval) = match timer {
+                Timer::Animation => (TIMER_ID_ANIMATION, ANIMATION_FRAME_MS),
+                Timer::Progress => (TIMER_ID_PROGRESS, PROGRESS_INTERVAL_MS),
+                Timer::Click => (TIMER_ID_CLICK, GetDoubleClickTime()),
+                Timer::Sleep => (TIMER_ID_SLEEP, SLEEP_TICK_MS),
+            };
+            SetTimer(hwnd, id, interval, None);
+        }
+        Effect::StopTimer(timer) => {
+            let id = match timer {
+                Timer::Animation => TIMER_ID_ANIMATION,
+                Timer::Progress => TIMER_ID_PROGRESS,
+                Timer::Click => TIMER_ID_CLICK,
+                Timer::Sleep => TIMER_ID_SLEEP,
+            };
+            let _ = KillTimer(hwnd, id);
+        }
+        Effect::WheelHook(enabled) => set_wheel_hook(app, enabled),
+        Effect::SaveSettings => {
+            let settings = app.borrow().state.settings.clone();
+            config::save_settings(&settings);
+        }
+        Effect::Exit => {
+            // Destroy from the queue, not from inside an effect loop that still holds `app`
+            let _ = PostMessageW(hwnd, WM_CLOSE, WPARAM(0), LPARAM(0));
         }
-        ICON_ID_NEXT => send_media_key(VK_MEDIA_NEXT_TRACK),
-        _ => {}
+    }
+}
+
+fn tray_icon_id(icon: TrayIcon) -> u32 {
+    match icon {
+        TrayIcon::Default => ICON_ID_DEFAULT,
+        TrayIcon::Prev => ICON_ID_PREV,
+        TrayIcon::Play => ICON_ID_PLAY,
+        TrayIcon::Next => ICON_ID_NEXT,
+        TrayIcon::Shuffle => ICON_ID_SHUFFLE,
+        TrayIcon::Repeat => ICON_ID_REPEAT,
+        TrayIcon::Speed => ICON_ID_SPEED,
+        TrayIcon::Output => ICON_ID_OUTPUT,
+    }
+}
+
+fn tray_icon_from_id(id: u32) -> Option<TrayIcon> {
+    match id {
+        ICON_ID_DEFAULT => Some(TrayIcon::Default),
+        ICON_ID_PREV => Some(TrayIcon::Prev),
+        ICON_ID_PLAY => Some(TrayIcon::Play),
+        ICON_ID_NEXT => Some(TrayIcon::Next),
+        ICON_ID_SHUFFLE => Some(TrayIcon::Shuffle),
+        ICON_ID_REPEAT => Some(TrayIcon::Repeat),
+        ICON_ID_SPEED => Some(TrayIcon::Speed),
+        ICON_ID_OUTPUT => Some(TrayIcon::Output),
+        _ => None,
     }
 }
 
@@ -213,98 +602,152 @@ unsafe fn send_media_key(vk: VIRTUAL_KEY) {
     let _ = SendInput(&inputs, size_of::<INPUT>() as i32);
 }
 
-unsafe fn show_context_menu(hwnd: HWND) {
-    let hmenu = CreatePopupMenu().unwrap();
-
-    let mut flags_prev = MF_STRING;
-    let mut flags_play = MF_STRING;
-    let mut flags_next = MF_STRING;
-
-    if APP_SETTINGS.show_prev { flags_prev |= MF_CHECKED; }
-    if APP_SETTINGS.show_play { flags_play |= MF_CHECKED; }
-    if APP_SETTINGS.show_next { flags_next |= MF_CHECKED; }
-
-    let _ = AppendMenuW(hmenu, flags_prev, MENU_SHOW_PREV as usize, w!("Show Previous"));
-    let _ = AppendMenuW(hmenu, flags_play, MENU_SHOW_PLAY as usize, w!("Show Play/Pause"));
-    let _ = AppendMenuW(hmenu, flags_next, MENU_SHOW_NEXT as usize, w!("Show Next"));
-    let _ = AppendMenuW(hmenu, MF_SEPARATOR, 0, None);
-    let _ = AppendMenuW(hmenu, MF_STRING, MENU_EXIT as usize, w!("Exit"));
+/// Brings up a player by its app ID. Packaged apps go through the shell's
+/// AppsFolder; desktop players report their executable name instead.
+unsafe fn launch_app(hwnd: HWND, app_id: &str) {
+    let (file, params) = if app_id.to_ascii_lowercase().ends_with(".exe") {
+        (HSTRING::from(app_id), HSTRING::new())
+    } else {
+        (HSTRING::from("explorer.exe"), HSTRING::from(format!("shell:AppsFolder\\{}", app_id)))
+    };
+    ShellExecuteW(hwnd, w!("open"), &file, &params, None, SW_SHOWNORMAL);
+}
 
-    let mut pt: POINT = zeroed();
-    let _ = GetCursorPos(&mut pt);
+/// Shows the menu next to `icon`, keeping the icon itself uncovered. Falls
+/// back to the cursor if the shell can't locate the icon. The choice is
+/// returned directly rather than through `WM_COMMAND`.
+unsafe fn show_context_menu(app: &RefCell<App>, icon: TrayIcon) {
+    let (hwnd, items) = {
+        let app = app.borrow();
+        (app.hwnd, menu::context_menu(&app.state))
+    };
+    let mut commands = Vec::new();
+    let hmenu = build_menu(&items, &mut commands);
 
     let _ = SetForegroundWindow(hwnd);
-    let _ = TrackPopupMenu(hmenu, TPM_RIGHTALIGN | TPM_BOTTOMALIGN, pt.x, pt.y, 0, hwnd, None);
+    let flags = TPM_RETURNCMD | TPM_NONOTIFY;
+    let chosen = if let Some(rect) = tray_icon_rect(hwnd, icon) {
+        let params = TPMPARAMS { cbSize: size_of::<TPMPARAMS>() as u32, rcExclude: rect };
+        let flags = flags | TPM_LEFTALIGN | TPM_BOTTOMALIGN | TPM_VERTICAL;
+        TrackPopupMenuEx(hmenu, flags.0, rect.left, rect.top, hwnd, Some(&params))
+    } else {
+        let mut pt: POINT = zeroed();
+        let _ = GetCursorPos(&mut pt);
+        TrackPopupMenu(hmenu, flags | TPM_RIGHTALIGN | TPM_BOTTOMALIGN, pt.x, pt.y, 0, hwnd, None)
+    };
     let _ = PostMessageW(hwnd, WM_NULL, WPARAM(0), LPARAM(0));
-
     let _ = DestroyMenu(hmenu);
+
+    // IDs are 1-based indexes into `commands`; 0 means dismissed
+    if let Some(&command) = (chosen.0 as usize).checked_sub(1).and_then(|i| commands.get(i)) {
+        dispatch(app, Event::Menu { now: Instant::now(), command });
+    }
 }
 
-unsafe fn handle_menu_command(menu_id: u16) {
-    match menu_id {
-        MENU_SHOW_PREV => {
-            APP_SETTINGS.show_prev = !APP_SETTINGS.show_prev;
-            update_tray_icons();
-            save_settings();
-        }
-        MENU_SHOW_PLAY => {
-            APP_SETTINGS.show_play = !APP_SETTINGS.show_play;
-            update_tray_icons();
-            save_settings();
-        }
-        MENU_SHOW_NEXT => {
-            APP_SETTINGS.show_next = !APP_SETTINGS.show_next;
-            update_tray_icons();
-            save_settings();
-        }
-        MENU_EXIT => {
-            let _ = PostMessageW(MAIN_HWND, WM_DESTROY, WPARAM(0), LPARAM(0));
+/// Creates the HMENU for a menu model, collecting each command in ID order.
+unsafe fn build_menu(items: &[MenuItem], commands: &mut Vec<MenuCommand>) -> HMENU {
+    let hmenu = CreatePopupMenu().unwrap();
+
+    for item in items {
+        match item {
+            MenuItem::Header(label) => {
+                let _ = AppendMenuW(hmenu, MF_STRING | MF_GRAYED, 0, &menu_label(label));
+            }
+            MenuItem::Command { label, command, checked, enabled } => {
+                commands.push(*command);
+                let mut flags = MF_STRING;
+                if *checked { flags |= MF_CHECKED; }
+                if !*enabled { flags |= MF_GRAYED; }
+                let _ = AppendMenuW(hmenu, flags, commands.len(), &menu_label(label));
+            }
+            MenuItem::Submenu { label, items } => {
+                let submenu = build_menu(items, commands);
+                let _ = AppendMenuW(hmenu, MF_POPUP, submenu.0 as usize, &menu_label(label));
+            }
+            MenuItem::Separator => {
+                let _ = AppendMenuW(hmenu, MF_SEPARATOR, 0, None);
+            }
         }
-        _ => {}
     }
+
+    hmenu
 }
 
-unsafe fn update_tray_icons() {
-    remove_all_icons();
+/// Escapes `&` so track titles don't turn into keyboard accelerators.
+fn menu_label(label: &str) -> HSTRING {
+    HSTRING::from(label.replace('&', "&&"))
+}
 
-    let any_selected = APP_SETTINGS.show_prev || APP_SETTINGS.show_play || APP_SETTINGS.show_next;
+// ============== Tray Icons ==============
+
+// Shell_NotifyIconW sends to Explorer and handles messages sent back to us
+// while it waits, so `window_proc` may run inside it. `app` is never borrowed
+// across a call into the shell.
+
+unsafe fn update_tray_icons(app: &RefCell<App>) {
+    let (hwnd, icons) = {
+        let app = app.borrow();
+        let icons: Vec<_> = app
+            .state
+            .visible_icons()
+            .into_iter()
+            .map(|icon| (tray_icon_id(icon), create_tray_icon(&app.state, icon)))
+            .collect();
+        (app.hwnd, icons)
+    };
+    remove_all_icons(hwnd);
 
-    if !any_selected {
-        add_tray_icon(ICON_ID_DEFAULT, create_default_icon(), "ClickPlay");
-    } else {
-        if APP_SETTINGS.show_prev {
-            add_tray_icon(ICON_ID_PREV, create_prev_icon(), "Previous");
-        }
-        if APP_SETTINGS.show_play {
-            let (icon, tip) = if IS_PLAYING {
-                (create_pause_icon(), "Pause")
-            } else {
-                (create_play_icon(), "Play")
-            };
-            add_tray_icon(ICON_ID_PLAY, icon, tip);
+    for (id, (hicon, tip)) in icons {
+        add_tray_icon(hwnd, id, hicon, &tip);
+    }
+}
+
+unsafe fn create_tray_icon(state: &State, icon: TrayIcon) -> (HICON, String) {
+    let now = Instant::now();
+    let msg = match icon {
+        TrayIcon::Default => None,
+        TrayIcon::Prev => Some(Msg::Previous),
+        TrayIcon::Play => Some(if state.playing { Msg::Pause } else { Msg::Play }),
+        TrayIcon::Next => Some(Msg::Next),
+        TrayIcon::Shuffle => {
+            Some(if state.modes.shuffle.unwrap_or(false) { Msg::ShuffleOn } else { Msg::ShuffleOff })
         }
-        if APP_SETTINGS.show_next {
-            add_tray_icon(ICON_ID_NEXT, create_next_icon(), "Next");
+        TrayIcon::Repeat => Some(match state.modes.repeat.unwrap_or(RepeatMode::Off) {
+            RepeatMode::Off => Msg::RepeatOff,
+            RepeatMode::Track => Msg::RepeatTrack,
+            RepeatMode::List => Msg::RepeatAll,
+        }),
+        TrayIcon::Speed => Some(Msg::PlaybackSpeed),
+        TrayIcon::Output => None,
+    };
+    let mut tip = match (icon, state.default_output()) {
+        (TrayIcon::Output, Some(device)) => device.name.clone(),
+        (TrayIcon::Speed, _) => format!(
+            "{} ({})",
+            i18n::text(state.language, Msg::PlaybackSpeed),
+            core::speed_label(state.modes.speed.unwrap_or(1.0))
+        ),
+        _ => msg.map(|msg| i18n::text(state.language, msg)).unwrap_or("ClickPlay").to_string(),
+    };
+    // The icon carrying the sleep badge also spells out the time left
+    if state.sleep.armed() && icon == state.sleep_badge_icon() {
+        tip.push('\n');
+        match state.sleep.minutes_left(now) {
+            Some(minutes) if !state.sleep.waits_for_track_end() => {
+                tip.push_str(&i18n::count(state.language, Msg::SleepIn, minutes));
+            }
+            _ => tip.push_str(i18n::text(state.language, Msg::SleepAtTrackEnd)),
         }
     }
+    (create_icon_from_pixels(&icons::icon_pixels(state, icon, now)), tip)
 }
 
-unsafe fn update_play_icon_only() {
-    if !APP_SETTINGS.show_play {
-        return;
-    }
-    
-    let (icon, tip) = if IS_PLAYING {
-        (create_pause_icon(), "Pause")
-    } else {
-        (create_play_icon(), "Play")
-    };
-    
+unsafe fn modify_tray_icon(hwnd: HWND, id: u32, icon: HICON, tip: &str) {
     let mut nid: NOTIFYICONDATAW = zeroed();
     nid.cbSize = size_of::<NOTIFYICONDATAW>() as u32;
-    nid.hWnd = MAIN_HWND;
-    nid.uID = ICON_ID_PLAY;
-    nid.uFlags = NIF_ICON | NIF_TIP;
+    nid.hWnd = hwnd;
+    nid.uID = id;
+    nid.uFlags = NIF_ICON | NIF_TIP | NIF_SHOWTIP;
     nid.hIcon = icon;
     
     let tip_wide: Vec<u16> = tip.encode_utf16().chain(std::iter::once(0)).collect();
@@ -312,14 +755,15 @@ unsafe fn update_play_icon_only() {
     nid.szTip[..len].copy_from_slice(&tip_wide[..len]);
     
     let _ = Shell_NotifyIconW(NIM_MODIFY, &nid);
+    let _ = DestroyIcon(icon);
 }
 
-unsafe fn add_tray_icon(id: u32, icon: HICON, tip: &str) {
+unsafe fn add_tray_icon(hwnd: HWND, id: u32, icon: HICON, tip: &str) {
     let mut nid: NOTIFYICONDATAW = zeroed();
     nid.cbSize = size_of::<NOTIFYICONDATAW>() as u32;
-    nid.hWnd = MAIN_HWND;
+    nid.hWnd = hwnd;
     nid.uID = id;
-    nid.uFlags = NIF_ICON | NIF_MESSAGE | NIF_TIP;
+    nid.uFlags = NIF_ICON | NIF_MESSAGE | NIF_TIP | NIF_SHOWTIP;
     nid.uCallbackMessage = WM_TRAYICON;
     nid.hIcon = icon;
 
@@ -328,42 +772,389 @@ unsafe fn add_tray_icon(id: u32, icon: HICON, tip: &str) {
     nid.szTip[..len].copy_from_slice(&tip_wide[..len]);
 
     let _ = Shell_NotifyIconW(NIM_ADD, &nid);
+    let _ = DestroyIcon(icon);
+
+    // Version 4 must be requested again for every added icon
+    nid.Anonymous.uVersion = NOTIFYICON_VERSION_4;
+    let _ = Shell_NotifyIconW(NIM_SETVERSION, &nid);
+}
+
+unsafe fn show_balloon(hwnd: HWND, id: u32, title: &str, text: &str, art: Option<&core::AlbumArt>) {
+    let mut nid: NOTIFYICONDATAW = zeroed();
+    nid.cbSize = size_of::<NOTIFYICONDATAW>() as u32;
+    nid.hWnd = hwnd;
+    nid.uID = id;
+    nid.uFlags = NIF_INFO;
+    nid.dwInfoFlags = NIIF_NOSOUND;
+    copy_wide(&mut nid.szInfoTitle, title);
+    copy_wide(&mut nid.szInfo, text);
+    // The shell keeps its own copy of the icon
+    let art_icon = art.map(|art| create_icon_from_pixels(&icons::art_icon(art)));
+    if let Some(hicon) = art_icon {
+        nid.dwInfoFlags |= NIIF_USER | NIIF_LARGE_ICON;
+        nid.hBalloonIcon = hicon;
+    }
+
+    let _ = Shell_NotifyIconW(NIM_MODIFY, &nid);
+    if let Some(hicon) = art_icon {
+        let _ = DestroyIcon(hicon);
+    }
+}
+
+/// Copies `text` into a fixed-size, null-terminated UTF-16 buffer, truncating if needed.
+fn copy_wide(dst: &mut [u16], text: &str) {
+    let max = dst.len() - 1;
+    let mut len = 0;
+    for unit in text.encode_utf16().take(max) {
+        dst[len] = unit;
+        len += 1;
+    }
+    dst[len] = 0;
+}
+
+/// Screen rectangle of an icon, in the taskbar or the overflow area.
+unsafe fn tray_icon_rect(hwnd: HWND, icon: TrayIcon) -> Option<RECT> {
+    let identifier = NOTIFYICONIDENTIFIER {
+        cbSize: size_of::<NOTIFYICONIDENTIFIER>() as u32,
+        hWnd: hwnd,
+        uID: tray_icon_id(icon),
+        ..zeroed()
+    };
+    Shell_NotifyIconGetRect(&identifier).ok()
 }
 
-unsafe fn remove_tray_icon(id: u32) {
+unsafe fn remove_tray_icon(hwnd: HWND, id: u32) {
     let mut nid: NOTIFYICONDATAW = zeroed();
     nid.cbSize = size_of::<NOTIFYICONDATAW>() as u32;
-    nid.hWnd = MAIN_HWND;
+    nid.hWnd = hwnd;
     nid.uID = id;
     let _ = Shell_NotifyIconW(NIM_DELETE, &nid);
 }
 
-unsafe fn remove_all_icons() {
-    remove_tray_icon(ICON_ID_DEFAULT);
-    remove_tray_icon(ICON_ID_PREV);
-    remove_tray_icon(ICON_ID_PLAY);
-    remove_tray_icon(ICON_ID_NEXT);
+unsafe fn remove_all_icons(hwnd: HWND) {
+    remove_tray_icon(hwnd, ICON_ID_DEFAULT);
+    remove_tray_icon(hwnd, ICON_ID_PREV);
+    remove_tray_icon(hwnd, ICON_ID_PLAY);
+    remove_tray_icon(hwnd, ICON_ID_NEXT);
+    remove_tray_icon(hwnd, ICON_ID_SHUFFLE);
+    remove_tray_icon(hwnd, ICON_ID_REPEAT);
+    remove_tray_icon(hwnd, ICON_ID_OUTPUT);
+    remove_tray_icon(hwnd, ICON_ID_SPEED);
+}
+
+// ============== Wheel Input ==============
+//
+// The shell doesn't forward wheel messages to notification icons, so a
+// low-level mouse hook sees every wheel event. It compares the cursor with
+// the icon rectangles cached in ICON_RECTS and only posts the events over
+// one of our icons. The cache is refreshed when the icons are re-added and
+// when the cursor moves over one, which catches icons dragged elsewhere.
+
+unsafe fn set_wheel_hook(app: &RefCell<App>, enabled: bool) {
+    {
+        let mut app = app.borrow_mut();
+        if enabled && app.wheel_hook.is_none() {
+            WHEEL_TARGET.store(app.hwnd.0 as isize, Ordering::Relaxed);
+            let h_module = GetModuleHandleW(None).unwrap_or(HMODULE(null_mut()));
+            app.wheel_hook = SetWindowsHookExW(WH_MOUSE_LL, Some(wheel_hook_proc), h_module, 0).ok();
+        } else if !enabled {
+            if let Some(hook) = app.wheel_hook.take() {
+                let _ = UnhookWindowsHookEx(hook);
+            }
+        }
+    }
+    refresh_icon_rects(app);
 }
 
-// Icon size - 32x32
-const ICON_SIZE: i32 = 32;
-const ICON_PIXELS: usize = (ICON_SIZE * ICON_SIZE) as usize;
+/// Re-reads where the visible icons are, or forgets them while there is no hook.
+unsafe fn refresh_icon_rects(app: &RefCell<App>) {
+    let (hwnd, icons) = {
+        let mut app = app.borrow_mut();
+        app.icon_rects_at = Some(Instant::now());
+        let icons = if app.wheel_hook.is_none() { Vec::new() } else { app.state.visible_icons() };
+        (app.hwnd, icons)
+    };
+    let rects: Vec<_> =
+        icons.into_iter().filter_map(|icon| Some((tray_icon_id(icon), tray_icon_rect(hwnd, icon)?))).collect();
+    if let Ok(mut cached) = ICON_RECTS.lock() {
+        *cached = rects;
+    }
+}
 
-// ============== Icon Color ==============
+// Runs on the UI thread, which installed it, between messages
+unsafe extern "system" fn wheel_hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code == HC_ACTION as i32 && wparam.0 as u32 == WM_MOUSEWHEEL {
+        // Only look up and post here; the hook must return quickly or Windows drops it
+        let info = &*(lparam.0 as *const MSLLHOOKSTRUCT);
+        let over = ICON_RECTS.lock().ok().and_then(|rects| {
+            rects.iter().find(|(_, rect)| rect_contains(rect, info.pt)).map(|&(id, _)| id)
+        });
+        if let Some(id) = over {
+            let delta = (info.mouseData >> 16) as u16 as usize;
+            let hwnd = HWND(WHEEL_TARGET.load(Ordering::Relaxed) as *mut std::ffi::c_void);
+            let _ = PostMessageW(hwnd, WM_WHEEL_INPUT, WPARAM(delta | ((id as usize) << 16)), LPARAM(0));
+        }
+    }
+    CallNextHookEx(None, code, wparam, lparam)
+}
 
-fn get_icon_colors() -> (u8, u8, u8) {
-    unsafe {
-        if APP_SETTINGS.dark_icons {
-            (0, 0, 0)
-        } else {
-            (255, 255, 255)
+fn rect_contains(rect: &RECT, pt: POINT) -> bool {
+    pt.x >= rect.left && pt.x < rect.right && pt.y >= rect.top && pt.y < rect.bottom
+}
+
+// ============== Flyout Window ==============
+//
+// Borderless mini-player owned by the main window. It shares the `App`
+// pointer but never frees it; layout comes from `flyout`, state from `core`.
+
+unsafe fn open_flyout(app: &RefCell<App>, icon: TrayIcon) {
+    let (hwnd, open) = {
+        let app = app.borrow();
+        (app.hwnd, app.flyout.is_some())
+    };
+    if open {
+        return;
+    }
+
+    let anchor = tray_icon_rect(hwnd, icon).unwrap_or_else(|| {
+        let mut pt: POINT = zeroed();
+        let _ = GetCursorPos(&mut pt);
+        RECT { left: pt.x, top: pt.y, right: pt.x + 1, bottom: pt.y + 1 }
+    });
+    let mut monitor = MONITORINFO { cbSize: size_of::<MONITORINFO>() as u32, ..zeroed() };
+    let _ = GetMonitorInfoW(MonitorFromRect(&anchor, MONITOR_DEFAULTTONEAREST), &mut monitor);
+    let screen = GetDC(None);
+    let dpi = GetDeviceCaps(screen, LOGPIXELSY).max(96) as u32;
+    ReleaseDC(None, screen);
+
+    let layout = FlyoutLayout::new(dpi, layout_rect(anchor), layout_rect(monitor.rcWork));
+    app.borrow_mut().flyout_layout = Some(layout);
+
+    let window = layout.window;
+    let h_module = GetModuleHandleW(None).unwrap_or(HMODULE(null_mut()));
+    let flyout = CreateWindowExW(
+        WS_EX_TOOLWINDOW | WS_EX_TOPMOST,
+        FLYOUT_CLASS,
+        w!("ClickPlay"),
+        WS_POPUP,
+        window.left, window.top, window.width(), window.height(),
+        hwnd,
+        None,
+        h_module,
+        Some(app as *const RefCell<App> as *const std::ffi::c_void),
+    );
+
+    match flyout {
+        Ok(flyout) => {
+            app.borrow_mut().flyout = Some(flyout);
+            let _ = ShowWindow(flyout, SW_SHOW);
+            let _ = SetForegroundWindow(flyout);
+        }
+        Err(_) => dispatch(app, Event::FlyoutClosed { now: Instant::now() }),
+    }
+}
+
+unsafe extern "system" fn flyout_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == WM_NCCREATE {
+        let create = &*(lparam.0 as *const CREATESTRUCTW);
+        SetWindowLongPtrW(hwnd, GWLP_USERDATA, create.lpCreateParams as isize);
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    }
+
+    let Some(app) = app_from_hwnd(hwnd) else {
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    };
+
+    match msg {
+        WM_PAINT => {
+            paint_flyout(app, hwnd);
+            LRESULT(0)
+        }
+        WM_LBUTTONUP => {
+            let x = (lparam.0 & 0xFFFF) as i16 as i32;
+            let y = ((lparam.0 >> 16) & 0xFFFF) as i16 as i32;
+            let hit = app.borrow().flyout_layout.and_then(|layout| layout.hit_test(x, y));
+            if let Some(hit) = hit {
+                dispatch(app, Event::FlyoutInput { now: Instant::now(), hit });
+            }
+            LRESULT(0)
+        }
+        WM_ACTIVATE => {
+            if (wparam.0 & 0xFFFF) as u32 == WA_INACTIVE {
+                let _ = PostMessageW(hwnd, WM_CLOSE, WPARAM(0), LPARAM(0));
+            }
+            LRESULT(0)
+        }
+        WM_KEYDOWN if wparam.0 == VK_ESCAPE.0 as usize => {
+            let _ = PostMessageW(hwnd, WM_CLOSE, WPARAM(0), LPARAM(0));
+            LRESULT(0)
+        }
+        WM_DESTROY => {
+            {
+                let mut app = app.borrow_mut();
+                app.flyout = None;
+                app.flyout_layout = None;
+            }
+            dispatch(app, Event::FlyoutClosed { now: Instant::now() });
+            LRESULT(0)
+        }
+        WM_NCDESTROY => {
+            // The App belongs to the main window
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
+            DefWindowProcW(hwnd, msg, wparam, lparam)
+        }
+        _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+    }
+}
+
+/// Paints through a memory DC so the once-a-tick refresh doesn't flicker.
+unsafe fn paint_flyout(app: &RefCell<App>, hwnd: HWND) {
+    let (state, layout) = {
+        let app = app.borrow();
+        (app.state.clone(), app.flyout_layout)
+    };
+
+    let mut ps: PAINTSTRUCT = zeroed();
+    let hdc = BeginPaint(hwnd, &mut ps);
+    if let Some(layout) = layout {
+        let (width, height) = (layout.window.width(), layout.window.height());
+        let mem = CreateCompatibleDC(hdc);
+        let bitmap = CreateCompatibleBitmap(hdc, width, height);
+        let old_bitmap = SelectObject(mem, bitmap);
+
+        draw_flyout(mem, &state, &layout);
+        let _ = BitBlt(hdc, 0, 0, width, height, mem, 0, 0, SRCCOPY);
+
+        SelectObject(mem, old_bitmap);
+        let _ = DeleteObject(bitmap);
+        let _ = DeleteDC(mem);
+    }
+    let _ = EndPaint(hwnd, &ps);
+}
+
+unsafe fn draw_flyout(hdc: HDC, state: &State, layout: &FlyoutLayout) {
+    // Follows the system theme rather than the icon theme: it's a window, not a tray glyph
+    let dark = state.system_dark;
+    let (background, surface, text, subtle) = if dark {
+        (rgb(32, 32, 32), rgb(70, 70, 70), rgb(255, 255, 255), rgb(170, 170, 170))
+    } else {
+        (rgb(243, 243, 243), rgb(210, 210, 210), rgb(0, 0, 0), rgb(96, 96, 96))
+    };
+    let window = layout.window;
+    fill_rect(hdc, flyout::Rect::new(0, 0, window.width(), window.height()), background);
+
+    match &state.art {
+        Some(art) => draw_art(hdc, art, layout.art),
+        None => {
+            fill_rect(hdc, layout.art, surface);
+            let size = layout.prev.width();
+            let (cx, cy) = ((layout.art.left + layout.art.right) / 2, (layout.art.top + layout.art.bottom) / 2);
+            let glyph_rect = flyout::Rect::new(cx - size / 2, cy - size / 2, cx + size / 2, cy + size / 2);
+            draw_glyph(hdc, TrayIcon::Default, false, !dark, glyph_rect);
         }
     }
+
+    let track = state.track.as_ref();
+    let session = track.map(|t| flyout::session_name(&t.app_id)).unwrap_or_default();
+    let title = track
+        .map(|t| t.title.as_str())
+        .filter(|t| !t.is_empty())
+        .unwrap_or(i18n::text(state.language, Msg::NothingPlaying));
+    let artist = track.map(|t| t.artist.as_str()).unwrap_or_default();
+    SetBkMode(hdc, TRANSPARENT);
+    draw_text(hdc, &session, layout.session, layout.small_font, FW_NORMAL, subtle);
+    draw_text(hdc, title, layout.title, layout.font, FW_SEMIBOLD, text);
+    draw_text(hdc, artist, layout.artist, layout.font, FW_NORMAL, subtle);
+
+    let bar = layout.seek_bar;
+    fill_rect(hdc, bar, surface);
+    let fraction = state.timeline.and_then(|t| core::timeline_fraction(t.position, t.start, t.end));
+    if let Some(fraction) = fraction {
+        let right = bar.left + (bar.width() as f32 * fraction).round() as i32;
+        fill_rect(hdc, flyout::Rect { right, ..bar }, text);
+    }
+
+    draw_glyph(hdc, TrayIcon::Prev, state.playing, !dark, layout.prev);
+    draw_glyph(hdc, TrayIcon::Play, state.playing, !dark, layout.play);
+    draw_glyph(hdc, TrayIcon::Next, state.playing, !dark, layout.next);
+}
+
+/// Draws album art centered in `area`, keeping its aspect ratio.
+unsafe fn draw_art(hdc: HDC, art: &core::AlbumArt, area: flyout::Rect) {
+    let scale = (area.width() as f32 / art.width as f32).min(area.height() as f32 / art.height as f32);
+    let (width, height) = ((art.width as f32 * scale) as i32, (art.height as f32 * scale) as i32);
+    let left = area.left + (area.width() - width) / 2;
+    let top = area.top + (area.height() - height) / 2;
+
+    let mut bmi: BITMAPINFO = zeroed();
+    bmi.bmiHeader.biSize = size_of::<BITMAPINFOHEADER>() as u32;
+    bmi.bmiHeader.biWidth = art.width;
+    bmi.bmiHeader.biHeight = -art.height;
+    bmi.bmiHeader.biPlanes = 1;
+    bmi.bmiHeader.biBitCount = 32;
+    bmi.bmiHeader.biCompression = BI_RGB.0;
+
+    StretchDIBits(
+        hdc,
+        left, top, width, height,
+        0, 0, art.width, art.height,
+        Some(art.pixels.as_ptr() as *const std::ffi::c_void),
+        &bmi,
+        DIB_RGB_COLORS,
+        SRCCOPY,
+    );
+}
+
+unsafe fn draw_glyph(hdc: HDC, icon: TrayIcon, playing: bool, dark_icons: bool, rect: flyout::Rect) {
+    let hicon = create_icon_from_pixels(&icons::flyout_glyph(icon, playing, dark_icons));
+    let _ = DrawIconEx(hdc, rect.left, rect.top, hicon, rect.width(), rect.height(), 0, None, DI_NORMAL);
+    let _ = DestroyIcon(hicon);
 }
 
-fn blend_color(r: u8, g: u8, b: u8, alpha: f32) -> u32 {
-    let a = (alpha * 255.0).clamp(0.0, 255.0) as u8;
-    ((a as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32)
+unsafe fn draw_text(hdc: HDC, text: &str, rect: flyout::Rect, height: i32, weight: FONT_WEIGHT, color: COLORREF) {
+    let font = CreateFontW(
+        -height, 0, 0, 0,
+        weight.0 as i32,
+        0, 0, 0,
+        DEFAULT_CHARSET.0 as u32,
+        OUT_DEFAULT_PRECIS.0 as u32,
+        CLIP_DEFAULT_PRECIS.0 as u32,
+        CLEARTYPE_QUALITY.0 as u32,
+        DEFAULT_PITCH.0 as u32,
+        w!("Segoe UI"),
+    );
+    let old_font = SelectObject(hdc, font);
+    SetTextColor(hdc, color);
+
+    let mut wide: Vec<u16> = text.encode_utf16().collect();
+    let mut rc = win32_rect(rect);
+    DrawTextW(hdc, &mut wide, &mut rc, DT_SINGLELINE | DT_VCENTER | DT_END_ELLIPSIS | DT_NOPREFIX);
+
+    SelectObject(hdc, old_font);
+    let _ = DeleteObject(font);
+}
+
+unsafe fn fill_rect(hdc: HDC, rect: flyout::Rect, color: COLORREF) {
+    let brush = CreateSolidBrush(color);
+    FillRect(hdc, &win32_rect(rect), brush);
+    let _ = DeleteObject(brush);
+}
+
+fn rgb(r: u8, g: u8, b: u8) -> COLORREF {
+    COLORREF(r as u32 | (g as u32) << 8 | (b as u32) << 16)
+}
+
+fn layout_rect(rect: RECT) -> flyout::Rect {
+    flyout::Rect::new(rect.left, rect.top, rect.right, rect.bottom)
+}
+
+fn win32_rect(rect: flyout::Rect) -> RECT {
+    RECT { left: rect.left, top: rect.top, right: rect.right, bottom: rect.bottom }
 }
 
 // ============== Icon Creation ==============
@@ -412,246 +1203,6 @@ unsafe fn create_icon_from_pixels(pixels: &[u32]) -> HICON {
     icon
 }
 
-fn set_pixel(pixels: &mut Vec<u32>, x: i32, y: i32, color: u32) {
-    if x >= 0 && x < ICON_SIZE && y >= 0 && y < ICON_SIZE {
-        pixels[(y * ICON_SIZE + x) as usize] = color;
-    }
-}
-
-fn draw_filled_circle(pixels: &mut Vec<u32>, cx: f32, cy: f32, radius: f32, r: u8, g: u8, b: u8) {
-    for py in 0..ICON_SIZE {
-        for px in 0..ICON_SIZE {
-            let dx = px as f32 + 0.5 - cx;
-            let dy = py as f32 + 0.5 - cy;
-            let dist = (dx * dx + dy * dy).sqrt();
-            
-            if dist <= radius - 0.7 {
-                set_pixel(pixels, px, py, blend_color(r, g, b, 1.0));
-            } else if dist <= radius + 0.7 {
-                let alpha = 1.0 - (dist - radius + 0.7) / 1.4;
-                let existing = pixels[(py * ICON_SIZE + px) as usize];
-                let existing_alpha = (existing >> 24) as f32 / 255.0;
-                let new_alpha = alpha.max(existing_alpha);
-                set_pixel(pixels, px, py, blend_color(r, g, b, new_alpha));
-            }
-        }
-    }
-}
-
-fn draw_rounded_rect(pixels: &mut Vec<u32>, x1: f32, y1: f32, x2: f32, y2: f32, corner_radius: f32, r: u8, g: u8, b: u8) {
-    let cr = corner_radius.min((x2 - x1) / 2.0).min((y2 - y1) / 2.0);
-    
-    for py in 0..ICON_SIZE {
-        for px in 0..ICON_SIZE {
-            let fx = px as f32 + 0.5;
-            let fy = py as f32 + 0.5;
-            
-            if fx < x1 - 0.7 || fx > x2 + 0.7 || fy < y1 - 0.7 || fy > y2 + 0.7 {
-                continue;
-            }
-            
-            let in_left = fx < x1 + cr;
-            let in_right = fx > x2 - cr;
-            let in_top = fy < y1 + cr;
-            let in_bottom = fy > y2 - cr;
-            
-            let dist_to_edge = if in_left && in_top {
-                let ccx = x1 + cr;
-                let ccy = y1 + cr;
-                cr - ((fx - ccx).powi(2) + (fy - ccy).powi(2)).sqrt()
-            } else if in_right && in_top {
-                let ccx = x2 - cr;
-                let ccy = y1 + cr;
-                cr - ((fx - ccx).powi(2) + (fy - ccy).powi(2)).sqrt()
-            } else if in_left && in_bottom {
-                let ccx = x1 + cr;
-                let ccy = y2 - cr;
-                cr - ((fx - ccx).powi(2) + (fy - ccy).powi(2)).sqrt()
-            } else if in_right && in_bottom {
-                let ccx = x2 - cr;
-                let ccy = y2 - cr;
-                cr - ((fx - ccx).powi(2) + (fy - ccy).powi(2)).sqrt()
-            } else {
-                (fx - x1).min(x2 - fx).min(fy - y1).min(y2 - fy)
-            };
-            
-            if dist_to_edge > 0.7 {
-                set_pixel(pixels, px, py, blend_color(r, g, b, 1.0));
-            } else if dist_to_edge > -0.7 {
-                let alpha = (dist_to_edge + 0.7) / 1.4;
-                let existing = pixels[(py * ICON_SIZE + px) as usize];
-                let existing_alpha = (existing >> 24) as f32 / 255.0;
-                let new_alpha = alpha.max(existing_alpha);
-                set_pixel(pixels, px, py, blend_color(r, g, b, new_alpha));
-            }
-        }
-    }
-}
-
-fn draw_triangle_right(pixels: &mut Vec<u32>, x1: f32, y_center: f32, width: f32, height: f32, r: u8, g: u8, b: u8) {
-    let half_height = height / 2.0;
-    let x2 = x1 + width;
-    
-    for py in 0..ICON_SIZE {
-        for px in 0..ICON_SIZE {
-            let fx = px as f32 + 0.5;
-            let fy = py as f32 + 0.5;
-            
-            let dy = (fy - y_center).abs();
-            if dy > half_height + 1.0 {
-                continue;
-            }
-            
-            let progress = dy / half_height;
-            let edge_x = x2 - progress * width;
-            
-            if fx >= x1 - 0.7 && fx <= edge_x + 0.7 {
-                let left_dist = fx - x1;
-                let right_dist = edge_x - fx;
-                let tb_dist = half_height - dy;
-                let min_dist = left_dist.min(right_dist).min(tb_dist);
-                
-                if min_dist > 0.7 {
-                    set_pixel(pixels, px, py, blend_color(r, g, b, 1.0));
-                } else if min_dist > -0.7 {
-                    let alpha = (min_dist + 0.7) / 1.4;
-                    let existing = pixels[(py * ICON_SIZE + px) as usize];
-                    let existing_alpha = (existing >> 24) as f32 / 255.0;
-                    let new_alpha = alpha.max(existing_alpha);
-                    set_pixel(pixels, px, py, blend_color(r, g, b, new_alpha));
-                }
-            }
-        }
-    }
-}
-
-fn draw_triangle_left(pixels: &mut Vec<u32>, x_right: f32, y_center: f32, width: f32, height: f32, r: u8, g: u8, b: u8) {
-    let half_height = height / 2.0;
-    let x_left = x_right - width;
-    
-    for py in 0..ICON_SIZE {
-        for px in 0..ICON_SIZE {
-            let fx = px as f32 + 0.5;
-            let fy = py as f32 + 0.5;
-            
-            let dy = (fy - y_center).abs();
-            if dy > half_height + 1.0 {
-                continue;
-            }
-            
-            let progress = dy / half_height;
-            let edge_x = x_left + progress * width;
-            
-            if fx <= x_right + 0.7 && fx >= edge_x - 0.7 {
-                let left_dist = fx - edge_x;
-                let right_dist = x_right - fx;
-                let tb_dist = half_height - dy;
-                let min_dist = left_dist.min(right_dist).min(tb_dist);
-                
-                if min_dist > 0.7 {
-                    set_pixel(pixels, px, py, blend_color(r, g, b, 1.0));
-                } else if min_dist > -0.7 {
-                    let alpha = (min_dist + 0.7) / 1.4;
-                    let existing = pixels[(py * ICON_SIZE + px) as usize];
-                    let existing_alpha = (existing >> 24) as f32 / 255.0;
-                    let new_alpha = alpha.max(existing_alpha);
-                    set_pixel(pixels, px, py, blend_color(r, g, b, new_alpha));
-                }
-            }
-        }
-    }
-}
-
-// ============== Icon Definitions (32x32) ==============
-
-unsafe fn create_default_icon() -> HICON {
-    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
-    let (r, g, b) = get_icon_colors();
-    
-    // Music note head
-    draw_filled_circle(&mut pixels, 10.0, 23.0, 7.0, r, g, b);
-    
-    // Note stem
-    draw_rounded_rect(&mut pixels, 13.0, 5.0, 17.0, 23.0, 2.0, r, g, b);
-    
-    // Note flag
-    draw_rounded_rect(&mut pixels, 14.0, 5.0, 25.0, 8.0, 2.0, r, g, b);
-    draw_rounded_rect(&mut pixels, 22.0, 7.5, 24.5, 13.0, 1.5, r, g, b);
-    draw_rounded_rect(&mut pixels, 21.0, 12.0, 23.0, 16.0, 1.0, r, g, b);
-
-    create_icon_from_pixels(&pixels)
-}
-
-unsafe fn create_prev_icon() -> HICON {
-    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
-    let (r, g, b) = get_icon_colors();
-    
-    // Left bar (y: 6-26 = height 20)
-    draw_rounded_rect(&mut pixels, 5.0, 6.0, 10.0, 26.0, 1.5, r, g, b);
-    
-    // Triangle pointing left
-    draw_triangle_left(&mut pixels, 27.0, 16.0, 19.0, 20.0, r, g, b);
-
-    create_icon_from_pixels(&pixels)
-}
-
-unsafe fn create_play_icon() -> HICON {
-    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
-    let (r, g, b) = get_icon_colors();
-    
-    // Play triangle
-    draw_triangle_right(&mut pixels, 7.0, 16.0, 21.0, 20.0, r, g, b);
-
-    create_icon_from_pixels(&pixels)
-}
-
-unsafe fn create_pause_icon() -> HICON {
-    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
-    let (r, g, b) = get_icon_colors();
-    
-    // Two vertical bars
-    draw_rounded_rect(&mut pixels, 6.0, 6.0, 12.0, 26.0, 1.5, r, g, b);
-    draw_rounded_rect(&mut pixels, 20.0, 6.0, 26.0, 26.0, 1.5, r, g, b);
-
-    create_icon_from_pixels(&pixels)
-}
-
-unsafe fn create_next_icon() -> HICON {
-    let mut pixels = vec![0x00000000u32; ICON_PIXELS];
-    let (r, g, b) = get_icon_colors();
-    
-    // Triangle pointing right
-    draw_triangle_right(&mut pixels, 5.0, 16.0, 19.0, 20.0, r, g, b);
-    
-    // Right bar (y: 6-26 = height 20)
-    draw_rounded_rect(&mut pixels, 22.0, 6.0, 27.0, 26.0, 1.5, r, g, b);
-
-    create_icon_from_pixels(&pixels)
-}
-
-// ============== Media State Detection ==============
-
-fn init_media_manager() -> Option<GlobalSystemMediaTransportControlsSessionManager> {
-    GlobalSystemMediaTransportControlsSessionManager::RequestAsync()
-        .ok()
-        .and_then(|op| op.get().ok())
-}
-
-fn check_media_playing() -> bool {
-    unsafe {
-        if let Some(ref manager) = MEDIA_MANAGER {
-            if let Ok(session) = manager.GetCurrentSession() {
-                if let Ok(info) = session.GetPlaybackInfo() {
-                    if let Ok(status) = info.PlaybackStatus() {
-                        return status == GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing;
-                    }
-                }
-            }
-        }
-    }
-    false
-}
-
 // ============== Color Theme Detection ==============
 
 fn is_system_light_theme() -> bool {
@@ -683,45 +1234,3 @@ fn is_system_light_theme() -> bool {
     }
 }
 
-// ============== Config File Storage ==============
-
-fn get_config_path() -> PathBuf {
-    let exe_path = env::current_exe().unwrap_or_default();
-    let exe_dir = exe_path.parent().unwrap_or(std::path::Path::new("."));
-    exe_dir.join("clickplay.cfg")
-}
-
-fn save_settings() {
-    unsafe {
-        let config = format!(
-            "prev={}\nplay={}\nnext={}\ndark_icons={}",
-            APP_SETTINGS.show_prev as u8,
-            APP_SETTINGS.show_play as u8,
-            APP_SETTINGS.show_next as u8,
-            APP_SETTINGS.dark_icons as u8
-        );
-        let _ = fs::write(get_config_path(), config);
-    }
-}
-
-fn load_settings() -> AppSettings {
-    let mut settings = AppSettings::default();
-    
-    if let Ok(content) = fs::read_to_string(get_config_path()) {
-        for line in content.lines() {
-            let parts: Vec<&str> = line.split('=').collect();
-            if parts.len() == 2 {
-                let value = parts[1].trim() == "1";
-                match parts[0].trim() {
-                    "prev" => settings.show_prev = value,
-                    "play" => settings.show_play = value,
-                    "next" => settings.show_next = value,
-                    "dark_icons" => settings.dark_icons = value,
-                    _ => {}
-                }
-            }
-        }
-    }
-    
-    settings
-}
@@ -0,0 +1,584 @@
+// ============== Media State Detection ==============
+//
+// All WinRT media calls run on a dedicated worker thread, so a hung player
+// can never freeze the tray icons or the context menu. The UI queues commands
+// for it; the worker answers through a channel and wakes the UI with a
+// callback (a posted window message). Some WinRT calls are synchronous and a
+// hung player can block them forever, so a worker stuck in one is abandoned
+// and replaced.
+
+use std::cell::RefCell;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use windows::Foundation::{AsyncStatus, IAsyncOperation};
+use windows::Media::Control::{
+    GlobalSystemMediaTransportControlsSession,
+    GlobalSystemMediaTransportControlsSessionManager,
+    GlobalSystemMediaTransportControlsSessionPlaybackStatus,
+};
+use windows::Graphics::Imaging::{
+    BitmapAlphaMode, BitmapDecoder, BitmapInterpolationMode, BitmapPixelFormat, BitmapTransform,
+    ColorManagementMode, ExifOrientationMode,
+};
+use windows::Media::MediaPlaybackAutoRepeatMode;
+
+use crate::core::{AlbumArt, MediaKey, ModeState, PlaybackStatus, RepeatMode, Timeline, TrackInfo};
+use crate::sessions::{self, Candidate, PausedSession, ResumeCandidate, SessionRule};
+use crate::worker::{CommandQueue, Watchdog};
+
+// Upper bound for any asynchronous WinRT operation before it is cancelled
+const CALL_TIMEOUT: Duration = Duration::from_millis(1500);
+const CALL_POLL_INTERVAL: Duration = Duration::from_millis(5);
+// A single WinRT call still running after this long is taken to be hung.
+// Every `wait` restarts the clock, so Pause All and Resume Paused, which make
+// one call per session, get this long for each call rather than in total.
+const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(5);
+// Queries waiting for the worker; past this, the oldest makes room
+const QUEUE_CAPACITY: usize = 32;
+
+// Album art is decoded to fit this box; the flyout scales it from there
+const ART_SIZE: u32 = 96;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MediaCommand {
+    QueryStatus,
+    QueryTrack,
+    QueryModes,
+    QueryTimeline,
+    QueryArt,
+    SetShuffle(bool),
+    SetRepeat(RepeatMode),
+    /// Playback rate, 1.0 being normal speed
+    SetSpeed(f64),
+    /// Relative seek in seconds
+    Seek(i32),
+    /// Seek to a fraction of the track
+    SeekTo(f32),
+    QueryPlayer,
+    /// Transport through the selected session rather than a media key
+    Control(MediaKey),
+    /// Pause every playing session, answered with the ones paused
+    PauseAll,
+    /// Resume these sessions if they are still open and paused
+    Resume(Vec<PausedSession>),
+    /// Pause the selected session; unlike the play/pause key, never starts it
+    Pause,
+}
+
+impl MediaCommand {
+    /// Queries are sent again on every poll, so a queued one can be dropped.
+    fn is_query(&self) -> bool {
+        matches!(
+            self,
+            MediaCommand::QueryStatus
+                | MediaCommand::QueryTrack
+                | MediaCommand::QueryModes
+                | MediaCommand::QueryTimeline
+                | MediaCommand::QueryArt
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MediaUpdate {
+    Status(Option<PlaybackStatus>),
+    Track(Option<TrackInfo>),
+    Modes(ModeState),
+    Timeline(Option<Timeline>),
+    Art(Option<AlbumArt>),
+    ShuffleChanged(bool),
+    RepeatChanged(RepeatMode),
+    SpeedChanged(f64),
+    Player(Option<String>),
+    Paused(Vec<PausedSession>),
+}
+
+/// Handle to the media worker thread. Dropping it closes the command queue,
+/// which ends the thread once its current call returns.
+pub struct MediaWorker {
+    rules: Vec<SessionRule>,
+    notify: Arc<dyn Fn() + Send + Sync>,
+    commands: Arc<CommandQueue<MediaCommand>>,
+    watchdog: Arc<Watchdog>,
+    update_tx: Sender<MediaUpdate>,
+    updates: Receiver<MediaUpdate>,
+}
+
+impl MediaWorker {
+    /// Starts the worker. `notify` runs on the worker thread after each update
+    /// is queued and should wake the UI thread.
+    pub fn spawn(rules: Vec<SessionRule>, notify: impl Fn() + Send + Sync + 'static) -> MediaWorker {
+        let (update_tx, update_rx) = mpsc::channel();
+        let notify: Arc<dyn Fn() + Send + Sync> = Arc::new(notify);
+        let (commands, watchdog) = start_worker(&rules, update_tx.clone(), notify.clone());
+
+        MediaWorker { rules, notify, commands, watchdog, update_tx, updates: update_rx }
+    }
+
+    pub fn send(&mut self, command: MediaCommand) {
+        if self.watchdog.stuck(Instant::now(), WATCHDOG_TIMEOUT) {
+            // The stuck thread ends if its call ever returns
+            self.commands.close();
+            (self.commands, self.watchdog) = start_worker(&self.rules, self.update_tx.clone(), self.notify.clone());
+        }
+        // Only a query can be refused, and the next poll sends it again
+        self.commands.push(command);
+    }
+
+    /// Takes every update received so far.
+    pub fn drain(&self) -> Vec<MediaUpdate> {
+        self.updates.try_iter().collect()
+    }
+}
+
+impl Drop for MediaWorker {
+    fn drop(&mut self) {
+        self.commands.close();
+    }
+}
+
+/// Starts a worker thread with its own queue and watchdog.
+fn start_worker(
+    rules: &[SessionRule],
+    updates: Sender<MediaUpdate>,
+    notify: Arc<dyn Fn() + Send + Sync>,
+) -> (Arc<CommandQueue<MediaCommand>>, Arc<Watchdog>) {
+    let commands = Arc::new(CommandQueue::new(QUEUE_CAPACITY, MediaCommand::is_query));
+    let watchdog = Arc::new(Watchdog::default());
+    let (rules, worker_commands, worker_watchdog) = (rules.to_vec(), commands.clone(), watchdog.clone());
+
+    thread::spawn(move || run_worker(rules, &worker_commands, worker_watchdog, updates, || notify()));
+
+    (commands, watchdog)
+}
+
+thread_local! {
+    // The watchdog of the worker running on this thread, for `wait`
+    static WATCHDOG: RefCell<Option<Arc<Watchdog>>> = const { RefCell::new(None) };
+}
+
+fn run_worker(
+    rules: Vec<SessionRule>,
+    commands: &CommandQueue<MediaCommand>,
+    watchdog: Arc<Watchdog>,
+    updates: Sender<MediaUpdate>,
+    notify: impl Fn(),
+) {
+    let mut manager: Option<MediaManager> = None;
+    WATCHDOG.with(|cell| *cell.borrow_mut() = Some(watchdog.clone()));
+
+    while let Some(command) = commands.pop() {
+        let query = command.is_query();
+        watchdog.start(Instant::now());
+        if manager.is_none() {
+            manager = MediaManager::request(&rules);
+        }
+        let update = match manager.as_ref() {
+            Some(manager) => manager.execute(command),
+            None => offline_update(command),
+        };
+        watchdog.finish();
+
+        // Replaced while the call hung: the new worker answers queries, but
+        // what a finished command did (such as a shuffle change) is still
+        // reported
+        let replaced = commands.is_closed();
+        if replaced && query {
+            return;
+        }
+        if let Some(update) = update {
+            if updates.send(update).is_err() {
+                return;
+            }
+            notify();
+        }
+        if replaced {
+            return;
+        }
+    }
+}
+
+/// What to report when the session manager isn't available.
+fn offline_update(command: MediaCommand) -> Option<MediaUpdate> {
+    match command {
+        MediaCommand::QueryStatus => Some(MediaUpdate::Status(None)),
+        MediaCommand::QueryTrack => Some(MediaUpdate::Track(None)),
+        MediaCommand::QueryModes => Some(MediaUpdate::Modes(ModeState::default())),
+        MediaCommand::QueryTimeline => Some(MediaUpdate::Timeline(None)),
+        MediaCommand::QueryArt => Some(MediaUpdate::Art(None)),
+        MediaCommand::QueryPlayer => Some(MediaUpdate::Player(None)),
+        MediaCommand::PauseAll => Some(MediaUpdate::Paused(Vec::new())),
+        MediaCommand::SetShuffle(_)
+        | MediaCommand::SetRepeat(_)
+        | MediaCommand::SetSpeed(_)
+        | MediaCommand::Seek(_)
+        | MediaCommand::SeekTo(_)
+        | MediaCommand::Control(_)
+        | MediaCommand::Resume(_)
+        | MediaCommand::Pause => None,
+    }
+}
+
+/// Waits for an async operation, cancelling it after `CALL_TIMEOUT`. The
+/// worker's watchdog is restarted, as the call before this one returned.
+fn wait<T: windows::core::RuntimeType + 'static>(op: IAsyncOperation<T>) -> Option<T> {
+    let start = Instant::now();
+    WATCHDOG.with(|cell| {
+        if let Some(watchdog) = cell.borrow().as_ref() {
+            watchdog.start(start);
+        }
+    });
+    let deadline = start + CALL_TIMEOUT;
+    loop {
+        match op.Status().ok()? {
+            AsyncStatus::Completed => return op.GetResults().ok(),
+            AsyncStatus::Started if Instant::now() < deadline => thread::sleep(CALL_POLL_INTERVAL),
+            AsyncStatus::Started => {
+                let _ = op.Cancel();
+                return None;
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Thin wrapper over the system media session manager that converts WinRT
+/// values into core types. Only used on the worker thread.
+struct MediaManager {
+    manager: GlobalSystemMediaTransportControlsSessionManager,
+    rules: Vec<SessionRule>,
+}
+
+impl MediaManager {
+    fn request(rules: &[SessionRule]) -> Option<MediaManager> {
+        GlobalSystemMediaTransportControlsSessionManager::RequestAsync()
+            .ok()
+            .and_then(wait)
+            .map(|manager| MediaManager { manager, rules: rules.to_vec() })
+    }
+
+    fn execute(&self, command: MediaCommand) -> Option<MediaUpdate> {
+        match command {
+            MediaCommand::QueryStatus => Some(MediaUpdate::Status(self.check_status())),
+            MediaCommand::QueryTrack => Some(MediaUpdate::Track(self.check_track())),
+            MediaCommand::QueryModes => Some(MediaUpdate::Modes(self.check_modes())),
+            MediaCommand::QueryTimeline => Some(MediaUpdate::Timeline(self.check_timeline())),
+            MediaCommand::QueryArt => Some(MediaUpdate::Art(self.check_art())),
+            MediaCommand::SetShuffle(active) => {
+                self.set_shuffle(active).then_some(MediaUpdate::ShuffleChanged(active))
+            }
+            MediaCommand::SetRepeat(mode) => {
+                self.set_repeat(mode).then_some(MediaUpdate::RepeatChanged(mode))
+            }
+            MediaCommand::SetSpeed(speed) => {
+                self.set_speed(speed).then_some(MediaUpdate::SpeedChanged(speed))
+            }
+            MediaCommand::Seek(seconds) => {
+                self.seek(seconds);
+                None
+            }
+            MediaCommand::SeekTo(fraction) => {
+                self.seek_to(fraction);
+                None
+            }
+            MediaCommand::Control(key) => {
+                self.control(key);
+                None
+            }
+            MediaCommand::PauseAll => Some(MediaUpdate::Paused(self.pause_all())),
+            MediaCommand::Resume(sessions) => {
+                self.resume(&sessions);
+                None
+            }
+            MediaCommand::Pause => {
+                self.pause();
+                None
+            }
+            MediaCommand::QueryPlayer => Some(MediaUpdate::Player(
+                self.current_session()
+                    .and_then(|session| session.SourceAppUserModelId().ok())
+                    .map(|id| id.to_string()),
+            )),
+        }
+    }
+
+    /// The session the icons follow: Windows' current one, or the one the
+    /// session rules pick.
+    fn current_session(&self) -> Option<GlobalSystemMediaTransportControlsSession> {
+        let current = self.manager.GetCurrentSession().ok();
+        if self.rules.is_empty() {
+            return current;
+        }
+
+        let current_id = current.as_ref().map(session_app_id);
+        let sessions = self.sessions();
+        let app_ids: Vec<String> = sessions.iter().map(session_app_id).collect();
+        let candidates: Vec<Candidate> = sessions
+            .iter()
+            .zip(&app_ids)
+            .map(|(session, app_id)| Candidate {
+                app_id,
+                playing: session_status(session) == Some(GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing),
+                current: current_id.as_ref() == Some(app_id),
+            })
+            .collect();
+
+        let index = sessions::select(&self.rules, &candidates)?;
+        sessions.into_iter().nth(index)
+    }
+
+    fn check_status(&self) -> Option<PlaybackStatus> {
+        let session = self.current_session()?;
+        let status = session.GetPlaybackInfo().ok()?.PlaybackStatus().ok()?;
+        Some(match status {
+            GlobalSystemMediaTransportControlsSessionPlaybackStatus::Opened => PlaybackStatus::Opened,
+            GlobalSystemMediaTransportControlsSessionPlaybackStatus::Changing => PlaybackStatus::Changing,
+            GlobalSystemMediaTransportControlsSessionPlaybackStatus::Stopped => PlaybackStatus::Stopped,
+            GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing => PlaybackStatus::Playing,
+            GlobalSystemMediaTransportControlsSessionPlaybackStatus::Paused => PlaybackStatus::Paused,
+            _ => PlaybackStatus::Closed,
+        })
+    }
+
+    fn check_timeline(&self) -> Option<Timeline> {
+        let timeline = self.current_session()?.GetTimelineProperties().ok()?;
+        Some(Timeline {
+            position: timeline.Position().ok()?.Duration,
+            start: timeline.StartTime().ok()?.Duration,
+            end: timeline.EndTime().ok()?.Duration,
+        })
+    }
+
+    fn check_track(&self) -> Option<TrackInfo> {
+        let session = self.current_session()?;
+        let app_id = session.SourceAppUserModelId().ok()?.to_string();
+        let props = wait(session.TryGetMediaPropertiesAsync().ok()?)?;
+        Some(TrackInfo {
+            app_id,
+            title: props.Title().map(|t| t.to_string()).unwrap_or_default(),
+            artist: props.Artist().map(|a| a.to_string()).unwrap_or_default(),
+        })
+    }
+
+    /// Decodes the session's thumbnail, scaled to fit `ART_SIZE`.
+    fn check_art(&self) -> Option<AlbumArt> {
+        let session = self.current_session()?;
+        let props = wait(session.TryGetMediaPropertiesAsync().ok()?)?;
+        let stream = wait(props.Thumbnail().ok()?.OpenReadAsync().ok()?)?;
+        let decoder = wait(BitmapDecoder::CreateAsync(&stream).ok()?)?;
+
+        let (width, height) = (decoder.OrientedPixelWidth().ok()?, decoder.OrientedPixelHeight().ok()?);
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let scale = (ART_SIZE as f32 / width.max(height) as f32).min(1.0);
+        let width = ((width as f32 * scale).round() as u32).max(1);
+        let height = ((height as f32 * scale).round() as u32).max(1);
+
+        let transform = BitmapTransform::new().ok()?;
+        transform.SetScaledWidth(width).ok()?;
+        transform.SetScaledHeight(height).ok()?;
+        transform.SetInterpolationMode(BitmapInterpolationMode::Fant).ok()?;
+        let data = wait(
+            decoder
+                .GetPixelDataTransformedAsync(
+                    BitmapPixelFormat::Bgra8,
+                    BitmapAlphaMode::Ignore,
+                    &transform,
+                    ExifOrientationMode::RespectExifOrientation,
+                    ColorManagementMode::DoNotColorManage,
+                )
+                .ok()?,
+        )?;
+        let bytes = data.DetachPixelData().ok()?;
+
+        let pixels: Vec<u32> = bytes
+            .chunks_exact(4)
+            .map(|p| u32::from_le_bytes([p[0], p[1], p[2], 0xFF]))
+            .collect();
+        if pixels.len() != (width * height) as usize {
+            return None;
+        }
+        Some(AlbumArt { width: width as i32, height: height as i32, pixels })
+    }
+
+    fn check_modes(&self) -> ModeState {
+        let mut state = ModeState::default();
+        let Some(info) = self.current_session().and_then(|s| s.GetPlaybackInfo().ok()) else {
+            return state;
+        };
+        if let Ok(controls) = info.Controls() {
+            if controls.IsShuffleEnabled().unwrap_or(false) {
+                let active = info.IsShuffleActive().and_then(|v| v.Value());
+                state.shuffle = Some(active.unwrap_or(false));
+            }
+            if controls.IsRepeatEnabled().unwrap_or(false) {
+                let mode = info.AutoRepeatMode().and_then(|v| v.Value());
+                state.repeat = Some(mode.map(repeat_from_winrt).unwrap_or(RepeatMode::Off));
+            }
+            if controls.IsPlaybackRateEnabled().unwrap_or(false) {
+                let rate = info.PlaybackRate().and_then(|v| v.Value());
+                state.speed = Some(rate.unwrap_or(1.0));
+            }
+        }
+        state
+    }
+
+    fn sessions(&self) -> Vec<GlobalSystemMediaTransportControlsSession> {
+        self.manager
+            .GetSessions()
+            .map(|sessions| sessions.into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Pauses every playing session. Returns the sessions that accepted, with
+    /// the title each was paused on.
+    fn pause_all(&self) -> Vec<PausedSession> {
+        self.sessions()
+            .iter()
+            .filter(|session| session_status(session) == Some(GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing))
+            .filter(|session| session.TryPauseAsync().ok().and_then(wait).unwrap_or(false))
+            .map(|session| PausedSession { app_id: session_app_id(session), title: session_title(session) })
+            .collect()
+    }
+
+    /// Resumes the given sessions if they are still paused; other sessions of
+    /// the same apps stay as they are.
+    fn resume(&self, remembered: &[PausedSession]) {
+        let open = self.sessions();
+        let found: Vec<(String, String, bool)> = open
+            .iter()
+            .map(|session| {
+                let paused = session_status(session) == Some(GlobalSystemMediaTransportControlsSessionPlaybackStatus::Paused);
+                // Only paused sessions can match, so the others aren't asked for their title
+                let title = if paused { session_title(session) } else { String::new() };
+                (session_app_id(session), title, paused)
+            })
+            .collect();
+        let candidates: Vec<ResumeCandidate> = found
+            .iter()
+            .map(|(app_id, title, paused)| ResumeCandidate { app_id, title, paused: *paused })
+            .collect();
+        for index in sessions::to_resume(remembered, &candidates) {
+            let _ = open[index].TryPlayAsync().ok().and_then(wait);
+        }
+    }
+
+    fn pause(&self) -> bool {
+        self.current_session()
+            .and_then(|session| session.TryPauseAsync().ok())
+            .and_then(wait)
+            .unwrap_or(false)
+    }
+
+    fn control(&self, key: MediaKey) -> bool {
+        let Some(session) = self.current_session() else {
+            return false;
+        };
+        let operation = match key {
+            MediaKey::Prev => session.TrySkipPreviousAsync(),
+            MediaKey::PlayPause => session.TryTogglePlayPauseAsync(),
+            MediaKey::Next => session.TrySkipNextAsync(),
+            MediaKey::Stop => session.TryStopAsync(),
+            // Volume isn't a session operation
+            MediaKey::Mute => return false,
+        };
+        operation.ok().and_then(wait).unwrap_or(false)
+    }
+
+    fn set_shuffle(&self, active: bool) -> bool {
+        self.current_session()
+            .and_then(|session| session.TryChangeShuffleActiveAsync(active).ok())
+            .and_then(wait)
+            .unwrap_or(false)
+    }
+
+    fn set_repeat(&self, mode: RepeatMode) -> bool {
+        self.current_session()
+            .and_then(|session| session.TryChangeAutoRepeatModeAsync(repeat_to_winrt(mode)).ok())
+            .and_then(wait)
+            .unwrap_or(false)
+    }
+
+    fn set_speed(&self, speed: f64) -> bool {
+        self.current_session()
+            .and_then(|session| session.TryChangePlaybackRateAsync(speed).ok())
+            .and_then(wait)
+            .unwrap_or(false)
+    }
+
+    /// Moves the position by `seconds`, clamped to the track. Players without
+    /// seek support simply refuse the request.
+    fn seek(&self, seconds: i32) -> bool {
+        let Some(session) = self.current_session() else {
+            return false;
+        };
+        let Some(timeline) = self.check_timeline() else {
+            return false;
+        };
+        let target = timeline.position + seconds as i64 * 10_000_000;
+        let target = if timeline.end > timeline.start {
+            target.clamp(timeline.start, timeline.end)
+        } else {
+            target.max(timeline.start)
+        };
+        session
+            .TryChangePlaybackPositionAsync(target)
+            .ok()
+            .and_then(wait)
+            .unwrap_or(false)
+    }
+
+    fn seek_to(&self, fraction: f32) -> bool {
+        let Some(timeline) = self.check_timeline() else {
+            return false;
+        };
+        if timeline.end <= timeline.start {
+            return false;
+        }
+        let target = timeline.start + ((timeline.end - timeline.start) as f64 * fraction as f64) as i64;
+        self.current_session()
+            .and_then(|session| session.TryChangePlaybackPositionAsync(target).ok())
+            .and_then(wait)
+            .unwrap_or(false)
+    }
+}
+
+fn repeat_from_winrt(mode: MediaPlaybackAutoRepeatMode) -> RepeatMode {
+    match mode {
+        MediaPlaybackAutoRepeatMode::Track => RepeatMode::Track,
+        MediaPlaybackAutoRepeatMode::List => RepeatMode::List,
+        _ => RepeatMode::Off,
+    }
+}
+
+fn repeat_to_winrt(mode: RepeatMode) -> MediaPlaybackAutoRepeatMode {
+    match mode {
+        RepeatMode::Off => MediaPlaybackAutoRepeatMode::None,
+        RepeatMode::Track => MediaPlaybackAutoRepeatMode::Track,
+        RepeatMode::List => MediaPlaybackAutoRepeatMode::List,
+    }
+}
+
+fn session_app_id(session: &GlobalSystemMediaTransportControlsSession) -> String {
+    session.SourceAppUserModelId().map(|id| id.to_string()).unwrap_or_default()
+}
+
+fn session_title(session: &GlobalSystemMediaTransportControlsSession) -> String {
+    session
+        .TryGetMediaPropertiesAsync()
+        .ok()
+        .and_then(wait)
+        .and_then(|props| props.Title().ok())
+        .map(|title| title.to_string())
+        .unwrap_or_default()
+}
+
+fn session_status(
+    session: &GlobalSystemMediaTransportControlsSession,
+) -> Option<GlobalSystemMediaTransportControlsSessionPlaybackStatus> {
+    session.GetPlaybackInfo().ok()?.PlaybackStatus().ok()
+}
@@ -0,0 +1,378 @@
+// ============== Context Menu Model ==============
+//
+// The context menu as plain data. The Win32 shell turns it into an HMENU;
+// everything about what the menu contains is decided here.
+
+use crate::bindings::Action;
+use crate::config::{AutoHide, IconTheme};
+use crate::core::{self, MenuCommand, State, SPEED_PRESETS};
+use crate::flyout;
+use crate::i18n::{self, Language, Msg};
+use crate::mixer::{self, VolumeChange};
+use crate::sleep::{SleepChoice, SLEEP_PRESETS};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MenuItem {
+    /// Disabled informational line
+    Header(String),
+    Command { label: String, command: MenuCommand, checked: bool, enabled: bool },
+    Submenu { label: String, items: Vec<MenuItem> },
+    Separator,
+}
+
+fn command(language: Language, msg: Msg, command: MenuCommand) -> MenuItem {
+    toggle(language, msg, command, false)
+}
+
+fn toggle(language: Language, msg: Msg, command: MenuCommand, checked: bool) -> MenuItem {
+    MenuItem::Command { label: i18n::text(language, msg).to_string(), command, checked, enabled: true }
+}
+
+fn submenu(language: Language, msg: Msg, items: Vec<MenuItem>) -> MenuItem {
+    MenuItem::Submenu { label: i18n::text(language, msg).to_string(), items }
+}
+
+pub fn context_menu(state: &State) -> Vec<MenuItem> {
+    let lang = state.language;
+    let mut items = vec![
+        MenuItem::Header(now_playing(state)),
+        MenuItem::Separator,
+        command(lang, if state.playing { Msg::Pause } else { Msg::Play }, MenuCommand::Run(Action::PlayPause)),
+        command(lang, Msg::Next, MenuCommand::Run(Action::Next)),
+        command(lang, Msg::Previous, MenuCommand::Run(Action::Prev)),
+        command(lang, Msg::Stop, MenuCommand::Run(Action::Stop)),
+        MenuItem::Separator,
+        command(lang, Msg::PauseAll, MenuCommand::Run(Action::PauseAll)),
+        MenuItem::Command {
+            label: i18n::text(lang, Msg::ResumePaused).to_string(),
+            command: MenuCommand::Run(Action::ResumePaused),
+            checked: false,
+            enabled: !state.paused_sessions.is_empty(),
+        },
+        submenu(lang, Msg::SleepTimer, sleep_menu(state)),
+        submenu(lang, Msg::PlaybackSpeed, speed_menu(state)),
+    ];
+    if !state.outputs.is_empty() {
+        items.push(submenu(lang, Msg::OutputDevice, output_menu(state)));
+    }
+    if !state.mixer.is_empty() {
+        items.push(submenu(lang, Msg::VolumeMixer, mixer_menu(state)));
+    }
+    items.extend([
+        MenuItem::Separator,
+        submenu(lang, Msg::Settings, settings_menu(state)),
+        MenuItem::Separator,
+        command(lang, Msg::Exit, MenuCommand::Exit),
+    ]);
+    items
+}
+
+/// "Title - Artist (App)", or a placeholder when nothing is playing.
+fn now_playing(state: &State) -> String {
+    let Some(track) = state.track.as_ref().filter(|t| !t.title.is_empty()) else {
+        return i18n::text(state.language, Msg::NothingPlaying).to_string();
+    };
+    let mut label = track.title.clone();
+    if !track.artist.is_empty() {
+        label = format!("{} - {}", label, track.artist);
+    }
+    format!("{} ({})", label, flyout::session_name(&track.app_id))
+}
+
+fn sleep_menu(state: &State) -> Vec<MenuItem> {
+    let lang = state.language;
+    let current = state.sleep.choice();
+    let entry = |label: String, choice: SleepChoice, enabled: bool| MenuItem::Command {
+        label,
+        command: MenuCommand::SetSleep(choice),
+        checked: current == choice,
+        enabled,
+    };
+
+    let mut items = vec![entry(i18n::text(lang, Msg::SleepOff).to_string(), SleepChoice::Off, true)];
+    for minutes in SLEEP_PRESETS {
+        items.push(entry(i18n::count(lang, Msg::SleepMinutes, minutes as u64), SleepChoice::Minutes(minutes), true));
+    }
+    let custom = state.settings.sleep_custom;
+    items.push(entry(i18n::count(lang, Msg::SleepCustom, custom as u64), SleepChoice::Minutes(custom), true));
+    // Only a known track has an end to wait for
+    let has_track = state.track.is_some() || current == SleepChoice::EndOfTrack;
+    items.push(entry(i18n::text(lang, Msg::SleepEndOfTrack).to_string(), SleepChoice::EndOfTrack, has_track));
+    items.push(MenuItem::Separator);
+    items.push(toggle(lang, Msg::SleepFade, MenuCommand::ToggleSleepFade, state.settings.sleep_fade));
+    items
+}
+
+/// The speed presets, the current rate checked. Disabled while the session
+/// can't change its rate.
+fn speed_menu(state: &State) -> Vec<MenuItem> {
+    let current = state.modes.speed;
+    SPEED_PRESETS
+        .into_iter()
+        .map(|speed| MenuItem::Command {
+            label: core::speed_label(speed),
+            command: MenuCommand::SetSpeed(speed),
+            checked: current.is_some_and(|current| (current - speed).abs() < 0.01),
+            enabled: current.is_some(),
+        })
+        .collect()
+}
+
+/// Active output devices by name, the default one checked.
+fn output_menu(state: &State) -> Vec<MenuItem> {
+    state
+        .outputs
+        .iter()
+        .enumerate()
+        .map(|(index, device)| MenuItem::Command {
+            label: device.name.clone(),
+            command: MenuCommand::SetOutput(index),
+            checked: device.default,
+            enabled: true,
+        })
+        .collect()
+}
+
+/// A submenu per app, labelled with its level. The app of the media session
+/// comes first, set apart from the others.
+fn mixer_menu(state: &State) -> Vec<MenuItem> {
+    let lang = state.language;
+    let playing = state.track.as_ref().map(|track| track.app_id.as_str());
+    let mut items = Vec::new();
+    for (index, app) in state.mixer.iter().enumerate() {
+        let percent = (app.volume * 100.0).round() as u32;
+        let step = |msg: Msg, change: VolumeChange, enabled: bool| MenuItem::Command {
+            label: i18n::text(lang, msg).to_string(),
+            command: MenuCommand::AppVolume(index, change),
+            checked: false,
+            enabled,
+        };
+        items.push(MenuItem::Submenu {
+            label: format!("{} ({}%)", app.label(), percent),
+            items: vec![
+                toggle(lang, Msg::MuteAudio, MenuCommand::AppVolume(index, VolumeChange::ToggleMute), app.muted),
+                step(Msg::VolumeUp, VolumeChange::Up, percent < 100 || app.muted),
+                step(Msg::VolumeDown, VolumeChange::Down, percent > 0),
+            ],
+        });
+        let linked = playing.is_some_and(|app_id| mixer::same_app(app_id, &app.process));
+        if index == 0 && linked && state.mixer.len() > 1 {
+            items.push(MenuItem::Separator);
+        }
+    }
+    items
+}
+
+fn settings_menu(state: &State) -> Vec<MenuItem> {
+    let settings = &state.settings;
+    let lang = state.language;
+
+    let theme = |msg: Msg, value: IconTheme| toggle(lang, msg, MenuCommand::SetTheme(value), settings.icon_theme == value);
+
+    let auto_hide = |msg: Msg, value: AutoHide| {
+        toggle(lang, msg, MenuCommand::SetAutoHide(value), settings.auto_hide == value)
+    };
+
+    let mut notifications = vec![toggle(lang, Msg::NotifyOnTrackChange, MenuCommand::ToggleNotifyTrack, settings.notify_track)];
+    if let Some(app_id) = state.notifier.current_app() {
+        notifications.push(MenuItem::Command {
+            label: i18n::mute_app(lang, &app_id),
+            command: MenuCommand::ToggleMuteCurrentApp,
+            checked: core::is_app_muted(&app_id, &settings.notify_mute),
+            enabled: settings.notify_track,
+        });
+    }
+
+    vec![
+        toggle(lang, Msg::ShowPrevious, MenuCommand::ToggleShowPrev, settings.show_prev),
+        toggle(lang, Msg::ShowPlayPause, MenuCommand::ToggleShowPlay, settings.show_play),
+        toggle(lang, Msg::ShowNext, MenuCommand::ToggleShowNext, settings.show_next),
+        toggle(lang, Msg::ShowShuffle, MenuCommand::ToggleShowShuffle, settings.show_shuffle),
+        toggle(lang, Msg::ShowRepeat, MenuCommand::ToggleShowRepeat, settings.show_repeat),
+        toggle(lang, Msg::ShowSpeed, MenuCommand::ToggleShowSpeed, settings.show_speed),
+        toggle(lang, Msg::ShowOutput, MenuCommand::ToggleShowOutput, settings.show_output),
+        submenu(
+            lang,
+            Msg::AutoHide,
+            vec![
+                auto_hide(Msg::AutoHideNever, AutoHide::Never),
+                auto_hide(Msg::AutoHideNoSession, AutoHide::NoSession),
+                auto_hide(Msg::AutoHideStopped, AutoHide::Stopped),
+            ],
+        ),
+        MenuItem::Separator,
+        submenu(
+            lang,
+            Msg::IconTheme,
+            vec![
+                theme(Msg::ThemeAuto, IconTheme::Auto),
+                theme(Msg::ThemeLight, IconTheme::Light),
+                theme(Msg::ThemeDark, IconTheme::Dark),
+            ],
+        ),
+        toggle(lang, Msg::AnimatePlayPause, MenuCommand::ToggleAnimations, settings.animations),
+        toggle(lang, Msg::ShowProgressRing, MenuCommand::ToggleProgressRing, settings.progress_ring),
+        submenu(lang, Msg::TrackNotifications, notifications),
+        toggle(lang, Msg::MouseWheelControl, MenuCommand::ToggleWheel, settings.wheel),
+        toggle(lang, Msg::MiniPlayer, MenuCommand::ToggleFlyout, settings.flyout),
+        submenu(
+            lang,
+            Msg::AutoPause,
+            vec![
+                toggle(lang, Msg::PauseOnLock, MenuCommand::TogglePauseOnLock, settings.pause_on_lock),
+                MenuItem::Command {
+                    label: i18n::count(lang, Msg::PauseOnIdle, settings.idle_minutes as u64),
+                    command: MenuCommand::TogglePauseOnIdle,
+                    checked: settings.pause_on_idle,
+                    enabled: true,
+                },
+                toggle(lang, Msg::PauseOnCall, MenuCommand::TogglePauseOnCall, settings.pause_on_call),
+                toggle(lang, Msg::PauseOnUnplug, MenuCommand::TogglePauseOnUnplug, settings.pause_on_unplug),
+                MenuItem::Command {
+                    label: i18n::text(lang, Msg::ResumeOnReconnect).to_string(),
+                    command: MenuCommand::ToggleResumeOnReconnect,
+                    checked: settings.resume_on_reconnect,
+                    enabled: settings.pause_on_unplug,
+                },
+            ],
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+    use crate::core::{ModeState, TrackInfo};
+    use crate::mixer::AudioSession;
+    use crate::output::{DeviceClass, OutputDevice};
+    use std::fs;
+
+    /// One line per item, submenus indented: `[x]` checked, `(off)` disabled.
+    fn render(items: &[MenuItem], depth: usize, out: &mut String) {
+        let indent = "  ".repeat(depth);
+        for item in items {
+            match item {
+                MenuItem::Header(text) => out.push_str(&format!("{}# {}\n", indent, text)),
+                MenuItem::Separator => out.push_str(&format!("{}---\n", indent)),
+                MenuItem::Command { label, command, checked, enabled } => out.push_str(&format!(
+                    "{}{} {}{} => {:?}\n",
+                    indent,
+                    if *checked { "[x]" } else { "[ ]" },
+                    label.replace('\t', " | "),
+                    if *enabled { "" } else { " (off)" },
+                    command
+                )),
+                MenuItem::Submenu { label, items } => {
+                    out.push_str(&format!("{}> {}\n", indent, label.replace('\t', " | ")));
+                    render(items, depth + 1, out);
+                }
+            }
+        }
+    }
+
+    /// Compares the menu with `testdata/menus/<name>.txt`; `UPDATE_GOLDEN=1`
+    /// rewrites it instead.
+    fn assert_snapshot(name: &str, items: &[MenuItem]) {
+        let mut actual = String::new();
+        render(items, 0, &mut actual);
+        let path = format!("{}/testdata/menus/{}.txt", env!("CARGO_MANIFEST_DIR"), name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&path, &actual).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_else(|_| panic!("missing snapshot {}", path));
+        assert_eq!(actual, expected, "{}", name);
+    }
+
+    fn settings() -> Settings {
+        Settings::parse("prev=1\nplay=1\nnext=1\nnotify_track=1\n")
+    }
+
+    /// Spotify with a track loaded.
+    fn session() -> State {
+        let mut state = State::new(settings());
+        state.track = Some(TrackInfo {
+            app_id: "Spotify.exe".to_string(),
+            title: "Song".to_string(),
+            artist: "Artist".to_string(),
+        });
+        state.modes = ModeState { shuffle: Some(false), repeat: None, speed: None };
+        state
+    }
+
+    #[test]
+    fn menu_while_playing() {
+        let mut state = session();
+        state.playing = true;
+        assert_snapshot("playing", &context_menu(&state));
+    }
+
+    #[test]
+    fn menu_while_paused() {
+        let state = session();
+        assert_snapshot("paused", &context_menu(&state));
+    }
+
+    #[test]
+    fn menu_with_the_sleep_timer_armed() {
+        let mut state = session();
+        state.playing = true;
+        state.settings.sleep_custom = 45;
+        let (next, _) = core::update(
+            state,
+            core::Event::Menu { now: std::time::Instant::now(), command: MenuCommand::SetSleep(SleepChoice::Minutes(30)) },
+        );
+        assert_snapshot("sleep_armed", &context_menu(&next));
+    }
+
+    #[test]
+    fn menu_without_a_session() {
+        let state = State::new(settings());
+        assert_snapshot("no_session", &context_menu(&state));
+    }
+
+    #[test]
+    fn menu_with_a_speed_the_session_can_change() {
+        let mut state = session();
+        state.playing = true;
+        state.modes.speed = Some(1.5);
+        state.settings.show_speed = true;
+        assert_snapshot("speed", &context_menu(&state));
+    }
+
+    #[test]
+    fn menu_with_output_devices() {
+        let mut state = session();
+        let device = |id: &str, name: &str, class, default| {
+            OutputDevice { id: id.to_string(), name: name.to_string(), class, default }
+        };
+        state.outputs = vec![
+            device("{0.0.0.00000000}.{a}", "Speakers (Realtek(R) Audio)", DeviceClass::Speakers, false),
+            device("{0.0.0.00000000}.{b}", "Headphones (WH-1000XM4)", DeviceClass::Headphones, true),
+            device("{0.0.0.00000000}.{c}", "DELL U2720Q (NVIDIA High Definition Audio)", DeviceClass::Display, false),
+        ];
+        state.settings.show_output = true;
+        assert_snapshot("outputs", &context_menu(&state));
+    }
+
+    #[test]
+    fn menu_with_the_volume_mixer() {
+        let mut state = session();
+        state.playing = true;
+        let app = |process: &str, name: &str, volume, muted| AudioSession {
+            id: process.to_string(),
+            process: process.to_string(),
+            name: name.to_string(),
+            volume,
+            muted,
+        };
+        let sessions = vec![
+            app("chrome.exe", "", 0.8, false),
+            app("Discord.exe", "Discord", 0.0, false),
+            app("Spotify.exe", "", 1.0, true),
+        ];
+        let (next, _) = core::update(state, core::Event::Mixer(sessions));
+        assert_snapshot("mixer", &context_menu(&next));
+    }
+}
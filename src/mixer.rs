@@ -0,0 +1,306 @@
+// ============== Volume Mixer ==============
+//
+// Per-app volume for the menu's mixer and the sleep timer's fade. Backends
+// report the audio sessions on the default output and set their level by
+// process; grouping them per app, stepping levels, fading and linking apps to
+// the media session happen here, so they can be tested against a fake backend.
+
+use crate::flyout::session_name;
+
+/// Level change of a step control
+pub const VOLUME_STEP: f32 = 0.1;
+// Levels round-trip through the backend as floats; closer than this is the same
+const LEVEL_TOLERANCE: f32 = 0.005;
+
+/// One app's audio session, or all of an app's sessions once grouped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioSession {
+    /// Session instance identifier, unique among the live sessions
+    pub id: String,
+    /// Executable name, e.g. `Spotify.exe`
+    pub process: String,
+    /// Name the app gives its session; empty if it gives none
+    pub name: String,
+    /// From 0.0 to 1.0
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl AudioSession {
+    pub fn label(&self) -> String {
+        if self.name.is_empty() {
+            session_name(&self.process)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VolumeChange {
+    Up,
+    Down,
+    ToggleMute,
+}
+
+/// Where audio sessions come from; the shell implements it per platform.
+/// Setters apply to every session of the process, except
+/// `set_session_volume`, which sets the session with that `id` alone.
+pub trait MixerBackend {
+    fn sessions(&self) -> Vec<AudioSession>;
+    fn set_volume(&self, process: &str, volume: f32);
+    fn set_muted(&self, process: &str, muted: bool);
+    fn set_session_volume(&self, id: &str, volume: f32);
+}
+
+/// Whether a media session's app ID belongs to an audio session's process.
+/// Packaged apps are matched by the app name after the `!`.
+pub fn same_app(app_id: &str, process: &str) -> bool {
+    session_name(app_id).eq_ignore_ascii_case(&session_name(process))
+}
+
+/// One entry per process, with the level of its first session, in backend
+/// order except that the app of `playing` (a media app ID) comes first.
+pub fn apps(sessions: Vec<AudioSession>, playing: Option<&str>) -> Vec<AudioSession> {
+    let mut apps: Vec<AudioSession> = Vec::new();
+    for session in sessions {
+        if !apps.iter().any(|app| app.process.eq_ignore_ascii_case(&session.process)) {
+            apps.push(session);
+        }
+    }
+    if let Some(index) = playing.and_then(|app_id| apps.iter().position(|app| same_app(app_id, &app.process))) {
+        let app = apps.remove(index);
+        apps.insert(0, app);
+    }
+    apps
+}
+
+/// Applies a change to a process's sessions, starting from their current
+/// level. Steps land on whole steps, and turning an app up unmutes it.
+pub fn apply(backend: &dyn MixerBackend, process: &str, change: VolumeChange) {
+    let sessions = backend.sessions();
+    let Some(current) = sessions.iter().find(|session| session.process.eq_ignore_ascii_case(process)) else {
+        return;
+    };
+    let steps = (current.volume / VOLUME_STEP).round();
+    match change {
+        VolumeChange::Up => {
+            backend.set_volume(process, ((steps + 1.0) * VOLUME_STEP).min(1.0));
+            if current.muted {
+                backend.set_muted(process, false);
+            }
+        }
+        VolumeChange::Down => backend.set_volume(process, ((steps - 1.0) * VOLUME_STEP).max(0.0)),
+        VolumeChange::ToggleMute => backend.set_muted(process, !current.muted),
+    }
+}
+
+/// The sleep timer's fade on the media session's app. Each of the app's
+/// sessions remembers the level to put back and the one the fade last set,
+/// so a level the user picks meanwhile is left alone rather than faded
+/// further or overwritten.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppFade {
+    sessions: Vec<FadedSession>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct FadedSession {
+    id: String,
+    base: f32,
+    last: f32,
+    /// The user changed the level, or the session closed
+    overridden: bool,
+}
+
+impl AppFade {
+    /// Starts a fade on the sessions of the media app `app_id`. `None` if
+    /// that app has no audio on the default output. Sessions it opens later
+    /// keep their level.
+    pub fn start(backend: &dyn MixerBackend, app_id: &str) -> Option<AppFade> {
+        let sessions: Vec<FadedSession> = backend
+            .sessions()
+            .into_iter()
+            .filter(|session| same_app(app_id, &session.process))
+            .map(|session| FadedSession {
+                id: session.id,
+                base: session.volume,
+                last: session.volume,
+                overridden: false,
+            })
+            .collect();
+        (!sessions.is_empty()).then_some(AppFade { sessions })
+    }
+
+    /// Sets each session to `level` times the level it started from.
+    pub fn step(&mut self, backend: &dyn MixerBackend, level: f32) {
+        let current = backend.sessions();
+        for session in &mut self.sessions {
+            if session.overridden || !session.unchanged(&current) {
+                session.overridden = true;
+                continue;
+            }
+            session.last = (session.base * level).clamp(0.0, 1.0);
+            backend.set_session_volume(&session.id, session.last);
+        }
+    }
+
+    /// Puts each session's starting level back, unless the user has changed
+    /// it since.
+    pub fn restore(self, backend: &dyn MixerBackend) {
+        let current = backend.sessions();
+        for session in self.sessions.iter().filter(|session| !session.overridden && session.unchanged(&current)) {
+            backend.set_session_volume(&session.id, session.base);
+        }
+    }
+}
+
+impl FadedSession {
+    /// Whether the session is still at the level the fade last set.
+    fn unchanged(&self, current: &[AudioSession]) -> bool {
+        current
+            .iter()
+            .find(|session| session.id == self.id)
+            .is_some_and(|session| (session.volume - self.last).abs() < LEVEL_TOLERANCE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn session(process: &str, volume: f32, muted: bool) -> AudioSession {
+        let id = format!("{process}/{volume}");
+        AudioSession { id, process: process.to_string(), name: String::new(), volume, muted }
+    }
+
+    /// Keeps sessions in memory, applying setters to every matching one.
+    struct FakeMixer(RefCell<Vec<AudioSession>>);
+
+    impl MixerBackend for FakeMixer {
+        fn sessions(&self) -> Vec<AudioSession> {
+            self.0.borrow().clone()
+        }
+
+        fn set_volume(&self, process: &str, volume: f32) {
+            for session in self.0.borrow_mut().iter_mut().filter(|s| s.process.eq_ignore_ascii_case(process)) {
+                session.volume = volume;
+            }
+        }
+
+        fn set_muted(&self, process: &str, muted: bool) {
+            for session in self.0.borrow_mut().iter_mut().filter(|s| s.process.eq_ignore_ascii_case(process)) {
+                session.muted = muted;
+            }
+        }
+
+        fn set_session_volume(&self, id: &str, volume: f32) {
+            for session in self.0.borrow_mut().iter_mut().filter(|s| s.id == id) {
+                session.volume = volume;
+            }
+        }
+    }
+
+    fn mixer() -> FakeMixer {
+        FakeMixer(RefCell::new(vec![
+            session("chrome.exe", 0.73, false),
+            session("Spotify.exe", 1.0, true),
+            session("chrome.exe", 0.5, false),
+        ]))
+    }
+
+    fn levels(mixer: &FakeMixer) -> Vec<(f32, bool)> {
+        mixer.sessions().iter().map(|s| ((s.volume * 100.0).round() / 100.0, s.muted)).collect()
+    }
+
+    #[test]
+    fn media_apps_match_their_processes() {
+        assert!(same_app("Spotify.exe", "Spotify.exe"));
+        assert!(same_app("SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify", "Spotify.exe"));
+        assert!(same_app("Chrome", "chrome.exe"));
+        assert!(!same_app("MSEdge", "chrome.exe"));
+    }
+
+    #[test]
+    fn sessions_group_per_app_with_the_playing_one_first() {
+        let grouped = apps(mixer().sessions(), Some("Spotify.exe"));
+        assert_eq!(grouped, vec![session("Spotify.exe", 1.0, true), session("chrome.exe", 0.73, false)]);
+        assert_eq!(apps(mixer().sessions(), None)[0].process, "chrome.exe");
+        assert_eq!(apps(mixer().sessions(), Some("VLC.exe")).len(), 2);
+    }
+
+    #[test]
+    fn labels_fall_back_to_the_executable() {
+        assert_eq!(session("Spotify.exe", 1.0, false).label(), "Spotify");
+        let named = AudioSession { name: "Discord".to_string(), ..session("Discord.exe", 1.0, false) };
+        assert_eq!(named.label(), "Discord");
+    }
+
+    #[test]
+    fn steps_land_on_whole_steps_for_every_session() {
+        let mixer = mixer();
+        apply(&mixer, "Chrome.exe", VolumeChange::Down);
+        assert_eq!(levels(&mixer), [(0.6, false), (1.0, true), (0.6, false)]);
+        apply(&mixer, "chrome.exe", VolumeChange::Up);
+        apply(&mixer, "chrome.exe", VolumeChange::Up);
+        assert_eq!(levels(&mixer), [(0.8, false), (1.0, true), (0.8, false)]);
+    }
+
+    #[test]
+    fn levels_stay_in_range_and_turning_up_unmutes() {
+        let mixer = mixer();
+        apply(&mixer, "Spotify.exe", VolumeChange::Up);
+        assert_eq!(levels(&mixer)[1], (1.0, false));
+        for _ in 0..12 {
+            apply(&mixer, "Spotify.exe", VolumeChange::Down);
+        }
+        assert_eq!(levels(&mixer)[1], (0.0, false));
+    }
+
+    #[test]
+    fn mute_toggles_and_unknown_apps_are_left_alone() {
+        let mixer = mixer();
+        apply(&mixer, "chrome.exe", VolumeChange::ToggleMute);
+        apply(&mixer, "Spotify.exe", VolumeChange::ToggleMute);
+        assert_eq!(levels(&mixer), [(0.73, true), (1.0, false), (0.5, true)]);
+        apply(&mixer, "vlc.exe", VolumeChange::Up);
+        assert_eq!(levels(&mixer), [(0.73, true), (1.0, false), (0.5, true)]);
+    }
+
+    #[test]
+    fn fade_lowers_only_the_media_app_and_puts_it_back() {
+        let mixer = mixer();
+        let mut fade = AppFade::start(&mixer, "SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify").unwrap();
+        fade.step(&mixer, 0.5);
+        assert_eq!(levels(&mixer), [(0.73, false), (0.5, true), (0.5, false)]);
+        fade.step(&mixer, 0.25);
+        assert_eq!(levels(&mixer)[1], (0.25, true));
+        fade.restore(&mixer);
+        assert_eq!(levels(&mixer), [(0.73, false), (1.0, true), (0.5, false)]);
+
+        assert_eq!(AppFade::start(&mixer, "VLC"), None);
+    }
+
+    #[test]
+    fn fade_keeps_each_sessions_own_level() {
+        let mixer = mixer();
+        let mut fade = AppFade::start(&mixer, "chrome.exe").unwrap();
+        fade.step(&mixer, 0.5);
+        assert_eq!(levels(&mixer), [(0.37, false), (1.0, true), (0.25, false)]);
+        fade.restore(&mixer);
+        assert_eq!(levels(&mixer), [(0.73, false), (1.0, true), (0.5, false)]);
+    }
+
+    #[test]
+    fn fade_leaves_a_level_the_user_changed() {
+        let mixer = mixer();
+        let mut fade = AppFade::start(&mixer, "chrome.exe").unwrap();
+        fade.step(&mixer, 0.5);
+        mixer.set_session_volume("chrome.exe/0.73", 0.9);
+        fade.step(&mixer, 0.25);
+        assert_eq!(levels(&mixer), [(0.9, false), (1.0, true), (0.13, false)]);
+        fade.restore(&mixer);
+        assert_eq!(levels(&mixer), [(0.9, false), (1.0, true), (0.5, false)]);
+    }
+}
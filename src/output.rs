@@ -0,0 +1,167 @@
+// ============== Output Devices ==============
+//
+// Audio output devices, switching between them, and the policy for pausing
+// when the default output moves away from the kind of device the user
+// listens on (e.g. Bluetooth headphones dropping out onto the speakers).
+// Backends list and switch devices; on Windows through its audio endpoints.
+
+/// Rough kind of an output device. Windows reports it as the endpoint's
+/// form factor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeviceClass {
+    /// Headphones and headsets, wired or wireless
+    Headphones,
+    Speakers,
+    /// A monitor or TV over HDMI or DisplayPort
+    Display,
+    Other,
+}
+
+impl DeviceClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeviceClass::Headphones => "headphones",
+            DeviceClass::Speakers => "speakers",
+            DeviceClass::Display => "display",
+            DeviceClass::Other => "other",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<DeviceClass> {
+        match value {
+            "headphones" => Some(DeviceClass::Headphones),
+            "speakers" => Some(DeviceClass::Speakers),
+            "display" => Some(DeviceClass::Display),
+            "other" => Some(DeviceClass::Other),
+            _ => None,
+        }
+    }
+}
+
+/// An active output device as the menu and the output icon show it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputDevice {
+    /// Backend's device ID, passed back to `set_default`
+    pub id: String,
+    pub name: String,
+    pub class: DeviceClass,
+    /// Whether this is the device apps play to
+    pub default: bool,
+}
+
+/// Lists and switches output devices; the shell implements it per platform.
+pub trait OutputBackend {
+    /// Active output devices, in the backend's order
+    fn outputs(&self) -> Vec<OutputDevice>;
+    fn set_default(&self, id: &str);
+}
+
+/// Device after the default one, wrapping around. `None` unless there is
+/// another device to switch to.
+pub fn next_output(devices: &[OutputDevice]) -> Option<&OutputDevice> {
+    let current = devices.iter().position(|device| device.default)?;
+    let next = &devices[(current + 1) % devices.len()];
+    (!next.default).then_some(next)
+}
+
+/// Watches the default output. Leaving a device of one of the watched
+/// classes starts `Away::Unplugged`; reaching one again ends it.
+#[derive(Clone, Debug, Default)]
+pub struct OutputWatch {
+    /// Whether the last default output was of a watched class; `None` until
+    /// the first observation
+    watched: Option<bool>,
+    left: bool,
+}
+
+impl OutputWatch {
+    /// Records the new default output. Returns true when it moved away from
+    /// the watched classes, and false when it came back.
+    pub fn observe(&mut self, class: Option<DeviceClass>, classes: &[DeviceClass]) -> Option<bool> {
+        let watched = class.is_some_and(|class| classes.contains(&class));
+        let was_watched = self.watched.replace(watched);
+        if self.left {
+            if watched {
+                self.left = false;
+                return Some(false);
+            }
+        } else if was_watched == Some(true) && !watched {
+            self.left = true;
+            return Some(true);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADPHONES: &[DeviceClass] = &[DeviceClass::Headphones];
+
+    fn device(id: &str, class: DeviceClass, default: bool) -> OutputDevice {
+        OutputDevice { id: id.to_string(), name: id.to_string(), class, default }
+    }
+
+    #[test]
+    fn parses_classes() {
+        for class in [DeviceClass::Headphones, DeviceClass::Speakers, DeviceClass::Display, DeviceClass::Other] {
+            assert_eq!(DeviceClass::parse(class.as_str()), Some(class));
+        }
+        assert_eq!(DeviceClass::parse("usb"), None);
+    }
+
+    #[test]
+    fn leaving_headphones_for_speakers_and_back() {
+        let mut watch = OutputWatch::default();
+        let buds = Some(DeviceClass::Headphones);
+        let speakers = Some(DeviceClass::Speakers);
+        assert_eq!(watch.observe(buds, HEADPHONES), None);
+        assert_eq!(watch.observe(speakers, HEADPHONES), Some(true));
+        // Repeated notifications for the same device change nothing
+        assert_eq!(watch.observe(speakers, HEADPHONES), None);
+        assert_eq!(watch.observe(buds, HEADPHONES), Some(false));
+        assert_eq!(watch.observe(buds, HEADPHONES), None);
+    }
+
+    #[test]
+    fn losing_every_output_counts_as_leaving() {
+        let mut watch = OutputWatch::default();
+        watch.observe(Some(DeviceClass::Headphones), HEADPHONES);
+        assert_eq!(watch.observe(None, HEADPHONES), Some(true));
+        // Other headphones are as good as the ones that left
+        assert_eq!(watch.observe(Some(DeviceClass::Headphones), HEADPHONES), Some(false));
+    }
+
+    #[test]
+    fn unwatched_devices_never_trigger() {
+        let mut watch = OutputWatch::default();
+        assert_eq!(watch.observe(Some(DeviceClass::Speakers), HEADPHONES), None);
+        assert_eq!(watch.observe(Some(DeviceClass::Display), HEADPHONES), None);
+        assert_eq!(watch.observe(None, HEADPHONES), None);
+
+        let both = &[DeviceClass::Headphones, DeviceClass::Display];
+        assert_eq!(watch.observe(Some(DeviceClass::Display), both), None);
+        assert_eq!(watch.observe(Some(DeviceClass::Speakers), both), Some(true));
+    }
+
+    #[test]
+    fn next_output_wraps_around() {
+        let mut devices = vec![
+            device("speakers", DeviceClass::Speakers, false),
+            device("buds", DeviceClass::Headphones, true),
+            device("monitor", DeviceClass::Display, false),
+        ];
+        assert_eq!(next_output(&devices).map(|d| d.id.as_str()), Some("monitor"));
+        devices[1].default = false;
+        devices[2].default = true;
+        assert_eq!(next_output(&devices).map(|d| d.id.as_str()), Some("speakers"));
+    }
+
+    #[test]
+    fn next_output_needs_a_default_and_another_device() {
+        assert_eq!(next_output(&[]), None);
+        assert_eq!(next_output(&[device("speakers", DeviceClass::Speakers, true)]), None);
+        assert_eq!(next_output(&[device("speakers", DeviceClass::Speakers, false)]), None);
+    }
+}
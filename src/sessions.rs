@@ -0,0 +1,303 @@
+// ============== Session Rules ==============
+//
+// Picks the media session the icons reflect and control. Rules come from
+// ordered `session=<kind> <pattern>` lines in the config, where the pattern is
+// a case-insensitive glob over the session's app ID (there is no regex
+// syntax). Without rules, Windows' own current session is used unchanged.
+// Also tells sessions apart when resuming the ones ClickPlay paused.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RuleKind {
+    /// Pick over unmatched sessions; earlier `prefer` rules rank higher
+    Prefer,
+    /// Pick only when nothing else is available
+    Avoid,
+    /// Never pick
+    Ignore,
+}
+
+impl RuleKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RuleKind::Prefer => "prefer",
+            RuleKind::Avoid => "avoid",
+            RuleKind::Ignore => "ignore",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<RuleKind> {
+        match value {
+            "prefer" => Some(RuleKind::Prefer),
+            "avoid" => Some(RuleKind::Avoid),
+            "ignore" => Some(RuleKind::Ignore),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionRule {
+    pub kind: RuleKind,
+    pub pattern: String,
+}
+
+impl SessionRule {
+    /// Parses `<kind> <pattern>`, e.g. `prefer Spotify*`.
+    pub fn parse(value: &str) -> Option<SessionRule> {
+        let (kind, pattern) = value.trim().split_once(char::is_whitespace)?;
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(SessionRule { kind: RuleKind::parse(kind)?, pattern: pattern.to_string() })
+    }
+
+    pub fn to_config(&self) -> String {
+        format!("session={} {}", self.kind.as_str(), self.pattern)
+    }
+
+    pub fn matches(&self, app_id: &str) -> bool {
+        glob_match(&self.pattern, app_id)
+    }
+}
+
+/// Case-insensitive glob match of the whole text. `*` matches any run of
+/// characters and `?` exactly one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().flat_map(char::to_lowercase).collect();
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and the text position it currently absorbs up to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        // `*` first, so it is a wildcard even where the text has a literal `*`
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if let Some((after_star, absorbed)) = star {
+            // Let the last `*` swallow one more character and retry
+            p = after_star;
+            t = absorbed + 1;
+            star = Some((after_star, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// One open session as seen by the selector.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Candidate<'a> {
+    pub app_id: &'a str,
+    pub playing: bool,
+    /// Whether Windows considers it the current session
+    pub current: bool,
+}
+
+/// Index of the session to use, or `None` if every session is ignored. The
+/// first matching rule decides a session's tier (`prefer`, unmatched,
+/// `avoid`); within a tier a playing session wins, then Windows' current one,
+/// then enumeration order.
+pub fn select(rules: &[SessionRule], candidates: &[Candidate]) -> Option<usize> {
+    candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, candidate)| {
+            let tier = match rules.iter().position(|rule| rule.matches(candidate.app_id)) {
+                None => (1, 0),
+                Some(rule) => match rules[rule].kind {
+                    RuleKind::Prefer => (0, rule),
+                    RuleKind::Avoid => (2, rule),
+                    RuleKind::Ignore => return None,
+                },
+            };
+            Some((tier, !candidate.playing, !candidate.current, index))
+        })
+        .min()
+        .map(|(.., index)| index)
+}
+
+/// A session ClickPlay paused, to resume later. Windows gives sessions no
+/// lasting ID, so one is told apart from other sessions of its app (e.g.
+/// another browser tab) by the title it had when paused. Two sessions of
+/// one app paused on the same title still can't be told apart.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PausedSession {
+    pub app_id: String,
+    pub title: String,
+}
+
+/// One open session as seen when resuming.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResumeCandidate<'a> {
+    pub app_id: &'a str,
+    pub title: &'a str,
+    pub paused: bool,
+}
+
+/// Indexes of the sessions to resume: for each remembered session, the
+/// first paused one with the same app ID and title that no earlier entry
+/// took. Sessions that closed since are simply gone, and ones the user
+/// restarted meanwhile aren't paused.
+pub fn to_resume(remembered: &[PausedSession], candidates: &[ResumeCandidate]) -> Vec<usize> {
+    let mut picked = Vec::new();
+    for session in remembered {
+        let found = (0..candidates.len()).find(|index| {
+            let candidate = &candidates[*index];
+            candidate.paused
+                && candidate.app_id == session.app_id
+                && candidate.title == session.title
+                && !picked.contains(index)
+        });
+        if let Some(index) = found {
+            picked.push(index);
+        }
+    }
+    picked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matching() {
+        let cases = [
+            ("spotify.exe", "Spotify.exe", true),
+            ("Spotify*", "Spotify.exe", true),
+            ("*", "", true),
+            ("*", "anything", true),
+            ("", "", true),
+            ("", "a", false),
+            ("a", "", false),
+            ("a*", "a", true),
+            ("a*", "a*x", true),
+            ("a*x", "a*x", true),
+            ("*.exe", "chrome.exe", true),
+            ("*.exe", "chrome.exe.bak", false),
+            ("*chrome*", "Microsoft.Chrome.Beta", true),
+            ("Micro*Edge*", "MicrosoftEdge.Stable_8wekyb3d8bbwe!App", true),
+            ("Micro*Edge", "MicrosoftEdge.Stable", false),
+            ("a*b*c", "aXbYbZc", true),
+            ("a*b*c", "aXbYbZ", false),
+            ("**", "x", true),
+            ("?", "x", true),
+            ("?", "", false),
+            ("?", "xy", false),
+            ("vlc.???", "vlc.exe", true),
+            ("vlc.???", "vlc.ex", false),
+            ("*?", "", false),
+            ("*?", "x", true),
+            ("Spotify", "Spotify.exe", false),
+            ("ÄPP*", "äpp.exe", true),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(glob_match(pattern, text), expected, "{:?} against {:?}", pattern, text);
+        }
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rule = |kind, pattern: &str| Some(SessionRule { kind, pattern: pattern.to_string() });
+        assert_eq!(SessionRule::parse("prefer Spotify*"), rule(RuleKind::Prefer, "Spotify*"));
+        assert_eq!(SessionRule::parse("  ignore   Teams*  "), rule(RuleKind::Ignore, "Teams*"));
+        assert_eq!(SessionRule::parse("avoid *Edge*"), rule(RuleKind::Avoid, "*Edge*"));
+        assert_eq!(SessionRule::parse("prefer"), None);
+        assert_eq!(SessionRule::parse("prefer   "), None);
+        assert_eq!(SessionRule::parse("like Spotify*"), None);
+        assert_eq!(SessionRule::parse("prefer Spotify*").unwrap().to_config(), "session=prefer Spotify*");
+    }
+
+    /// Name, rules, open sessions and the one expected to be picked
+    type Case<'a> = (&'a str, &'a [&'a str], &'a [Candidate<'a>], Option<usize>);
+
+    fn candidate(app_id: &str, playing: bool, current: bool) -> Candidate<'_> {
+        Candidate { app_id, playing, current }
+    }
+
+    #[test]
+    fn selection() {
+        let spotify_paused = candidate("Spotify.exe", false, false);
+        let chrome_playing = candidate("chrome.exe", true, false);
+        let edge_current = candidate("MSEdge", false, true);
+        let teams_playing = candidate("MSTeams_8wekyb3d8bbwe!MSTeams", true, true);
+
+        let cases: [Case; 14] = [
+            ("nothing open", &[], &[], None),
+            ("no rules: playing wins", &[], &[edge_current, chrome_playing], Some(1)),
+            ("no rules: then current", &[], &[spotify_paused, edge_current], Some(1)),
+            ("no rules: then order", &[], &[spotify_paused, candidate("vlc.exe", false, false)], Some(0)),
+            ("prefer beats playing", &["prefer Spotify*"], &[chrome_playing, spotify_paused], Some(1)),
+            (
+                "earlier prefer ranks higher",
+                &["prefer vlc*", "prefer Spotify*"],
+                &[spotify_paused, candidate("vlc.exe", false, false)],
+                Some(1),
+            ),
+            (
+                "playing wins within a tier",
+                &["prefer *"],
+                &[spotify_paused, chrome_playing],
+                Some(1),
+            ),
+            ("avoid loses to unmatched", &["avoid chrome*"], &[chrome_playing, spotify_paused], Some(1)),
+            ("avoid used as last resort", &["avoid chrome*"], &[chrome_playing], Some(0)),
+            ("ignore never picked", &["ignore MSTeams*"], &[teams_playing, spotify_paused], Some(1)),
+            ("all ignored", &["ignore *"], &[teams_playing, spotify_paused], None),
+            (
+                "first matching rule decides",
+                &["ignore chrome*", "prefer *"],
+                &[chrome_playing, spotify_paused],
+                Some(1),
+            ),
+            (
+                "earlier prefer shadows later ignore",
+                &["prefer chrome*", "ignore *"],
+                &[spotify_paused, chrome_playing],
+                Some(1),
+            ),
+            ("rules are case-insensitive", &["ignore SPOTIFY.EXE"], &[spotify_paused], None),
+        ];
+        for (name, rules, candidates, expected) in cases {
+            let rules: Vec<SessionRule> = rules.iter().map(|line| SessionRule::parse(line).unwrap()).collect();
+            assert_eq!(select(&rules, candidates), expected, "{}", name);
+        }
+    }
+
+    fn paused(app_id: &str, title: &str) -> PausedSession {
+        PausedSession { app_id: app_id.to_string(), title: title.to_string() }
+    }
+
+    fn open<'a>(app_id: &'a str, title: &'a str, paused: bool) -> ResumeCandidate<'a> {
+        ResumeCandidate { app_id, title, paused }
+    }
+
+    #[test]
+    fn resumes_only_the_sessions_that_were_paused() {
+        // Two tabs of one browser: ClickPlay paused the video, the user the podcast
+        let remembered = [paused("Chrome", "Video"), paused("Spotify.exe", "Song")];
+        let candidates = [open("Chrome", "Podcast", true), open("Chrome", "Video", true), open("Spotify.exe", "Song", true)];
+        assert_eq!(to_resume(&remembered, &candidates), [1, 2]);
+    }
+
+    #[test]
+    fn sessions_restarted_or_closed_are_left_alone() {
+        let remembered = [paused("Chrome", "Video"), paused("Spotify.exe", "Song")];
+        assert_eq!(to_resume(&remembered, &[open("Chrome", "Video", false)]), Vec::<usize>::new());
+        assert_eq!(to_resume(&remembered, &[open("Chrome", "Other", true)]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn each_remembered_session_resumes_one() {
+        let remembered = [paused("Chrome", "Video")];
+        let candidates = [open("Chrome", "Video", true), open("Chrome", "Video", true)];
+        assert_eq!(to_resume(&remembered, &candidates), [0]);
+        let both = [paused("Chrome", "Video"), paused("Chrome", "Video")];
+        assert_eq!(to_resume(&both, &candidates), [0, 1]);
+    }
+}
@@ -0,0 +1,375 @@
+// ============== Sleep Timer ==============
+//
+// Pauses playback after a set time or when the current track ends, with an
+// optional volume fade over the last stretch. Deadlines are monotonic
+// `Instant`s, which may stop while the PC is suspended; each tick also
+// carries the wall clock, and time the wall clock saw pass but the monotonic
+// clock missed is taken off the deadline, so a timer that ran out during
+// sleep fires on resume instead of starting over.
+
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::core::{Timeline, TrackInfo};
+
+pub const SLEEP_TICK_MS: u32 = 1000;
+pub const SLEEP_PRESETS: [u32; 3] = [15, 30, 60];
+
+// The volume ramps down over this last part of the countdown
+const SLEEP_FADE: Duration = Duration::from_secs(30);
+// Ticks are a second apart, so a larger gap between the clocks means suspend
+const SUSPEND_THRESHOLD: Duration = Duration::from_secs(2);
+// The faded volume comes back once the player has had time to pause
+const RESTORE_DELAY: Duration = Duration::from_secs(2);
+// Badge arc resolution; the icon is only redrawn when the quantized value changes
+const BADGE_STEPS: f32 = 24.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SleepChoice {
+    #[default]
+    Off,
+    Minutes(u32),
+    EndOfTrack,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SleepStep {
+    /// Set the playing app to this fraction of its level when the fade began
+    Volume(f32),
+    Pause,
+    RestoreVolume,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Plan {
+    Until { deadline: Instant, length: Duration },
+    EndOfTrack {
+        track: TrackInfo,
+        /// Set once the track changes or the session goes away
+        ended: bool,
+        /// Extrapolated from when the reported position last moved
+        ends_at: Option<Instant>,
+        length: Option<Duration>,
+        position: i64,
+    },
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SleepTimer {
+    plan: Option<Plan>,
+    last_tick: Option<(Instant, SystemTime)>,
+    /// The volume was lowered and must be put back
+    faded: bool,
+    restore_at: Option<Instant>,
+}
+
+impl SleepTimer {
+    /// Replaces the current timer. `track` is what end-of-track waits for; without
+    /// one that choice does nothing. Returns the steps undoing a fade in progress.
+    pub fn arm(&mut self, choice: SleepChoice, now: Instant, track: Option<&TrackInfo>) -> Vec<SleepStep> {
+        let mut steps = Vec::new();
+        if self.faded {
+            self.faded = false;
+            self.restore_at = None;
+            steps.push(SleepStep::RestoreVolume);
+        }
+        self.last_tick = None;
+        self.plan = match (choice, track) {
+            (SleepChoice::Minutes(minutes), _) if minutes > 0 => {
+                let length = Duration::from_secs(minutes as u64 * 60);
+                Some(Plan::Until { deadline: now + length, length })
+            }
+            (SleepChoice::EndOfTrack, Some(track)) => Some(Plan::EndOfTrack {
+                track: track.clone(),
+                ended: false,
+                ends_at: None,
+                length: None,
+                position: 0,
+            }),
+            _ => None,
+        };
+        steps
+    }
+
+    pub fn choice(&self) -> SleepChoice {
+        match &self.plan {
+            None => SleepChoice::Off,
+            Some(Plan::Until { length, .. }) => SleepChoice::Minutes((length.as_secs() / 60) as u32),
+            Some(Plan::EndOfTrack { .. }) => SleepChoice::EndOfTrack,
+        }
+    }
+
+    pub fn armed(&self) -> bool {
+        self.plan.is_some()
+    }
+
+    /// Whether ticks are still needed, including to restore a faded volume.
+    pub fn active(&self) -> bool {
+        self.plan.is_some() || self.restore_at.is_some()
+    }
+
+    pub fn waits_for_track_end(&self) -> bool {
+        matches!(self.plan, Some(Plan::EndOfTrack { .. }))
+    }
+
+    /// Time left, if known. End-of-track only knows it once a timeline arrived.
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        match self.plan.as_ref()? {
+            Plan::Until { deadline, .. } => Some(deadline.saturating_duration_since(now)),
+            Plan::EndOfTrack { ended: true, .. } => Some(Duration::ZERO),
+            Plan::EndOfTrack { ends_at, .. } => ends_at.map(|at| at.saturating_duration_since(now)),
+        }
+    }
+
+    /// Fraction of the countdown left, quantized for the icon badge. Full
+    /// while the end of the track isn't known yet.
+    pub fn badge(&self, now: Instant) -> Option<f32> {
+        let length = match self.plan.as_ref()? {
+            Plan::Until { length, .. } => Some(*length),
+            Plan::EndOfTrack { length, .. } => *length,
+        };
+        let fraction = match (self.remaining(now), length) {
+            (Some(remaining), Some(length)) if !length.is_zero() => {
+                (remaining.as_secs_f32() / length.as_secs_f32()).clamp(0.0, 1.0)
+            }
+            _ => 1.0,
+        };
+        Some((fraction * BADGE_STEPS).round() / BADGE_STEPS)
+    }
+
+    /// Whole minutes left, rounded up, for the tooltip.
+    pub fn minutes_left(&self, now: Instant) -> Option<u64> {
+        self.remaining(now).map(|remaining| remaining.as_secs().div_ceil(60))
+    }
+
+    /// Notes the current track; any change ends the track being waited for.
+    pub fn observe_track(&mut self, current: Option<&TrackInfo>) {
+        if let Some(Plan::EndOfTrack { track, ended, .. }) = &mut self.plan {
+            if current != Some(&*track) {
+                *ended = true;
+            }
+        }
+    }
+
+    /// Anchors the end of the track. While paused the end isn't known, so it is
+    /// taken again from the next timeline after playback resumes.
+    pub fn observe_timeline(&mut self, now: Instant, timeline: Option<Timeline>, playing: bool) {
+        let Some(Plan::EndOfTrack { ends_at, length, position, .. }) = &mut self.plan else {
+            return;
+        };
+        let Some(timeline) = timeline.filter(|t| t.end > t.start && playing) else {
+            *ends_at = None;
+            return;
+        };
+        *length = Some(from_ticks(timeline.end - timeline.start));
+        if ends_at.is_none() || *position != timeline.position {
+            *position = timeline.position;
+            *ends_at = Some(now + from_ticks(timeline.end - timeline.position));
+        }
+    }
+
+    /// Advances the timer. `fade` lowers the volume over the last stretch.
+    pub fn tick(&mut self, now: Instant, wall: SystemTime, fade: bool) -> Vec<SleepStep> {
+        self.catch_up(now, wall);
+
+        let mut steps = Vec::new();
+        if self.restore_at.is_some_and(|at| now >= at) {
+            self.restore_at = None;
+            self.faded = false;
+            steps.push(SleepStep::RestoreVolume);
+        }
+
+        let Some(remaining) = self.remaining(now) else {
+            return steps;
+        };
+        if remaining.is_zero() {
+            self.plan = None;
+            self.last_tick = None;
+            steps.push(SleepStep::Pause);
+            if self.faded {
+                self.restore_at = Some(now + RESTORE_DELAY);
+            }
+        } else if fade && remaining < SLEEP_FADE {
+            self.faded = true;
+            steps.push(SleepStep::Volume(remaining.as_secs_f32() / SLEEP_FADE.as_secs_f32()));
+        }
+        steps
+    }
+
+    /// Takes time spent suspended off the deadline.
+    fn catch_up(&mut self, now: Instant, wall: SystemTime) {
+        let last = self.last_tick.replace((now, wall));
+        let Some((last_now, last_wall)) = last else {
+            return;
+        };
+        let Ok(wall_elapsed) = wall.duration_since(last_wall) else {
+            return;
+        };
+        let missed = wall_elapsed.saturating_sub(now.duration_since(last_now));
+        if missed < SUSPEND_THRESHOLD {
+            return;
+        }
+        match &mut self.plan {
+            Some(Plan::Until { deadline, .. }) => {
+                *deadline = deadline.checked_sub(missed).unwrap_or(now);
+            }
+            // The position moved on while we weren't looking
+            Some(Plan::EndOfTrack { ends_at, .. }) => *ends_at = None,
+            None => {}
+        }
+    }
+}
+
+fn from_ticks(ticks: i64) -> Duration {
+    Duration::from_nanos(ticks.max(0) as u64 * 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fake clocks: `at(s)` and `wall(s)` are that many seconds after fixed starts.
+    fn clocks() -> (impl Fn(u64) -> Instant, impl Fn(u64) -> SystemTime) {
+        let start = Instant::now();
+        let wall_start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        (move |s| start + Duration::from_secs(s), move |s| wall_start + Duration::from_secs(s))
+    }
+
+    fn track(title: &str) -> TrackInfo {
+        TrackInfo { app_id: "Spotify.exe".to_string(), title: title.to_string(), artist: String::new() }
+    }
+
+    fn timeline(position_s: i64, end_s: i64) -> Option<Timeline> {
+        Some(Timeline { position: position_s * 10_000_000, start: 0, end: end_s * 10_000_000 })
+    }
+
+    #[test]
+    fn pauses_when_the_minutes_run_out() {
+        let (at, wall) = clocks();
+        let mut timer = SleepTimer::default();
+        assert_eq!(timer.arm(SleepChoice::Minutes(15), at(0), None), vec![]);
+        assert_eq!(timer.choice(), SleepChoice::Minutes(15));
+        assert_eq!(timer.minutes_left(at(1)), Some(15));
+        assert_eq!(timer.minutes_left(at(61)), Some(14));
+
+        assert_eq!(timer.tick(at(899), wall(899), false), vec![]);
+        assert_eq!(timer.tick(at(900), wall(900), false), vec![SleepStep::Pause]);
+        assert!(!timer.active());
+        assert_eq!(timer.tick(at(901), wall(901), false), vec![]);
+    }
+
+    #[test]
+    fn fades_out_then_restores_the_volume() {
+        let (at, wall) = clocks();
+        let mut timer = SleepTimer::default();
+        timer.arm(SleepChoice::Minutes(1), at(0), None);
+
+        assert_eq!(timer.tick(at(30), wall(30), true), vec![]);
+        assert_eq!(timer.tick(at(45), wall(45), true), vec![SleepStep::Volume(0.5)]);
+        assert_eq!(timer.tick(at(57), wall(57), true), vec![SleepStep::Volume(0.1)]);
+        assert_eq!(timer.tick(at(60), wall(60), true), vec![SleepStep::Pause]);
+        // Still ticking until the volume is back
+        assert!(timer.active() && !timer.armed());
+        assert_eq!(timer.tick(at(61), wall(61), true), vec![]);
+        assert_eq!(timer.tick(at(62), wall(62), true), vec![SleepStep::RestoreVolume]);
+        assert!(!timer.active());
+    }
+
+    #[test]
+    fn cancelling_during_the_fade_restores_the_volume() {
+        let (at, wall) = clocks();
+        let mut timer = SleepTimer::default();
+        timer.arm(SleepChoice::Minutes(1), at(0), None);
+        timer.tick(at(50), wall(50), true);
+        assert_eq!(timer.arm(SleepChoice::Off, at(51), None), vec![SleepStep::RestoreVolume]);
+        assert!(!timer.active());
+        assert_eq!(timer.tick(at(70), wall(70), true), vec![]);
+    }
+
+    #[test]
+    fn time_spent_suspended_counts() {
+        let (at, wall) = clocks();
+        let mut timer = SleepTimer::default();
+        timer.arm(SleepChoice::Minutes(30), at(0), None);
+        timer.tick(at(600), wall(600), false);
+
+        // Suspended for an hour; the monotonic clock only moved a second
+        assert_eq!(timer.tick(at(601), wall(4200), false), vec![SleepStep::Pause]);
+    }
+
+    #[test]
+    fn suspend_shortens_the_countdown() {
+        let (at, wall) = clocks();
+        let mut timer = SleepTimer::default();
+        timer.arm(SleepChoice::Minutes(30), at(0), None);
+        timer.tick(at(600), wall(600), false);
+
+        // Ten minutes asleep, twenty minutes into the countdown
+        assert_eq!(timer.tick(at(601), wall(1201), false), vec![]);
+        assert_eq!(timer.minutes_left(at(601)), Some(10));
+        // A monotonic clock that kept running isn't corrected twice
+        assert_eq!(timer.tick(at(1200), wall(1800), false), vec![SleepStep::Pause]);
+    }
+
+    #[test]
+    fn wall_clock_set_back_changes_nothing() {
+        let (at, wall) = clocks();
+        let mut timer = SleepTimer::default();
+        timer.arm(SleepChoice::Minutes(15), at(0), None);
+        timer.tick(at(600), wall(600), false);
+        assert_eq!(timer.tick(at(601), wall(0), false), vec![]);
+        assert_eq!(timer.minutes_left(at(601)), Some(5));
+    }
+
+    #[test]
+    fn end_of_track_waits_for_the_track_to_change() {
+        let (at, wall) = clocks();
+        let mut timer = SleepTimer::default();
+        // Nothing to wait for without a track
+        timer.arm(SleepChoice::EndOfTrack, at(0), None);
+        assert!(!timer.armed());
+
+        timer.arm(SleepChoice::EndOfTrack, at(0), Some(&track("Episode 1")));
+        assert_eq!(timer.choice(), SleepChoice::EndOfTrack);
+        assert_eq!(timer.remaining(at(0)), None);
+        assert_eq!(timer.badge(at(0)), Some(1.0));
+        timer.observe_track(Some(&track("Episode 1")));
+        assert_eq!(timer.tick(at(1), wall(1), false), vec![]);
+
+        timer.observe_track(Some(&track("Episode 2")));
+        assert_eq!(timer.tick(at(2), wall(2), false), vec![SleepStep::Pause]);
+    }
+
+    #[test]
+    fn end_of_track_follows_the_timeline() {
+        let (at, wall) = clocks();
+        let mut timer = SleepTimer::default();
+        timer.arm(SleepChoice::EndOfTrack, at(0), Some(&track("Episode 1")));
+        timer.observe_timeline(at(0), timeline(540, 600), true);
+        assert_eq!(timer.remaining(at(0)), Some(Duration::from_secs(60)));
+        assert_eq!(timer.badge(at(0)), Some(2.0 / 24.0));
+
+        // Players that don't update the position are extrapolated
+        timer.observe_timeline(at(20), timeline(540, 600), true);
+        assert_eq!(timer.remaining(at(20)), Some(Duration::from_secs(40)));
+        assert_eq!(timer.tick(at(40), wall(40), true), vec![SleepStep::Volume(20.0 / 30.0)]);
+
+        // Paused: the end is unknown until playback goes on
+        timer.observe_timeline(at(45), timeline(585, 600), false);
+        assert_eq!(timer.remaining(at(100)), None);
+        assert_eq!(timer.tick(at(100), wall(100), false), vec![]);
+        timer.observe_timeline(at(100), timeline(585, 600), true);
+        assert_eq!(timer.tick(at(115), wall(115), false), vec![SleepStep::Pause]);
+    }
+
+    #[test]
+    fn badge_shrinks_in_steps() {
+        let (at, _) = clocks();
+        let mut timer = SleepTimer::default();
+        assert_eq!(timer.badge(at(0)), None);
+        timer.arm(SleepChoice::Minutes(24), at(0), None);
+        assert_eq!(timer.badge(at(0)), Some(1.0));
+        assert_eq!(timer.badge(at(20)), Some(1.0));
+        assert_eq!(timer.badge(at(60)), Some(23.0 / 24.0));
+        assert_eq!(timer.badge(at(12 * 60)), Some(0.5));
+    }
+}
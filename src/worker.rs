@@ -0,0 +1,223 @@
+// ============== Worker Plumbing ==============
+//
+// What a worker thread that calls into other processes needs from the UI
+// side: a command queue that can't grow without bound while a call is slow,
+// and a watchdog that notices a call that never returns, so the worker can be
+// abandoned and replaced. Neither knows what the commands mean.
+
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Commands for one worker. `droppable` marks the commands that are safe to
+/// lose, such as queries that are sent again on every poll: pushing one that
+/// is already waiting does nothing, and at `capacity` the oldest of them makes
+/// room. Other commands are never merged or refused, so only droppable ones
+/// are bounded.
+pub struct CommandQueue<T> {
+    state: Mutex<QueueState<T>>,
+    ready: Condvar,
+    capacity: usize,
+    droppable: fn(&T) -> bool,
+}
+
+struct QueueState<T> {
+    commands: VecDeque<T>,
+    closed: bool,
+}
+
+impl<T: PartialEq> CommandQueue<T> {
+    pub fn new(capacity: usize, droppable: fn(&T) -> bool) -> CommandQueue<T> {
+        CommandQueue {
+            state: Mutex::new(QueueState { commands: VecDeque::new(), closed: false }),
+            ready: Condvar::new(),
+            capacity,
+            droppable,
+        }
+    }
+
+    /// Queues `command`, returning whether it is now waiting. Only a closed
+    /// queue refuses a command that isn't droppable.
+    pub fn push(&self, command: T) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return false;
+        }
+        let droppable = (self.droppable)(&command);
+        if droppable && state.commands.contains(&command) {
+            return true;
+        }
+        if state.commands.len() >= self.capacity {
+            match state.commands.iter().position(self.droppable) {
+                Some(index) => {
+                    state.commands.remove(index);
+                }
+                None if droppable => return false,
+                None => {}
+            }
+        }
+        state.commands.push_back(command);
+        self.ready.notify_one();
+        true
+    }
+
+    /// Waits for the next command; `None` once the queue is closed.
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.closed {
+                return None;
+            }
+            if let Some(command) = state.commands.pop_front() {
+                return Some(command);
+            }
+            state = self.ready.wait(state).unwrap();
+        }
+    }
+
+    /// Drops the waiting commands and ends the worker's `pop` loop.
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.commands.clear();
+        self.ready.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().commands.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// When the worker's current call started, if it is in one.
+#[derive(Default)]
+pub struct Watchdog {
+    busy_since: Mutex<Option<Instant>>,
+}
+
+impl Watchdog {
+    /// Called by the worker before each call. Calling it again during a
+    /// command that makes several calls restarts the clock.
+    pub fn start(&self, now: Instant) {
+        *self.busy_since.lock().unwrap() = Some(now);
+    }
+
+    /// Called by the worker after each call.
+    pub fn finish(&self) {
+        *self.busy_since.lock().unwrap() = None;
+    }
+
+    /// Whether the current call has been running for longer than `timeout`.
+    pub fn stuck(&self, now: Instant, timeout: Duration) -> bool {
+        self.busy_since.lock().unwrap().is_some_and(|since| now.duration_since(since) > timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn queue(capacity: usize) -> CommandQueue<&'static str> {
+        CommandQueue::new(capacity, |command| command.starts_with("query"))
+    }
+
+    fn drain(queue: &CommandQueue<&'static str>) -> Vec<&'static str> {
+        let mut commands = Vec::new();
+        while !queue.is_empty() {
+            commands.extend(queue.pop());
+        }
+        commands
+    }
+
+    #[test]
+    fn repeated_queries_collapse() {
+        let queue = queue(8);
+        for command in ["query status", "query track", "query status", "seek", "query status", "seek"] {
+            assert!(queue.push(command));
+        }
+        // Each seek moves further, so both are kept
+        assert_eq!(drain(&queue), ["query status", "query track", "seek", "seek"]);
+        // Once taken, the same command queues again
+        queue.push("query status");
+        assert_eq!(drain(&queue), ["query status"]);
+    }
+
+    #[test]
+    fn full_queue_drops_the_oldest_query() {
+        let queue = queue(3);
+        assert!(queue.push("query status"));
+        assert!(queue.push("pause"));
+        assert!(queue.push("query track"));
+        assert!(queue.push("next"));
+        assert_eq!(queue.len(), 3);
+        assert!(queue.push("query art"));
+        assert_eq!(drain(&queue), ["pause", "next", "query art"]);
+
+        // Nothing left to drop: a new query is refused, a command is not
+        for command in ["play", "next", "prev"] {
+            assert!(queue.push(command));
+        }
+        assert!(!queue.push("query status"));
+        assert!(queue.push("stop"));
+        assert!(queue.push("stop"));
+        assert_eq!(drain(&queue), ["play", "next", "prev", "stop", "stop"]);
+    }
+
+    #[test]
+    fn close_ends_a_waiting_pop() {
+        let queue = Arc::new(queue(8));
+        let worker = {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                let mut seen = Vec::new();
+                while let Some(command) = queue.pop() {
+                    seen.push(command);
+                }
+                seen
+            })
+        };
+        queue.push("query status");
+        while !queue.is_empty() {
+            thread::yield_now();
+        }
+        queue.close();
+        assert_eq!(worker.join().unwrap(), ["query status"]);
+        assert!(queue.is_closed());
+        assert!(!queue.push("query status"));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn watchdog_flags_long_calls() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let timeout = Duration::from_secs(5);
+        let watchdog = Watchdog::default();
+
+        assert!(!watchdog.stuck(at(60_000), timeout));
+        watchdog.start(at(1_000));
+        assert!(!watchdog.stuck(at(6_000), timeout));
+        assert!(watchdog.stuck(at(6_001), timeout));
+        watchdog.finish();
+        assert!(!watchdog.stuck(at(60_000), timeout));
+
+        // A command of several calls restarts the clock with each one
+        watchdog.start(at(10_000));
+        watchdog.start(at(14_000));
+        assert!(!watchdog.stuck(at(18_000), timeout));
+        assert!(watchdog.stuck(at(19_001), timeout));
+        watchdog.finish();
+        assert!(!watchdog.stuck(at(60_000), timeout));
+    }
+}